@@ -0,0 +1,189 @@
+use super::problem::{AcmeResult, Problem};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use openssl::{
+    bn::BigNum,
+    ec::{EcGroup, EcKey},
+    ecdsa::EcdsaSig,
+    error::ErrorStack,
+    hash::{hash, MessageDigest},
+    nid::Nid,
+    pkey::{Id, PKey, Public},
+    rsa::Rsa,
+    sign::Verifier,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value;
+
+#[derive(Deserialize)]
+struct FlattenedJws {
+    protected: String,
+    payload: String,
+    signature: String,
+}
+
+#[derive(Deserialize)]
+pub struct ProtectedHeader {
+    pub alg: String,
+    pub nonce: Option<String>,
+    pub url: String,
+    pub jwk: Option<Value>,
+    pub kid: Option<String>,
+}
+
+// A request body in the flattened JSON serialization RFC 8555 section 6.2 requires
+pub struct Jws {
+    pub header: ProtectedHeader,
+    pub payload: Vec<u8>,
+    signing_input: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl Jws {
+    pub fn parse(body: &[u8]) -> AcmeResult<Self> {
+        let jws: FlattenedJws = serde_json::from_slice(body)
+            .map_err(|err| Problem::malformed(format!("Invalid JWS: {}", err)))?;
+        let header: ProtectedHeader = serde_json::from_slice(&decode(&jws.protected)?)
+            .map_err(|err| Problem::malformed(format!("Invalid JWS protected header: {}", err)))?;
+        if header.jwk.is_some() == header.kid.is_some() {
+            return Err(Problem::malformed(
+                "The JWS protected header needs exactly one of `jwk` and `kid`",
+            ));
+        }
+        Ok(Jws {
+            header,
+            payload: decode(&jws.payload)?,
+            signing_input: format!("{}.{}", jws.protected, jws.payload).into_bytes(),
+            signature: decode(&jws.signature)?,
+        })
+    }
+
+    pub fn verify(&self, key: &PKey<Public>) -> AcmeResult<()> {
+        let (digest, id, coordinate_size): (MessageDigest, Id, usize) =
+            match self.header.alg.as_str() {
+                "RS256" => (MessageDigest::sha256(), Id::RSA, 0),
+                "ES256" => (MessageDigest::sha256(), Id::EC, 32),
+                "ES384" => (MessageDigest::sha384(), Id::EC, 48),
+                alg => {
+                    return Err(Problem::new(
+                        "badSignatureAlgorithm",
+                        400,
+                        format!("{} is not supported, use RS256, ES256 or ES384", alg),
+                    ))
+                }
+            };
+        if key.id() != id {
+            return Err(Problem::malformed(format!(
+                "{} does not match the account key type",
+                self.header.alg
+            )));
+        }
+        let verified: bool = (|| -> Result<bool, ErrorStack> {
+            // JWS carries ECDSA signatures as fixed-size r || s, OpenSSL wants DER
+            let signature: Vec<u8> = if id == Id::EC {
+                if self.signature.len() != coordinate_size * 2 {
+                    return Ok(false);
+                }
+                let (r, s) = self.signature.split_at(coordinate_size);
+                EcdsaSig::from_private_components(BigNum::from_slice(r)?, BigNum::from_slice(s)?)?
+                    .to_der()?
+            } else {
+                self.signature.clone()
+            };
+            Verifier::new(digest, key)?.verify_oneshot(&signature, &self.signing_input)
+        })()
+        .map_err(Problem::server_internal)?;
+        if !verified {
+            return Err(Problem::malformed("JWS signature is invalid"));
+        }
+        Ok(())
+    }
+
+    // POST-as-GET requests carry an empty payload
+    pub fn is_post_as_get(&self) -> bool {
+        self.payload.is_empty()
+    }
+
+    pub fn payload_json<T: DeserializeOwned>(&self) -> AcmeResult<T> {
+        serde_json::from_slice(&self.payload)
+            .map_err(|err| Problem::malformed(format!("Invalid request payload: {}", err)))
+    }
+}
+
+pub fn jwk_public_key(jwk: &Value) -> AcmeResult<PKey<Public>> {
+    let field = |name: &str| -> AcmeResult<Vec<u8>> {
+        match jwk.get(name).and_then(Value::as_str) {
+            Some(value) => decode(value),
+            None => Err(Problem::malformed(format!("JWK is missing `{}`", name))),
+        }
+    };
+    let key: Result<PKey<Public>, ErrorStack> = match jwk.get("kty").and_then(Value::as_str) {
+        Some("RSA") => {
+            let (n, e): (Vec<u8>, Vec<u8>) = (field("n")?, field("e")?);
+            (|| {
+                let rsa: Rsa<Public> =
+                    Rsa::from_public_components(BigNum::from_slice(&n)?, BigNum::from_slice(&e)?)?;
+                PKey::from_rsa(rsa)
+            })()
+        }
+        Some("EC") => {
+            let curve: Nid = match jwk.get("crv").and_then(Value::as_str) {
+                Some("P-256") => Nid::X9_62_PRIME256V1,
+                Some("P-384") => Nid::SECP384R1,
+                _ => {
+                    return Err(Problem::malformed(
+                        "Only P-256 and P-384 EC keys are supported",
+                    ))
+                }
+            };
+            let (x, y): (Vec<u8>, Vec<u8>) = (field("x")?, field("y")?);
+            (|| {
+                let group: EcGroup = EcGroup::from_curve_name(curve)?;
+                let (x, y): (BigNum, BigNum) = (BigNum::from_slice(&x)?, BigNum::from_slice(&y)?);
+                let ec_key: EcKey<Public> =
+                    EcKey::from_public_key_affine_coordinates(&group, &x, &y)?;
+                PKey::from_ec_key(ec_key)
+            })()
+        }
+        _ => {
+            return Err(Problem::malformed(
+                "Only RSA and EC account keys are supported",
+            ))
+        }
+    };
+    key.map_err(|err: ErrorStack| Problem::malformed(format!("Invalid JWK: {}", err)))
+}
+
+// RFC 7638: SHA-256 over the required members in lexicographic order
+pub fn jwk_thumbprint(jwk: &Value) -> AcmeResult<String> {
+    let members: &[&str] = match jwk.get("kty").and_then(Value::as_str) {
+        Some("RSA") => &["e", "kty", "n"],
+        Some("EC") => &["crv", "kty", "x", "y"],
+        _ => {
+            return Err(Problem::malformed(
+                "Only RSA and EC account keys are supported",
+            ))
+        }
+    };
+    let mut canonical: Vec<String> = Vec::new();
+    for member in members {
+        let value: &str = jwk
+            .get(*member)
+            .and_then(Value::as_str)
+            .ok_or_else(|| Problem::malformed(format!("JWK is missing `{}`", member)))?;
+        canonical.push(format!("{}:{}", Value::from(*member), Value::from(value)));
+    }
+    let canonical: String = format!("{{{}}}", canonical.join(","));
+    let digest =
+        hash(MessageDigest::sha256(), canonical.as_bytes()).map_err(Problem::server_internal)?;
+    Ok(encode(&digest))
+}
+
+pub fn encode(data: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(data)
+}
+
+pub fn decode(data: &str) -> AcmeResult<Vec<u8>> {
+    URL_SAFE_NO_PAD
+        .decode(data.trim_end_matches('='))
+        .map_err(|err| Problem::malformed(format!("Invalid base64url: {}", err)))
+}
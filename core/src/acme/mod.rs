@@ -0,0 +1,3 @@
+pub mod jws;
+pub mod problem;
+pub mod server;
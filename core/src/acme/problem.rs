@@ -0,0 +1,64 @@
+use crate::http::HttpResponse;
+use core::fmt;
+use serde_json::json;
+use std::error::Error;
+
+// An RFC 7807 problem document with one of the RFC 8555 error types
+#[derive(Debug)]
+pub struct Problem {
+    pub kind: &'static str,
+    pub status: u16,
+    pub detail: String,
+}
+
+pub type AcmeResult<T> = Result<T, Problem>;
+
+impl Problem {
+    pub fn new(kind: &'static str, status: u16, detail: impl Into<String>) -> Self {
+        Problem {
+            kind,
+            status,
+            detail: detail.into(),
+        }
+    }
+
+    pub fn malformed(detail: impl Into<String>) -> Self {
+        Problem::new("malformed", 400, detail)
+    }
+
+    pub fn unauthorized(detail: impl Into<String>) -> Self {
+        Problem::new("unauthorized", 403, detail)
+    }
+
+    pub fn not_found() -> Self {
+        Problem::new("malformed", 404, "No such resource")
+    }
+
+    pub fn server_internal(err: impl fmt::Display) -> Self {
+        Problem::new("serverInternal", 500, err.to_string())
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "type": format!("urn:ietf:params:acme:error:{}", self.kind),
+            "detail": self.detail,
+            "status": self.status,
+        })
+    }
+
+    pub fn response(&self) -> HttpResponse {
+        HttpResponse::new(
+            self.status,
+            "application/problem+json",
+            self.to_json().to_string().into(),
+        )
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.kind, self.detail)
+    }
+}
+
+impl Error for Problem {}
@@ -0,0 +1,996 @@
+use super::{
+    jws::{decode, encode, jwk_public_key, jwk_thumbprint, Jws},
+    problem::{AcmeResult, Problem},
+};
+use crate::{
+    ca_store::issuing_chain,
+    commands::utils::{sign_request, RevocationUrls},
+    http::{self, HttpRequest, HttpResponse},
+    ledger::{record_issued, Ledger, LedgerLock},
+    utils::{format_iso8601, unix_now},
+    x509::{ca_req::CAReq, crl::RevocationReason, describe::serial_hex},
+};
+use openssl::{
+    hash::{hash, MessageDigest},
+    pkey::{PKey, Private, Public},
+    rand::rand_bytes,
+    x509::{X509Req, X509},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+
+const ORDER_LIFETIME_SECS: i64 = 7 * 86400;
+// Clients fetch a fresh nonce after a badNonce error, so forgetting them all is safe
+const MAX_NONCES: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Pending,
+    Ready,
+    Valid,
+    Invalid,
+    Deactivated,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Identifier {
+    #[serde(rename = "type")]
+    kind: String,
+    value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChallengeType {
+    Http01,
+    Dns01,
+}
+
+impl ChallengeType {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Http01 => "http-01",
+            Self::Dns01 => "dns-01",
+        }
+    }
+}
+
+struct Account {
+    key: PKey<Public>,
+    thumbprint: String,
+    contact: Vec<String>,
+    status: Status,
+    orders: Vec<String>,
+}
+
+struct Order {
+    account: String,
+    status: Status,
+    expires: i64,
+    identifiers: Vec<Identifier>,
+    authorizations: Vec<String>,
+    certificate: Option<String>,
+}
+
+struct Authorization {
+    account: String,
+    identifier: Identifier,
+    wildcard: bool,
+    status: Status,
+    expires: i64,
+    challenges: Vec<Challenge>,
+}
+
+struct Challenge {
+    kind: ChallengeType,
+    token: String,
+    status: Status,
+    validated: Option<i64>,
+    error: Option<Problem>,
+}
+
+struct IssuedCertificate {
+    account: String,
+    serial: String,
+    chain: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NewAccountPayload {
+    #[serde(default)]
+    contact: Vec<String>,
+    #[serde(default)]
+    only_return_existing: bool,
+}
+
+#[derive(Deserialize)]
+struct AccountPayload {
+    contact: Option<Vec<String>>,
+    status: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NewOrderPayload {
+    identifiers: Vec<Identifier>,
+    not_before: Option<String>,
+    not_after: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FinalizePayload {
+    csr: String,
+}
+
+#[derive(Deserialize)]
+struct AuthorizationPayload {
+    status: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RevokeCertPayload {
+    certificate: String,
+    #[serde(default)]
+    reason: u8,
+}
+
+// The format lego's `httpreq` DNS provider posts, which Traefik uses as well
+#[derive(Deserialize)]
+struct TxtRecordPayload {
+    fqdn: String,
+    value: String,
+}
+
+// RFC 8555 state for one run of `vanish acme serve`, kept in memory only
+pub struct AcmeServer {
+    base_url: String,
+    ca_cert: X509,
+    ca_key: PKey<Private>,
+    revocation_urls: RevocationUrls,
+    http_01_port: u16,
+    dns_01: bool,
+    nonces: HashSet<String>,
+    accounts: HashMap<String, Account>,
+    orders: HashMap<String, Order>,
+    authorizations: HashMap<String, Authorization>,
+    certificates: HashMap<String, IssuedCertificate>,
+    txt_records: HashMap<String, Vec<String>>,
+}
+
+impl AcmeServer {
+    pub fn new(
+        base_url: String,
+        ca_cert: X509,
+        ca_key: PKey<Private>,
+        revocation_urls: RevocationUrls,
+        http_01_port: u16,
+        dns_01: bool,
+    ) -> Self {
+        AcmeServer {
+            base_url,
+            ca_cert,
+            ca_key,
+            revocation_urls,
+            http_01_port,
+            dns_01,
+            nonces: HashSet::new(),
+            accounts: HashMap::new(),
+            orders: HashMap::new(),
+            authorizations: HashMap::new(),
+            certificates: HashMap::new(),
+            txt_records: HashMap::new(),
+        }
+    }
+
+    pub fn handle(&mut self, request: &HttpRequest) -> HttpResponse {
+        let response: HttpResponse = match self.route(request) {
+            Ok(response) => response,
+            Err(problem) => {
+                println!(
+                    "   - {} {} ❌ {}: {}",
+                    request.method, request.path, problem.kind, problem.detail
+                );
+                problem.response()
+            }
+        };
+        // Every response carries a fresh nonce, as RFC 8555 section 6.5 asks
+        let response: HttpResponse = match self.new_nonce() {
+            Ok(nonce) => response.with_header("Replay-Nonce", &nonce),
+            Err(_) => response,
+        };
+        response.with_header(
+            "Link",
+            &format!("<{}>;rel=\"index\"", self.url("/directory")),
+        )
+    }
+
+    fn route(&mut self, request: &HttpRequest) -> AcmeResult<HttpResponse> {
+        let path: &str = request.path.split('?').next().unwrap_or_default();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["directory"]) => Ok(self.directory()),
+            ("HEAD", ["acme", "new-nonce"]) => {
+                Ok(HttpResponse::empty(200).with_header("Cache-Control", "no-store"))
+            }
+            ("GET", ["acme", "new-nonce"]) => {
+                Ok(HttpResponse::empty(204).with_header("Cache-Control", "no-store"))
+            }
+            ("POST", ["dns", action @ ("present" | "cleanup")]) if self.dns_01 => {
+                self.txt_record(request, *action == "present")
+            }
+            ("POST", ["acme", resource @ ..]) => self.post(request, path, resource),
+            ("GET" | "HEAD", ["acme", ..]) => Err(Problem::new(
+                "malformed",
+                405,
+                "ACME resources are fetched with POST-as-GET",
+            )),
+            _ => Err(Problem::not_found()),
+        }
+    }
+
+    fn post(
+        &mut self,
+        request: &HttpRequest,
+        path: &str,
+        resource: &[&str],
+    ) -> AcmeResult<HttpResponse> {
+        if request.header("content-type") != Some("application/jose+json") {
+            return Err(Problem::new(
+                "malformed",
+                415,
+                "Expected Content-Type: application/jose+json",
+            ));
+        }
+        let jws: Jws = Jws::parse(&request.body)?;
+        let fresh_nonce: bool = jws
+            .header
+            .nonce
+            .as_ref()
+            .is_some_and(|nonce: &String| self.nonces.remove(nonce));
+        if !fresh_nonce {
+            return Err(Problem::new(
+                "badNonce",
+                400,
+                "The nonce is unknown or was already used",
+            ));
+        }
+        if jws.header.url != self.url(path) {
+            return Err(Problem::unauthorized(format!(
+                "The JWS was signed for {}, not {}",
+                jws.header.url,
+                self.url(path)
+            )));
+        }
+
+        match resource {
+            ["new-account"] => self.new_account(&jws),
+            ["revoke-cert"] => self.revoke_certificate(&jws),
+            _ => {
+                let account: String = self.authenticate(&jws)?;
+                match resource {
+                    ["account", id] if *id == account => self.account(&jws, &account),
+                    ["account", id, "orders"] if *id == account => {
+                        Ok(self.account_orders(&account))
+                    }
+                    ["account", ..] => Err(Problem::unauthorized(
+                        "Accounts can only be managed with their own key",
+                    )),
+                    ["new-order"] => self.new_order(&jws, &account),
+                    ["order", id] => self.order(&account, id),
+                    ["order", id, "finalize"] => self.finalize(&jws, &account, id),
+                    ["authz", id] => self.authorization(&jws, &account, id),
+                    ["chall", id, kind] => self.challenge(&jws, &account, id, kind),
+                    ["cert", id] => self.certificate(&account, id),
+                    _ => Err(Problem::not_found()),
+                }
+            }
+        }
+    }
+
+    fn directory(&self) -> HttpResponse {
+        json_response(
+            200,
+            json!({
+                "newNonce": self.url("/acme/new-nonce"),
+                "newAccount": self.url("/acme/new-account"),
+                "newOrder": self.url("/acme/new-order"),
+                "revokeCert": self.url("/acme/revoke-cert"),
+                "meta": { "externalAccountRequired": false },
+            }),
+        )
+    }
+
+    fn authenticate(&self, jws: &Jws) -> AcmeResult<String> {
+        let Some(kid) = &jws.header.kid else {
+            return Err(Problem::malformed(
+                "Only newAccount and revokeCert requests are signed with `jwk`",
+            ));
+        };
+        let account: Option<(&str, &Account)> = kid
+            .strip_prefix(&self.url("/acme/account/"))
+            .and_then(|id: &str| self.accounts.get(id).map(|account| (id, account)));
+        let Some((id, account)) = account else {
+            return Err(Problem::new(
+                "accountDoesNotExist",
+                400,
+                "No such account, note that accounts are forgotten when the server stops",
+            ));
+        };
+        jws.verify(&account.key)?;
+        if account.status != Status::Valid {
+            return Err(Problem::unauthorized("The account is deactivated"));
+        }
+        Ok(id.to_string())
+    }
+
+    fn new_account(&mut self, jws: &Jws) -> AcmeResult<HttpResponse> {
+        let Some(jwk) = &jws.header.jwk else {
+            return Err(Problem::malformed(
+                "newAccount requests are signed with `jwk`",
+            ));
+        };
+        let key: PKey<Public> = jwk_public_key(jwk)?;
+        jws.verify(&key)?;
+        let payload: NewAccountPayload = jws.payload_json()?;
+        let thumbprint: String = jwk_thumbprint(jwk)?;
+
+        let existing: Option<String> = self
+            .accounts
+            .iter()
+            .find(|(_, account)| account.thumbprint == thumbprint)
+            .map(|(id, _)| id.clone());
+        if let Some(id) = existing {
+            return Ok(self.account_response(&id, 200));
+        }
+        if payload.only_return_existing {
+            return Err(Problem::new(
+                "accountDoesNotExist",
+                400,
+                "No account exists for this key",
+            ));
+        }
+        let id: String = random_id()?;
+        self.accounts.insert(
+            id.clone(),
+            Account {
+                key,
+                thumbprint,
+                contact: payload.contact,
+                status: Status::Valid,
+                orders: Vec::new(),
+            },
+        );
+        println!("   - Registered account {} ✅", id);
+        Ok(self.account_response(&id, 201))
+    }
+
+    fn account(&mut self, jws: &Jws, id: &str) -> AcmeResult<HttpResponse> {
+        if !jws.is_post_as_get() {
+            let payload: AccountPayload = jws.payload_json()?;
+            if let Some(account) = self.accounts.get_mut(id) {
+                if let Some(contact) = payload.contact {
+                    account.contact = contact;
+                }
+                if payload.status.as_deref() == Some("deactivated") {
+                    account.status = Status::Deactivated;
+                    println!("   - Deactivated account {}", id);
+                }
+            }
+        }
+        Ok(self.account_response(id, 200))
+    }
+
+    fn account_response(&self, id: &str, status: u16) -> HttpResponse {
+        let account: &Account = &self.accounts[id];
+        json_response(
+            status,
+            json!({
+                "status": account.status,
+                "contact": account.contact,
+                "orders": self.url(&format!("/acme/account/{}/orders", id)),
+            }),
+        )
+        .with_header("Location", &self.url(&format!("/acme/account/{}", id)))
+    }
+
+    fn account_orders(&self, id: &str) -> HttpResponse {
+        let orders: Vec<String> = self.accounts[id]
+            .orders
+            .iter()
+            .map(|order: &String| self.url(&format!("/acme/order/{}", order)))
+            .collect();
+        json_response(200, json!({ "orders": orders }))
+    }
+
+    fn new_order(&mut self, jws: &Jws, account: &str) -> AcmeResult<HttpResponse> {
+        let payload: NewOrderPayload = jws.payload_json()?;
+        if payload.not_before.is_some() || payload.not_after.is_some() {
+            return Err(Problem::malformed(
+                "notBefore and notAfter are not supported, certificates are valid for two years",
+            ));
+        }
+        let mut identifiers: Vec<Identifier> = Vec::new();
+        for identifier in payload.identifiers {
+            if identifier.kind != "dns" {
+                return Err(Problem::new(
+                    "unsupportedIdentifier",
+                    400,
+                    format!(
+                        "Only dns identifiers are supported, not {}",
+                        identifier.kind
+                    ),
+                ));
+            }
+            let value: String = identifier.value.trim_end_matches('.').to_ascii_lowercase();
+            if value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '/') {
+                return Err(Problem::new(
+                    "rejectedIdentifier",
+                    400,
+                    format!("{:?} is not a domain name", identifier.value),
+                ));
+            }
+            if value.starts_with("*.") && !self.dns_01 {
+                return Err(Problem::new(
+                    "rejectedIdentifier",
+                    400,
+                    format!(
+                        "{} needs a dns-01 challenge, start the server with `--dns-01`",
+                        value
+                    ),
+                ));
+            }
+            let identifier: Identifier = Identifier {
+                kind: identifier.kind,
+                value,
+            };
+            if !identifiers.contains(&identifier) {
+                identifiers.push(identifier);
+            }
+        }
+        if identifiers.is_empty() {
+            return Err(Problem::malformed("The order has no identifiers"));
+        }
+
+        let expires: i64 = unix_now() + ORDER_LIFETIME_SECS;
+        let mut authorizations: Vec<String> = Vec::new();
+        for identifier in &identifiers {
+            let (wildcard, domain): (bool, &str) = match identifier.value.strip_prefix("*.") {
+                Some(domain) => (true, domain),
+                None => (false, &identifier.value),
+            };
+            let mut challenges: Vec<Challenge> = Vec::new();
+            // A wildcard can only be proven over DNS
+            if !wildcard {
+                challenges.push(Challenge::new(ChallengeType::Http01)?);
+            }
+            if self.dns_01 {
+                challenges.push(Challenge::new(ChallengeType::Dns01)?);
+            }
+            let id: String = random_id()?;
+            self.authorizations.insert(
+                id.clone(),
+                Authorization {
+                    account: account.to_string(),
+                    identifier: Identifier {
+                        kind: identifier.kind.clone(),
+                        value: domain.to_string(),
+                    },
+                    wildcard,
+                    status: Status::Pending,
+                    expires,
+                    challenges,
+                },
+            );
+            authorizations.push(id);
+        }
+
+        let id: String = random_id()?;
+        println!("   - New order for \"{}\"", names(&identifiers));
+        self.orders.insert(
+            id.clone(),
+            Order {
+                account: account.to_string(),
+                status: Status::Pending,
+                expires,
+                identifiers,
+                authorizations,
+                certificate: None,
+            },
+        );
+        if let Some(account) = self.accounts.get_mut(account) {
+            account.orders.push(id.clone());
+        }
+        Ok(self.order_response(&id, 201))
+    }
+
+    fn order(&mut self, account: &str, id: &str) -> AcmeResult<HttpResponse> {
+        self.owned_order(account, id)?;
+        self.refresh_order(id);
+        Ok(self.order_response(id, 200))
+    }
+
+    fn finalize(&mut self, jws: &Jws, account: &str, id: &str) -> AcmeResult<HttpResponse> {
+        self.owned_order(account, id)?;
+        self.refresh_order(id);
+        let order: &Order = &self.orders[id];
+        if order.status != Status::Ready {
+            return Err(Problem::new(
+                "orderNotReady",
+                403,
+                format!(
+                    "The order is {}, only ready orders can be finalized",
+                    json!(order.status)
+                ),
+            ));
+        }
+        let payload: FinalizePayload = jws.payload_json()?;
+        let csr: X509Req = X509Req::from_der(&decode(&payload.csr)?)
+            .map_err(|err| Problem::new("badCSR", 400, format!("Invalid CSR: {}", err)))?;
+        CAReq::check_signature(&csr).map_err(|err| Problem::new("badCSR", 400, err.to_string()))?;
+        // Every requested name must be one of the order's validated DNS names.
+        // Signing itself refuses names that are not DNS names or IP addresses.
+        let mut requested: Vec<String> = CAReq::requested_identifiers(&csr)
+            .map_err(|err| Problem::new("badCSR", 400, err.to_string()))?;
+        requested.sort();
+        let mut ordered: Vec<String> = order
+            .identifiers
+            .iter()
+            .map(|identifier: &Identifier| identifier.value.clone())
+            .collect();
+        ordered.sort();
+        if requested != ordered {
+            return Err(Problem::new(
+                "badCSR",
+                400,
+                format!(
+                    "The CSR asks for \"{}\" but the order is for \"{}\"",
+                    requested.join(", "),
+                    ordered.join(", ")
+                ),
+            ));
+        }
+
+        let certificate: X509 =
+            sign_request(&csr, &self.ca_cert, &self.ca_key, &self.revocation_urls)
+                .map_err(Problem::server_internal)?;
+        record_issued(&certificate, &self.ca_cert, None, None);
+        let serial: String = serial_hex(&certificate).map_err(Problem::server_internal)?;
+        let chain: Vec<u8> = std::iter::once(&certificate)
+            .chain(issuing_chain(&self.ca_cert).iter())
+            .map(|cert: &X509| cert.to_pem())
+            .collect::<Result<Vec<Vec<u8>>, _>>()
+            .map_err(Problem::server_internal)?
+            .concat();
+        let certificate_id: String = random_id()?;
+        self.certificates.insert(
+            certificate_id.clone(),
+            IssuedCertificate {
+                account: account.to_string(),
+                serial: serial.clone(),
+                chain,
+            },
+        );
+        if let Some(order) = self.orders.get_mut(id) {
+            order.status = Status::Valid;
+            order.certificate = Some(certificate_id);
+        }
+        println!(
+            "   - Issued certificate for \"{}\" ({}) ✅",
+            ordered.join(", "),
+            serial
+        );
+        Ok(self.order_response(id, 200))
+    }
+
+    fn owned_order(&self, account: &str, id: &str) -> AcmeResult<()> {
+        match self.orders.get(id) {
+            Some(order) if order.account == account => Ok(()),
+            Some(_) => Err(Problem::unauthorized(
+                "The order belongs to another account",
+            )),
+            None => Err(Problem::not_found()),
+        }
+    }
+
+    // Orders move on once all their authorizations are settled
+    fn refresh_order(&mut self, id: &str) {
+        let now: i64 = unix_now();
+        let Some(order) = self.orders.get(id) else {
+            return;
+        };
+        if order.status != Status::Pending {
+            return;
+        }
+        let statuses: Vec<Status> = order
+            .authorizations
+            .iter()
+            .filter_map(|authorization: &String| self.authorizations.get(authorization))
+            .map(|authorization: &Authorization| authorization.status)
+            .collect();
+        let status: Status = if now > order.expires
+            || statuses
+                .iter()
+                .any(|status: &Status| matches!(status, Status::Invalid | Status::Deactivated))
+        {
+            Status::Invalid
+        } else if statuses
+            .iter()
+            .all(|status: &Status| *status == Status::Valid)
+        {
+            Status::Ready
+        } else {
+            Status::Pending
+        };
+        if let Some(order) = self.orders.get_mut(id) {
+            order.status = status;
+        }
+    }
+
+    fn order_response(&self, id: &str, status: u16) -> HttpResponse {
+        let order: &Order = &self.orders[id];
+        let authorizations: Vec<String> = order
+            .authorizations
+            .iter()
+            .map(|authorization: &String| self.url(&format!("/acme/authz/{}", authorization)))
+            .collect();
+        let mut body: Value = json!({
+            "status": order.status,
+            "expires": format_iso8601(order.expires),
+            "identifiers": order.identifiers,
+            "authorizations": authorizations,
+            "finalize": self.url(&format!("/acme/order/{}/finalize", id)),
+        });
+        if let Some(certificate) = &order.certificate {
+            body["certificate"] = json!(self.url(&format!("/acme/cert/{}", certificate)));
+        }
+        json_response(status, body)
+            .with_header("Location", &self.url(&format!("/acme/order/{}", id)))
+    }
+
+    fn owned_authorization(&mut self, account: &str, id: &str) -> AcmeResult<&mut Authorization> {
+        match self.authorizations.get_mut(id) {
+            Some(authorization) if authorization.account == account => {
+                if authorization.status == Status::Pending && unix_now() > authorization.expires {
+                    authorization.status = Status::Invalid;
+                }
+                Ok(authorization)
+            }
+            Some(_) => Err(Problem::unauthorized(
+                "The authorization belongs to another account",
+            )),
+            None => Err(Problem::not_found()),
+        }
+    }
+
+    fn authorization(&mut self, jws: &Jws, account: &str, id: &str) -> AcmeResult<HttpResponse> {
+        let authorization: &mut Authorization = self.owned_authorization(account, id)?;
+        if !jws.is_post_as_get() {
+            let payload: AuthorizationPayload = jws.payload_json()?;
+            if payload.status.as_deref() == Some("deactivated") {
+                authorization.status = Status::Deactivated;
+            }
+        }
+        let body: Value = self.authorization_json(id);
+        Ok(json_response(200, body))
+    }
+
+    fn authorization_json(&self, id: &str) -> Value {
+        let authorization: &Authorization = &self.authorizations[id];
+        let challenges: Vec<Value> = authorization
+            .challenges
+            .iter()
+            .map(|challenge: &Challenge| self.challenge_json(id, challenge))
+            .collect();
+        let mut body: Value = json!({
+            "identifier": authorization.identifier,
+            "status": authorization.status,
+            "expires": format_iso8601(authorization.expires),
+            "challenges": challenges,
+        });
+        if authorization.wildcard {
+            body["wildcard"] = json!(true);
+        }
+        body
+    }
+
+    fn challenge_json(&self, authorization: &str, challenge: &Challenge) -> Value {
+        let mut body: Value = json!({
+            "type": challenge.kind.name(),
+            "url": self.url(&format!("/acme/chall/{}/{}", authorization, challenge.kind.name())),
+            "token": challenge.token,
+            "status": challenge.status,
+        });
+        if let Some(validated) = challenge.validated {
+            body["validated"] = json!(format_iso8601(validated));
+        }
+        if let Some(error) = &challenge.error {
+            body["error"] = error.to_json();
+        }
+        body
+    }
+
+    // Validation happens while the client waits, so the challenge comes back
+    // already valid or invalid instead of processing
+    fn challenge(
+        &mut self,
+        jws: &Jws,
+        account: &str,
+        id: &str,
+        kind: &str,
+    ) -> AcmeResult<HttpResponse> {
+        let thumbprint: String = self.accounts[account].thumbprint.clone();
+        let authorization: &mut Authorization = self.owned_authorization(account, id)?;
+        let Some(index) = authorization
+            .challenges
+            .iter()
+            .position(|challenge: &Challenge| challenge.kind.name() == kind)
+        else {
+            return Err(Problem::not_found());
+        };
+        let challenge: &Challenge = &authorization.challenges[index];
+        if !jws.is_post_as_get()
+            && authorization.status == Status::Pending
+            && challenge.status == Status::Pending
+        {
+            let challenge_type: ChallengeType = challenge.kind;
+            let domain: String = authorization.identifier.value.clone();
+            let key_authorization: String = format!("{}.{}", challenge.token, thumbprint);
+            let result: AcmeResult<()> = self.validate(
+                challenge_type,
+                &domain,
+                &challenge_token(&key_authorization),
+                &key_authorization,
+            );
+            let authorization: &mut Authorization = self.owned_authorization(account, id)?;
+            let challenge: &mut Challenge = &mut authorization.challenges[index];
+            match result {
+                Ok(()) => {
+                    println!(
+                        "   - {} challenge for \"{}\" passed ✅",
+                        challenge_type.name(),
+                        domain
+                    );
+                    challenge.status = Status::Valid;
+                    challenge.validated = Some(unix_now());
+                    authorization.status = Status::Valid;
+                }
+                Err(problem) => {
+                    println!(
+                        "   - {} challenge for \"{}\" failed ❌ {}",
+                        challenge_type.name(),
+                        domain,
+                        problem.detail
+                    );
+                    challenge.status = Status::Invalid;
+                    challenge.error = Some(problem);
+                    authorization.status = Status::Invalid;
+                }
+            }
+        }
+        let body: Value = self.challenge_json(id, &self.authorizations[id].challenges[index]);
+        Ok(json_response(200, body).with_header(
+            "Link",
+            &format!("<{}>;rel=\"up\"", self.url(&format!("/acme/authz/{}", id))),
+        ))
+    }
+
+    fn validate(
+        &self,
+        challenge_type: ChallengeType,
+        domain: &str,
+        token: &str,
+        key_authorization: &str,
+    ) -> AcmeResult<()> {
+        match challenge_type {
+            ChallengeType::Http01 => {
+                let path: String = format!("/.well-known/acme-challenge/{}", token);
+                let location: String = format!("http://{}:{}{}", domain, self.http_01_port, path);
+                let (status, body) =
+                    http::get(domain, self.http_01_port, &path).map_err(|err| {
+                        Problem::new("connection", 400, format!("Fetching {}: {}", location, err))
+                    })?;
+                if status != 200 {
+                    return Err(Problem::new(
+                        "unauthorized",
+                        403,
+                        format!("{} answered with HTTP {}", location, status),
+                    ));
+                }
+                if String::from_utf8_lossy(&body).trim() != key_authorization {
+                    return Err(Problem::new(
+                        "incorrectResponse",
+                        403,
+                        format!("{} does not hold the key authorization", location),
+                    ));
+                }
+                Ok(())
+            }
+            ChallengeType::Dns01 => {
+                let name: String = format!("_acme-challenge.{}", domain);
+                let expected: String = encode(
+                    &hash(MessageDigest::sha256(), key_authorization.as_bytes())
+                        .map_err(Problem::server_internal)?,
+                );
+                let published: bool = self
+                    .txt_records
+                    .get(&name)
+                    .is_some_and(|values: &Vec<String>| values.contains(&expected));
+                if !published {
+                    return Err(Problem::new(
+                        "incorrectResponse",
+                        403,
+                        format!(
+                            "No TXT record for {} holds the expected value, publish it at {}",
+                            name,
+                            self.url("/dns/present")
+                        ),
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn certificate(&self, account: &str, id: &str) -> AcmeResult<HttpResponse> {
+        match self.certificates.get(id) {
+            Some(issued) if issued.account == account => Ok(HttpResponse::new(
+                200,
+                "application/pem-certificate-chain",
+                issued.chain.clone(),
+            )),
+            Some(_) => Err(Problem::unauthorized(
+                "The certificate was ordered by another account",
+            )),
+            None => Err(Problem::not_found()),
+        }
+    }
+
+    // Allowed for the account that ordered the certificate, or with its own key
+    fn revoke_certificate(&mut self, jws: &Jws) -> AcmeResult<HttpResponse> {
+        let payload: RevokeCertPayload = jws.payload_json()?;
+        let certificate: X509 = X509::from_der(&decode(&payload.certificate)?)
+            .map_err(|err| Problem::malformed(format!("Invalid certificate: {}", err)))?;
+        let serial: String = serial_hex(&certificate).map_err(Problem::server_internal)?;
+        match &jws.header.jwk {
+            Some(jwk) => {
+                let key: PKey<Public> = jwk_public_key(jwk)?;
+                jws.verify(&key)?;
+                let matches: bool = certificate
+                    .public_key()
+                    .map(|public_key| public_key.public_eq(&key))
+                    .unwrap_or(false);
+                if !matches {
+                    return Err(Problem::unauthorized(
+                        "The request is not signed with the certificate key",
+                    ));
+                }
+            }
+            None => {
+                let account: String = self.authenticate(jws)?;
+                let ordered: bool = self.certificates.values().any(|issued| {
+                    issued.account == account && issued.serial.eq_ignore_ascii_case(&serial)
+                });
+                if !ordered {
+                    return Err(Problem::unauthorized(
+                        "The certificate was not ordered by this account",
+                    ));
+                }
+            }
+        }
+        let reason: RevocationReason =
+            RevocationReason::from_code(payload.reason).ok_or_else(|| {
+                Problem::new(
+                    "badRevocationReason",
+                    400,
+                    format!("{} is not a CRL reason code", payload.reason),
+                )
+            })?;
+        let issued_here: bool = self
+            .ca_cert
+            .public_key()
+            .and_then(|ca_key| certificate.verify(&ca_key))
+            .unwrap_or(false);
+        if !issued_here {
+            return Err(Problem::unauthorized(
+                "The certificate was not issued by this CA",
+            ));
+        }
+
+        let _lock: LedgerLock = Ledger::lock().map_err(Problem::server_internal)?;
+        let mut ledger: Ledger = Ledger::load().map_err(Problem::server_internal)?;
+        let Some(entry) = ledger.find_serial_mut(&serial) else {
+            return Err(Problem::malformed(
+                "The certificate is not in the Vanish ledger",
+            ));
+        };
+        if entry.revocation.is_some() {
+            return Err(Problem::new(
+                "alreadyRevoked",
+                400,
+                "The certificate is already revoked",
+            ));
+        }
+        entry.revoke(reason);
+        let names: String = entry.names();
+        ledger.save().map_err(Problem::server_internal)?;
+        println!("   - Revoked \"{}\" ({}, {}) ✅", names, serial, reason);
+        Ok(HttpResponse::empty(200))
+    }
+
+    // The stand-in for DNS that dns-01 challenges are checked against
+    fn txt_record(&mut self, request: &HttpRequest, present: bool) -> AcmeResult<HttpResponse> {
+        let record: TxtRecordPayload = serde_json::from_slice(&request.body)
+            .map_err(|err| Problem::malformed(format!("Invalid TXT record: {}", err)))?;
+        let name: String = record.fqdn.trim_end_matches('.').to_ascii_lowercase();
+        let values: &mut Vec<String> = self.txt_records.entry(name.clone()).or_default();
+        if present {
+            if !values.contains(&record.value) {
+                values.push(record.value);
+            }
+            println!("   - Published TXT record for {}", name);
+        } else {
+            values.retain(|value: &String| *value != record.value);
+            println!("   - Removed TXT record for {}", name);
+        }
+        Ok(HttpResponse::empty(200))
+    }
+
+    fn new_nonce(&mut self) -> AcmeResult<String> {
+        if self.nonces.len() >= MAX_NONCES {
+            self.nonces.clear();
+        }
+        let nonce: String = random_token(16)?;
+        self.nonces.insert(nonce.clone());
+        Ok(nonce)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+}
+
+impl Challenge {
+    fn new(kind: ChallengeType) -> AcmeResult<Self> {
+        Ok(Challenge {
+            kind,
+            token: random_token(32)?,
+            status: Status::Pending,
+            validated: None,
+            error: None,
+        })
+    }
+}
+
+// The token is the part of the key authorization before the thumbprint
+fn challenge_token(key_authorization: &str) -> String {
+    key_authorization
+        .split_once('.')
+        .map_or(key_authorization, |(token, _)| token)
+        .to_string()
+}
+
+fn names(identifiers: &[Identifier]) -> String {
+    identifiers
+        .iter()
+        .map(|identifier: &Identifier| identifier.value.as_str())
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+fn json_response(status: u16, body: Value) -> HttpResponse {
+    HttpResponse::new(status, "application/json", body.to_string().into())
+}
+
+fn random_token(bytes: usize) -> AcmeResult<String> {
+    let mut token: Vec<u8> = vec![0; bytes];
+    rand_bytes(&mut token).map_err(Problem::server_internal)?;
+    Ok(encode(&token))
+}
+
+fn random_id() -> AcmeResult<String> {
+    let mut id: [u8; 8] = [0; 8];
+    rand_bytes(&mut id).map_err(Problem::server_internal)?;
+    Ok(id.iter().map(|byte: &u8| format!("{:02x}", byte)).collect())
+}
@@ -0,0 +1,270 @@
+use crate::{
+    utils::{certificate_fingerprint, prompt_passphrase},
+    x509::{self, encoding::decrypt_private_key},
+};
+use colored::*;
+use openssl::{
+    pkey::{PKey, Private},
+    x509::X509,
+};
+use std::{
+    env,
+    error::Error,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+pub const CA_PASSPHRASE_ENV: &str = "VANISH_CA_PASSPHRASE";
+// Neither side waits longer on an idle peer, so one stuck client can't block the agent
+#[cfg(unix)]
+const AGENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn socket_path() -> Option<PathBuf> {
+    x509::DATA_DIR
+        .as_ref()
+        .map(|data_dir: &PathBuf| data_dir.join("agent.sock"))
+}
+
+pub fn unlock_ca_key(
+    cert: &X509,
+    key_data: &[u8],
+    key_path: &str,
+) -> Result<PKey<Private>, Box<dyn Error>> {
+    let fingerprint: String = certificate_fingerprint(cert)?;
+    let socket: Option<PathBuf> = socket_path();
+    if let Some(key) = socket
+        .as_deref()
+        .and_then(|socket: &Path| agent_key(socket, cert, &fingerprint))
+    {
+        println!("Unlocking Key at {} with vanish agent ✅", key_path);
+        return Ok(key);
+    }
+
+    let passphrase: String = match env::var(CA_PASSPHRASE_ENV) {
+        Ok(passphrase) => passphrase,
+        Err(_) => prompt_passphrase(&format!("Enter passphrase for CA key {}: ", key_path))?,
+    };
+    match decrypt_private_key(key_data, key_path, passphrase.as_bytes()) {
+        Ok(key) => {
+            println!("Unlocking Key at {} ✅", key_path);
+            let cached: bool = socket
+                .as_deref()
+                .is_some_and(|socket: &Path| client::cache_key(socket, &fingerprint, &key).is_ok());
+            if cached {
+                println!("{}: Unlocked CA key cached in vanish agent", "Info".blue());
+            }
+            Ok(key)
+        }
+        Err(err) => {
+            println!("Unlocking Key at {} ❌", key_path);
+            Err(Box::new(err))
+        }
+    }
+}
+
+// The key cached for `cert`, if the agent has one that belongs to it
+pub(crate) fn agent_key(socket: &Path, cert: &X509, fingerprint: &str) -> Option<PKey<Private>> {
+    let key: PKey<Private> = client::request_key(socket, fingerprint)?;
+    // A stale or foreign agent must not make the CA sign with another key
+    if cert.public_key().ok()?.public_eq(&key) {
+        return Some(key);
+    }
+    eprintln!(
+        "{}: vanish agent returned a key that does not match the CA certificate, ignoring it",
+        "Warning".yellow()
+    );
+    None
+}
+
+pub fn lock_agent() -> Result<(), io::Error> {
+    let socket: PathBuf = socket_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No data directory"))?;
+    client::lock(&socket)
+}
+
+#[cfg(unix)]
+pub fn run_agent(ttl: u64) -> Result<(), Box<dyn Error>> {
+    server::run(ttl)
+}
+
+#[cfg(not(unix))]
+pub fn run_agent(_ttl: u64) -> Result<(), Box<dyn Error>> {
+    Err("vanish agent is only supported on Unix platforms".into())
+}
+
+// Requests are a single command line, optionally followed by a PEM payload:
+//   GET <fingerprint>        -> "OK\n<pem>" or "NONE\n"
+//   PUT <fingerprint>\n<pem> -> "OK\n"
+//   LOCK                     -> "OK\n"
+#[cfg(unix)]
+pub(crate) mod client {
+    use super::AGENT_TIMEOUT;
+    use openssl::pkey::{PKey, Private};
+    use std::{
+        io::{self, Read, Write},
+        net::Shutdown,
+        os::unix::net::UnixStream,
+        path::Path,
+    };
+
+    fn send(socket: &Path, request: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let mut stream: UnixStream = UnixStream::connect(socket)?;
+        stream.set_read_timeout(Some(AGENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(AGENT_TIMEOUT))?;
+        stream.write_all(request)?;
+        stream.shutdown(Shutdown::Write)?;
+        let mut response: Vec<u8> = Vec::new();
+        stream.read_to_end(&mut response)?;
+        Ok(response)
+    }
+
+    pub fn request_key(socket: &Path, fingerprint: &str) -> Option<PKey<Private>> {
+        let response: Vec<u8> = send(socket, format!("GET {}\n", fingerprint).as_bytes()).ok()?;
+        let pem: &[u8] = response.strip_prefix(b"OK\n")?;
+        PKey::private_key_from_pem(pem).ok()
+    }
+
+    pub fn cache_key(
+        socket: &Path,
+        fingerprint: &str,
+        key: &PKey<Private>,
+    ) -> Result<(), io::Error> {
+        let mut request: Vec<u8> = format!("PUT {}\n", fingerprint).into_bytes();
+        request.extend(key.private_key_to_pem_pkcs8()?);
+        match send(socket, &request)?.starts_with(b"OK\n") {
+            true => Ok(()),
+            false => Err(io::Error::other("vanish agent refused the key")),
+        }
+    }
+
+    pub fn lock(socket: &Path) -> Result<(), io::Error> {
+        send(socket, b"LOCK\n").map(|_| ())
+    }
+}
+
+#[cfg(not(unix))]
+mod client {
+    use openssl::pkey::{PKey, Private};
+    use std::{io, path::Path};
+
+    fn unsupported() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "vanish agent is only supported on Unix platforms",
+        )
+    }
+
+    pub fn request_key(_socket: &Path, _fingerprint: &str) -> Option<PKey<Private>> {
+        None
+    }
+
+    pub fn cache_key(
+        _socket: &Path,
+        _fingerprint: &str,
+        _key: &PKey<Private>,
+    ) -> Result<(), io::Error> {
+        Err(unsupported())
+    }
+
+    pub fn lock(_socket: &Path) -> Result<(), io::Error> {
+        Err(unsupported())
+    }
+}
+
+#[cfg(unix)]
+pub(crate) mod server {
+    use super::{socket_path, AGENT_TIMEOUT};
+    use crate::x509::file::{create_directory, DATA_DIR_MODE};
+    use colored::*;
+    use std::{
+        collections::HashMap,
+        error::Error,
+        fs,
+        io::{self, Read, Write},
+        os::unix::net::{UnixListener, UnixStream},
+        path::PathBuf,
+        time::{Duration, Instant},
+    };
+
+    pub fn run(ttl: u64) -> Result<(), Box<dyn Error>> {
+        let path: PathBuf = socket_path().ok_or("Unable to get Data Directory")?;
+        if let Some(parent) = path.parent() {
+            create_directory(parent, DATA_DIR_MODE)?;
+        }
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(
+                    format!("A vanish agent is already listening at {}", path.display()).into(),
+                );
+            }
+            fs::remove_file(&path)?;
+        }
+        // The socket is created owner-only, with no window where others could connect
+        // SAFETY: umask only swaps the process file mode mask
+        let umask: libc::mode_t = unsafe { libc::umask(0o177) };
+        let listener: Result<UnixListener, io::Error> = UnixListener::bind(&path);
+        // SAFETY: restores the mask saved above
+        unsafe { libc::umask(umask) };
+        let listener: UnixListener = listener?;
+        println!(
+            "{}: vanish agent listening at {}, unlocked keys are kept for {} seconds",
+            "Info".blue(),
+            path.display(),
+            ttl
+        );
+        serve(listener, Duration::from_secs(ttl));
+        Ok(())
+    }
+
+    pub fn serve(listener: UnixListener, ttl: Duration) {
+        let mut keys: HashMap<String, (Vec<u8>, Instant)> = HashMap::new();
+        for stream in listener.incoming() {
+            let mut stream: UnixStream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("{}: Accepting connection: {}", "Error".red(), err);
+                    continue;
+                }
+            };
+            keys.retain(|_, (_, expires_at)| *expires_at > Instant::now());
+
+            if stream.set_read_timeout(Some(AGENT_TIMEOUT)).is_err()
+                || stream.set_write_timeout(Some(AGENT_TIMEOUT)).is_err()
+            {
+                continue;
+            }
+            let mut request: Vec<u8> = Vec::new();
+            if stream.read_to_end(&mut request).is_err() {
+                continue;
+            }
+            let (command, payload): (&[u8], &[u8]) =
+                match request.iter().position(|byte: &u8| *byte == b'\n') {
+                    Some(newline) => (&request[..newline], &request[newline + 1..]),
+                    None => (&request[..], &[]),
+                };
+            let command: String = String::from_utf8_lossy(command).to_string();
+            let response: Vec<u8> = match command.split_once(' ') {
+                Some(("GET", fingerprint)) => match keys.get(fingerprint) {
+                    Some((pem, _)) => [b"OK\n".as_slice(), pem].concat(),
+                    None => b"NONE\n".to_vec(),
+                },
+                Some(("PUT", fingerprint)) if !payload.is_empty() => {
+                    keys.insert(
+                        fingerprint.to_string(),
+                        (payload.to_vec(), Instant::now() + ttl),
+                    );
+                    println!("{}: Cached CA key {}", "Info".blue(), fingerprint);
+                    b"OK\n".to_vec()
+                }
+                None if command == "LOCK" => {
+                    keys.clear();
+                    println!("{}: Forgot all cached keys", "Info".blue());
+                    b"OK\n".to_vec()
+                }
+                _ => b"ERR\n".to_vec(),
+            };
+            let _ = stream.write_all(&response);
+        }
+    }
+}
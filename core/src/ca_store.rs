@@ -0,0 +1,215 @@
+use crate::{
+    utils::unix_now,
+    x509::{
+        self,
+        ca_cert::CASettings,
+        describe::asn1_time_to_unix,
+        encoding::read_certificate,
+        file::{create_directory, write_file, Overwrite, CERT_FILE_MODE, DATA_DIR_MODE},
+    },
+};
+use colored::*;
+use openssl::x509::X509;
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub const CA_NAME_ENV: &str = "VANISH_CA";
+// The CA kept directly in the data directory, as created before named CAs existed
+pub const DEFAULT_CA_NAME: &str = "default";
+const NAMED_CAS_DIR: &str = "cas";
+const DEFAULT_CA_FILE: &str = "default_ca";
+const CA_SETTINGS_FILE: &str = "ca.json";
+// Kept in the CA directory while a rotation is in its grace period
+pub const PREVIOUS_CA_CERT_FILE: &str = "previous_ca_cert.pem";
+pub const PREVIOUS_CA_KEY_FILE: &str = "previous_ca_key.pem";
+pub const CROSS_SIGNED_FILE: &str = "cross_signed.pem";
+pub const ROTATION_FILE: &str = "rotation.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rotation {
+    pub rotated_at: String,
+    // The previous root stays trusted until then, see `vanish ca rotate --finish`
+    pub uninstall_after: String,
+}
+
+pub fn valid_ca_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+pub fn ca_dir(name: &str) -> Option<PathBuf> {
+    let data_dir: &PathBuf = x509::DATA_DIR.as_ref()?;
+    if name == DEFAULT_CA_NAME {
+        Some(data_dir.clone())
+    } else {
+        Some(data_dir.join(NAMED_CAS_DIR).join(name))
+    }
+}
+
+pub fn ca_exists(name: &str) -> bool {
+    valid_ca_name(name)
+        && ca_dir(name).is_some_and(|dir: PathBuf| dir.join("ca_cert.pem").is_file())
+}
+
+// `VANISH_CA` (also set by `--ca`) wins over the default pointer
+pub fn active_ca_name() -> String {
+    match env::var(CA_NAME_ENV) {
+        Ok(name) if !name.is_empty() => name,
+        _ => default_ca_name(),
+    }
+}
+
+pub fn active_ca_dir() -> Option<PathBuf> {
+    let name: String = active_ca_name();
+    if !valid_ca_name(&name) {
+        eprintln!(
+            "{}: \"{}\" is not a valid CA name, use letters, digits, `-` and `_`",
+            "Error".red(),
+            name
+        );
+        std::process::exit(1);
+    }
+    ca_dir(&name)
+}
+
+pub fn default_ca_name() -> String {
+    x509::DATA_DIR
+        .as_ref()
+        .and_then(|data_dir: &PathBuf| fs::read_to_string(data_dir.join(DEFAULT_CA_FILE)).ok())
+        .map(|name: String| name.trim().to_string())
+        .filter(|name: &String| valid_ca_name(name))
+        .unwrap_or_else(|| DEFAULT_CA_NAME.to_string())
+}
+
+pub fn set_default_ca(name: &str) -> Result<(), Box<dyn Error>> {
+    let data_dir: &PathBuf = x509::DATA_DIR
+        .as_ref()
+        .ok_or("Unable to get Data Directory")?;
+    let pointer: PathBuf = data_dir.join(DEFAULT_CA_FILE);
+    if !data_dir.exists() {
+        create_directory(data_dir, DATA_DIR_MODE)?;
+    }
+    if name == DEFAULT_CA_NAME {
+        if pointer.exists() {
+            fs::remove_file(pointer)?;
+        }
+        return Ok(());
+    }
+    let pointer: &str = pointer
+        .to_str()
+        .ok_or("Failed to convert the default CA path to string")?;
+    write_file(
+        pointer,
+        format!("{}\n", name).as_bytes(),
+        CERT_FILE_MODE,
+        Overwrite::Replace,
+    )?;
+    Ok(())
+}
+
+// Every CA with a certificate on disk, the default one first
+pub fn ca_names() -> Vec<String> {
+    let mut names: Vec<String> = x509::DATA_DIR
+        .as_ref()
+        .and_then(|data_dir: &PathBuf| fs::read_dir(data_dir.join(NAMED_CAS_DIR)).ok())
+        .map(|entries: fs::ReadDir| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry: fs::DirEntry| entry.file_name().into_string().ok())
+                .filter(|name: &String| name != DEFAULT_CA_NAME && ca_exists(name))
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    if ca_exists(DEFAULT_CA_NAME) {
+        names.insert(0, DEFAULT_CA_NAME.to_string());
+    }
+    names
+}
+
+pub fn load_ca_settings(ca_dir: &Path) -> CASettings {
+    let path: PathBuf = ca_dir.join(CA_SETTINGS_FILE);
+    match fs::read(&path) {
+        Ok(data) => match serde_json::from_slice(&data) {
+            Ok(settings) => settings,
+            Err(err) => {
+                eprintln!(
+                    "{}: Ignoring the CA settings in {}: {}",
+                    "Warning".yellow(),
+                    path.display(),
+                    err
+                );
+                CASettings::default()
+            }
+        },
+        Err(_) => CASettings::default(),
+    }
+}
+
+pub fn save_ca_settings(ca_dir: &Path, settings: &CASettings) -> Result<(), Box<dyn Error>> {
+    if !ca_dir.exists() {
+        create_directory(ca_dir, DATA_DIR_MODE)?;
+    }
+    let path: PathBuf = ca_dir.join(CA_SETTINGS_FILE);
+    let path: &str = path
+        .to_str()
+        .ok_or("Failed to convert the CA settings path to string")?;
+    let mut data: Vec<u8> = serde_json::to_vec_pretty(settings)?;
+    data.push(b'\n');
+    write_file(path, &data, CERT_FILE_MODE, Overwrite::Replace)?;
+    Ok(())
+}
+
+pub fn load_rotation(ca_dir: &Path) -> Option<Rotation> {
+    let data: Vec<u8> = fs::read(ca_dir.join(ROTATION_FILE)).ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+pub fn save_rotation(ca_dir: &Path, rotation: &Rotation) -> Result<(), Box<dyn Error>> {
+    let path: PathBuf = ca_dir.join(ROTATION_FILE);
+    let path: &str = path
+        .to_str()
+        .ok_or("Failed to convert the rotation path to string")?;
+    let mut data: Vec<u8> = serde_json::to_vec_pretty(rotation)?;
+    data.push(b'\n');
+    write_file(path, &data, CERT_FILE_MODE, Overwrite::Replace)?;
+    Ok(())
+}
+
+// The root a rotation replaced, until `vanish ca rotate --finish` ends the
+// grace period. Certificates it issued are still revoked in its name.
+pub fn previous_ca_cert(ca_dir: &Path) -> Option<X509> {
+    load_rotation(ca_dir)?;
+    let path: PathBuf = ca_dir.join(PREVIOUS_CA_CERT_FILE);
+    let data: Vec<u8> = fs::read(&path).ok()?;
+    read_certificate(&data, &path.display().to_string()).ok()
+}
+
+// What to serve after a leaf: the root cross-signed by its predecessor while a
+// rotation is under way, so both old and new trust stores accept the chain
+pub fn issuing_chain(ca_cert: &X509) -> Vec<X509> {
+    let cross_signed: Option<X509> = active_ca_dir()
+        .and_then(|ca_dir: PathBuf| fs::read(ca_dir.join(CROSS_SIGNED_FILE)).ok())
+        .and_then(|data: Vec<u8>| X509::from_pem(&data).ok())
+        .filter(|cross_signed: &X509| {
+            let same_key: bool = match (cross_signed.public_key(), ca_cert.public_key()) {
+                (Ok(cross_key), Ok(ca_key)) => cross_key.public_eq(&ca_key),
+                _ => false,
+            };
+            let still_valid: bool = asn1_time_to_unix(cross_signed.not_after())
+                .is_ok_and(|not_after: i64| not_after > unix_now());
+            same_key && still_valid
+        });
+    match cross_signed {
+        Some(cross_signed) => vec![cross_signed],
+        None => vec![ca_cert.clone()],
+    }
+}
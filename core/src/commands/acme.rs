@@ -0,0 +1,76 @@
+use super::utils::{serving_certificate, RevocationUrls};
+use crate::{
+    acme::server::AcmeServer,
+    http::{serve, tls_acceptor, HttpRequest},
+    utils::get_certificates_from_data_dir,
+    x509::describe::format_name,
+};
+use colored::*;
+use openssl::ssl::SslAcceptor;
+use std::{error::Error, net::TcpListener};
+
+pub fn serve_acme(
+    host: String,
+    port: u16,
+    http_01_port: u16,
+    dns_01: bool,
+    crl_url: Option<String>,
+    ocsp_url: Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    println!();
+    let Some((ca_cert, ca_key)) = get_certificates_from_data_dir() else {
+        eprintln!(
+            "{}: No CA Certificates found. Run `vanish generate` first",
+            "Error".red()
+        );
+        std::process::exit(1);
+    };
+    let revocation_urls: RevocationUrls = RevocationUrls::resolve(crl_url, ocsp_url)?;
+    // ACME clients insist on HTTPS, and already trust the Vanish CA
+    let (tls_cert, tls_key) = serving_certificate(&host, &ca_cert, &ca_key)?;
+    let acceptor: SslAcceptor = tls_acceptor(&tls_cert, &tls_key, &ca_cert, None)?;
+    let listener: TcpListener = TcpListener::bind((host.as_str(), port))?;
+
+    let base_url: String = format!("https://{}:{}", host, port);
+    println!(
+        "ACME server for \"{}\" listening at {}/directory 👂",
+        format_name(ca_cert.subject_name()),
+        base_url
+    );
+    println!();
+    println!(
+        "{}: http-01 challenges are checked at http://<domain>:{}/.well-known/acme-challenge/",
+        "Note".green(),
+        http_01_port
+    );
+    if dns_01 {
+        println!(
+            "{}: dns-01 TXT records are published by POSTing {{\"fqdn\", \"value\"}} to {}/dns/present (lego's `httpreq` provider)",
+            "Note".green(),
+            base_url
+        );
+    }
+    println!(
+        "{}: Accounts and orders are kept in memory and forgotten when the server stops",
+        "Note".green()
+    );
+    println!(
+        "{}: e.g. `certbot certonly --server {}/directory --standalone -d localhost`",
+        "Note".green(),
+        base_url
+    );
+    println!();
+
+    let mut server: AcmeServer = AcmeServer::new(
+        base_url,
+        ca_cert.clone(),
+        ca_key,
+        revocation_urls,
+        http_01_port,
+        dns_01,
+    );
+    serve(listener, Some(&acceptor), |request: &HttpRequest| {
+        server.handle(request)
+    });
+    Ok(())
+}
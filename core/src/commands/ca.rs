@@ -0,0 +1,702 @@
+use super::{
+    inspect::print_certificate,
+    uninstall::uninstall,
+    utils::{create_distinguished_name, install_trust},
+};
+use crate::{
+    agent::CA_PASSPHRASE_ENV,
+    ca_store::{
+        active_ca_name, ca_dir, ca_exists, ca_names, default_ca_name, load_ca_settings,
+        load_rotation, save_ca_settings, save_rotation, set_default_ca, valid_ca_name, Rotation,
+        CROSS_SIGNED_FILE, DEFAULT_CA_NAME, PREVIOUS_CA_CERT_FILE, PREVIOUS_CA_KEY_FILE,
+        ROTATION_FILE,
+    },
+    commands::crl::{CRL_DER_FILE, CRL_FILE, PREVIOUS_CRL_DER_FILE, PREVIOUS_CRL_FILE},
+    utils::{
+        ca_key_is_protected, data_dir_ca_cert, format_iso8601, get_certificates_from_data_dir,
+        parse_iso8601, read_new_passphrase, save_generated_cert_key_files, unix_now,
+    },
+    x509::{
+        self,
+        ca_cert::{CACert, CASettings, KeyType},
+        der::is_ca_certificate,
+        describe::{asn1_time_to_unix, format_name, key_description},
+        distinguished_name::DistinguishedName,
+        encoding::{is_encrypted_pem, read_certificate},
+        file::{
+            reserve_stdout_for_pem, write_file, Overwrite, CERT_FILE_MODE, KEY_FILE_MODE,
+            STDOUT_PATH,
+        },
+        Certificate,
+    },
+};
+use colored::*;
+use openssl::{
+    nid::Nid,
+    pkey::{Id, PKey, Private},
+    string::OpensslString,
+    x509::{X509Name, X509NameEntryRef, X509NameRef, X509},
+};
+use std::{error::Error, fs, path::PathBuf};
+
+// What a CA keeps in its directory, removed by `vanish ca delete`
+const CA_FILES: [&str; 11] = [
+    "ca_cert.pem",
+    "ca_key.pem",
+    "ca.json",
+    CRL_FILE,
+    CRL_DER_FILE,
+    PREVIOUS_CA_CERT_FILE,
+    PREVIOUS_CA_KEY_FILE,
+    PREVIOUS_CRL_FILE,
+    PREVIOUS_CRL_DER_FILE,
+    CROSS_SIGNED_FILE,
+    ROTATION_FILE,
+];
+
+// Creates a named CA next to the others. `main` has already made it the active
+// CA, so it is saved to its own directory.
+#[allow(clippy::too_many_arguments)]
+pub fn create_ca(
+    name: String,
+    key_type: KeyType,
+    validity_days: u32,
+    name_constraints: Vec<String>,
+    country: Option<String>,
+    commonname: Option<String>,
+    state: Option<String>,
+    make_default: bool,
+    install: bool,
+    protect_ca: bool,
+) -> Result<bool, Box<dyn Error>> {
+    println!();
+    if !valid_ca_name(&name) {
+        eprintln!(
+            "{}: \"{}\" is not a valid CA name, use letters, digits, `-` and `_`",
+            "Error".red(),
+            name
+        );
+        return Ok(false);
+    }
+    if ca_exists(&name) {
+        eprintln!("{}: A CA named \"{}\" already exists", "Error".red(), name);
+        return Ok(false);
+    }
+    if let Some(constraint) = name_constraints
+        .iter()
+        .find(|constraint: &&String| !valid_name_constraint(constraint))
+    {
+        eprintln!(
+            "{}: \"{}\" is not a DNS name to constrain the CA to",
+            "Error".red(),
+            constraint
+        );
+        return Ok(false);
+    }
+    let ca_dir: PathBuf = ca_dir(&name).ok_or("Unable to get Data Directory")?;
+
+    let settings: CASettings = CASettings {
+        key_type,
+        validity_days,
+        name_constraints,
+    };
+    let ca_passphrase: Option<String> = if protect_ca {
+        Some(read_new_passphrase(CA_PASSPHRASE_ENV, "the CA key")?)
+    } else {
+        None
+    };
+    // Named CAs default their Common Name to their name, so they are told apart in trust stores
+    let commonname: Option<String> = match (commonname, name.as_str()) {
+        (None, DEFAULT_CA_NAME) => None,
+        (None, name) => Some(format!("Vanish {} CA", name)),
+        (commonname, _) => commonname,
+    };
+    let (cert, key) = CACert::with_settings(
+        create_distinguished_name(&commonname, &country, &state),
+        &settings,
+    )?
+    .generate_certificate()?;
+    save_ca_settings(&ca_dir, &settings)?;
+    save_generated_cert_key_files(
+        &cert,
+        &key,
+        ca_passphrase.as_deref().map(str::as_bytes),
+        Overwrite::Refuse,
+    )?;
+    println!(
+        "{}: CA \"{}\" created for \"{}\" 🎉",
+        "Note".green(),
+        name,
+        format_name(cert.subject_name())
+    );
+
+    // The first CA becomes the default, so `vanish generate` doesn't create another
+    if make_default || !ca_exists(&default_ca_name()) {
+        set_default_ca(&name)?;
+        println!("{}: \"{}\" is now the default CA", "Note".green(), name);
+    }
+    if install {
+        println!();
+        return install_trust(&cert);
+    }
+    println!();
+    Ok(true)
+}
+
+pub fn list_cas() -> Result<(), Box<dyn Error>> {
+    println!();
+    let names: Vec<String> = ca_names();
+    let data_dir: String = x509::DATA_DIR
+        .as_ref()
+        .map(|data_dir: &PathBuf| data_dir.display().to_string())
+        .unwrap_or_default();
+    if names.is_empty() {
+        println!(
+            "{}: No CAs in {} yet, create one with `vanish ca create <name>`",
+            "Info".blue(),
+            data_dir
+        );
+        println!();
+        return Ok(());
+    }
+    let default_name: String = default_ca_name();
+    let active_name: String = active_ca_name();
+    println!("CAs in {} :", data_dir);
+    for name in &names {
+        let mut marks: Vec<&str> = Vec::new();
+        if *name == default_name {
+            marks.push("default");
+        }
+        if *name == active_name && active_name != default_name {
+            marks.push("active");
+        }
+        let label: String = if marks.is_empty() {
+            name.to_string()
+        } else {
+            format!("{} ({})", name, marks.join(", "))
+        };
+        match describe_ca(name) {
+            Ok(description) => println!("   - {} : {}", label.bold(), description),
+            Err(err) => println!("   - {} : {} ❌", label.bold(), err),
+        }
+    }
+    println!();
+    Ok(())
+}
+
+pub fn set_default(name: String) -> Result<bool, Box<dyn Error>> {
+    println!();
+    if !ca_exists(&name) {
+        eprintln!(
+            "{}: No CA named \"{}\", see `vanish ca list`",
+            "Error".red(),
+            name
+        );
+        return Ok(false);
+    }
+    set_default_ca(&name)?;
+    println!("{}: \"{}\" is now the default CA 👍", "Note".green(), name);
+    println!();
+    Ok(true)
+}
+
+pub fn show_ca(name: Option<String>) -> Result<bool, Box<dyn Error>> {
+    let name: String = name.unwrap_or_else(active_ca_name);
+    let Some((ca_dir, cert)) = read_named_ca(&name)? else {
+        return Ok(false);
+    };
+    let settings: CASettings = load_ca_settings(&ca_dir);
+    let protected: bool = fs::read(ca_dir.join("ca_key.pem"))
+        .map(|key_data: Vec<u8>| is_encrypted_pem(&key_data))
+        .unwrap_or(false);
+    println!();
+    println!("{}", format!("CA \"{}\"", name).bold());
+    println!("   Directory    : {}", ca_dir.display());
+    println!(
+        "   Default      : {}",
+        if name == default_ca_name() {
+            "Yes"
+        } else {
+            "No"
+        }
+    );
+    println!(
+        "   Key File     : {}",
+        if protected {
+            "Protected with a passphrase 🔒"
+        } else {
+            "Not protected"
+        }
+    );
+    if !settings.name_constraints.is_empty() {
+        println!("   Limited To   : {}", settings.name_constraints.join(", "));
+    }
+    if let Some(rotation) = load_rotation(&ca_dir) {
+        println!(
+            "   Rotation     : Rotated on {}, the previous root is trusted until {}",
+            rotation.rotated_at, rotation.uninstall_after
+        );
+    }
+    println!();
+    print_certificate(&cert, data_dir_ca_cert().as_ref())?;
+    println!();
+    Ok(true)
+}
+
+// Only the certificate, to hand out to other machines or tools
+pub fn export_ca(
+    name: Option<String>,
+    output: Option<String>,
+    der: bool,
+    force: bool,
+) -> Result<bool, Box<dyn Error>> {
+    let name: String = name.unwrap_or_else(active_ca_name);
+    let path: String =
+        output.unwrap_or_else(|| format!("{}-ca.{}", name, if der { "der" } else { "pem" }));
+    if path == STDOUT_PATH {
+        reserve_stdout_for_pem()?;
+    }
+    let Some((_ca_dir, cert)) = read_named_ca(&name)? else {
+        return Ok(false);
+    };
+    let data: Vec<u8> = if der { cert.to_der()? } else { cert.to_pem()? };
+    if let Err(err) = write_file(&path, &data, CERT_FILE_MODE, Overwrite::from(force)) {
+        eprintln!("{}", err);
+        return Ok(false);
+    }
+    if path != STDOUT_PATH {
+        println!();
+        println!(
+            "{}: CA \"{}\" exported to {} 👍",
+            "Note".green(),
+            name,
+            path
+        );
+        println!();
+    }
+    Ok(true)
+}
+
+// Adopts an existing CA. `main` has already made it the active CA, so it is
+// saved to its own directory.
+pub fn import_ca(
+    name: String,
+    certfile: String,
+    keyfile: Option<String>,
+    make_default: bool,
+    install: bool,
+    protect_ca: bool,
+) -> Result<bool, Box<dyn Error>> {
+    println!();
+    if !valid_ca_name(&name) {
+        eprintln!(
+            "{}: \"{}\" is not a valid CA name, use letters, digits, `-` and `_`",
+            "Error".red(),
+            name
+        );
+        return Ok(false);
+    }
+    if ca_exists(&name) {
+        eprintln!("{}: A CA named \"{}\" already exists", "Error".red(), name);
+        return Ok(false);
+    }
+    let (cert, key) = match CACert::load_ca_cert_or_bundle(&certfile, keyfile.as_deref()) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("{}", err);
+            return Ok(false);
+        }
+    };
+    if !cert.public_key()?.public_eq(&key) {
+        eprintln!(
+            "{}: The key in {} does not belong to the certificate in {}",
+            "Error".red(),
+            keyfile.as_deref().unwrap_or(&certfile),
+            certfile
+        );
+        return Ok(false);
+    }
+    if !is_ca_certificate(&cert)? {
+        eprintln!(
+            "{}: \"{}\" is not a CA certificate, its Basic Constraints don't allow it to issue certificates",
+            "Error".red(),
+            format_name(cert.subject_name())
+        );
+        return Ok(false);
+    }
+    if asn1_time_to_unix(cert.not_after())? <= unix_now() {
+        eprintln!(
+            "{}: \"{}\" expired on {}",
+            "Error".red(),
+            format_name(cert.subject_name()),
+            format_iso8601(asn1_time_to_unix(cert.not_after())?)
+        );
+        return Ok(false);
+    }
+    let ca_dir: PathBuf = ca_dir(&name).ok_or("Unable to get Data Directory")?;
+
+    let ca_passphrase: Option<String> = if protect_ca {
+        Some(read_new_passphrase(CA_PASSPHRASE_ENV, "the CA key")?)
+    } else {
+        None
+    };
+    // Recorded so that re-creating the CA gives the same kind of key and lifetime
+    let settings: CASettings = CASettings {
+        key_type: key_type_of(&key).unwrap_or_default(),
+        validity_days: ((asn1_time_to_unix(cert.not_after())?
+            - asn1_time_to_unix(cert.not_before())?)
+            / 86400) as u32,
+        name_constraints: Vec::new(),
+    };
+    save_ca_settings(&ca_dir, &settings)?;
+    save_generated_cert_key_files(
+        &cert,
+        &key,
+        ca_passphrase.as_deref().map(str::as_bytes),
+        Overwrite::Refuse,
+    )?;
+    println!(
+        "{}: CA \"{}\" imported for \"{}\" 🎉",
+        "Note".green(),
+        name,
+        format_name(cert.subject_name())
+    );
+
+    if make_default || !ca_exists(&default_ca_name()) {
+        set_default_ca(&name)?;
+        println!("{}: \"{}\" is now the default CA", "Note".green(), name);
+    }
+    if install {
+        println!();
+        return install_trust(&cert);
+    }
+    println!();
+    Ok(true)
+}
+
+// Untrusts the CA everywhere first, and keeps its files if that fails so it can be retried
+pub fn delete_ca(name: String, yes: bool) -> Result<bool, Box<dyn Error>> {
+    let Some((ca_dir, _cert)) = read_named_ca(&name)? else {
+        return Ok(false);
+    };
+    if !yes {
+        eprintln!(
+            "{}: Deleting CA \"{}\" removes its private key for good, every certificate it issued can no longer be renewed or revoked",
+            "Warning".yellow(),
+            name
+        );
+        eprintln!("{}: Pass `--yes` to delete it", "Note".green());
+        return Ok(false);
+    }
+    let mut trusted: Vec<String> = vec![ca_dir.join("ca_cert.pem").display().to_string()];
+    // Mid-rotation, the previous root is still installed as well
+    if load_rotation(&ca_dir).is_some() {
+        trusted.push(ca_dir.join(PREVIOUS_CA_CERT_FILE).display().to_string());
+    }
+    let mut untrusted: bool = true;
+    for cert_path in trusted {
+        untrusted &= uninstall(Some(cert_path))?;
+    }
+    if !untrusted {
+        eprintln!(
+            "{}: CA \"{}\" was kept, remove the remaining trust and try again",
+            "Note".green(),
+            name
+        );
+        return Ok(false);
+    }
+
+    if name == DEFAULT_CA_NAME {
+        // The data directory also holds the ledger and the other CAs
+        for file in CA_FILES {
+            let path: PathBuf = ca_dir.join(file);
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+    } else {
+        fs::remove_dir_all(&ca_dir)?;
+    }
+    if default_ca_name() == name {
+        set_default_ca(DEFAULT_CA_NAME)?;
+    }
+    println!();
+    println!(
+        "{}: CA \"{}\" deleted from {} 🗑️",
+        "Note".green(),
+        name,
+        ca_dir.display()
+    );
+    println!();
+    Ok(true)
+}
+
+// Replaces the CA's root with a new one that its predecessor cross-signs. The
+// old root stays installed for the grace period, then `--finish` removes it.
+pub fn rotate_ca(
+    name: Option<String>,
+    commonname: Option<String>,
+    grace_days: u32,
+    install: bool,
+) -> Result<bool, Box<dyn Error>> {
+    let name: String = name.unwrap_or_else(active_ca_name);
+    let Some((ca_dir, _cert)) = read_named_ca(&name)? else {
+        return Ok(false);
+    };
+    if let Some(rotation) = load_rotation(&ca_dir) {
+        eprintln!(
+            "{}: CA \"{}\" was already rotated on {}, the previous root is trusted until {}",
+            "Error".red(),
+            name,
+            rotation.rotated_at,
+            rotation.uninstall_after
+        );
+        eprintln!(
+            "{}: Run `vanish ca rotate {} --finish` first",
+            "Note".green(),
+            name
+        );
+        return Ok(false);
+    }
+    // `main` has already made it the active CA, so this loads (and unlocks) its key
+    let Some((old_cert, old_key)) = get_certificates_from_data_dir() else {
+        return Ok(false);
+    };
+    let old_subject: &X509NameRef = old_cert.subject_name();
+    let base_name: String = name_entry(old_subject, Nid::COMMONNAME)
+        .map(|common_name: String| strip_rotation_date(&common_name).to_string())
+        .unwrap_or_else(|| match name.as_str() {
+            DEFAULT_CA_NAME => "Vanish CA".to_string(),
+            name => format!("Vanish {} CA", name),
+        });
+    let timestamp: String = format_iso8601(unix_now());
+    let old_common_name: Option<String> = name_entry(old_subject, Nid::COMMONNAME);
+    // Dated, with the time as well when rotating twice on the same day
+    let common_name: String = commonname.unwrap_or_else(|| {
+        let dated: String = format!("{} {}", base_name, &timestamp[..10]);
+        if old_common_name.as_deref() == Some(dated.as_str()) {
+            format!("{} {}", base_name, timestamp)
+        } else {
+            dated
+        }
+    });
+    let distinguished_name: DistinguishedName = DistinguishedName {
+        common_name: Some(common_name),
+        organization: name_entry(old_subject, Nid::ORGANIZATIONNAME)
+            .unwrap_or_else(|| "Vanish".to_string()),
+        country: name_entry(old_subject, Nid::COUNTRYNAME),
+        state: name_entry(old_subject, Nid::STATEORPROVINCENAME),
+    };
+    let new_subject: X509Name =
+        DistinguishedName::distinguished_name_builder(distinguished_name.clone())?;
+    if new_subject.to_der()? == old_subject.to_der()? {
+        eprintln!(
+            "{}: The new root needs a name of its own to be told apart from \"{}\", pass another `--cn`",
+            "Error".red(),
+            format_name(old_subject)
+        );
+        return Ok(false);
+    }
+    let settings: CASettings = load_ca_settings(&ca_dir);
+    let (new_cert, new_key) =
+        CACert::with_settings(distinguished_name, &settings)?.generate_certificate()?;
+    let cross_signed: X509 = CACert::cross_sign(&new_cert, &old_cert, &old_key)?;
+    let ca_passphrase: Option<String> = if ca_key_is_protected() {
+        Some(read_new_passphrase(CA_PASSPHRASE_ENV, "the new CA key")?)
+    } else {
+        None
+    };
+
+    println!();
+    let path = |file: &str| -> Result<String, Box<dyn Error>> {
+        Ok(ca_dir
+            .join(file)
+            .to_str()
+            .ok_or("Failed to convert the CA path to string")?
+            .to_string())
+    };
+    // The old key is moved as is, still encrypted if it was
+    let old_key_data: Vec<u8> = fs::read(ca_dir.join("ca_key.pem"))?;
+    CACert::save_cert(&old_cert, &path(PREVIOUS_CA_CERT_FILE)?, Overwrite::Backup)?;
+    write_file(
+        &path(PREVIOUS_CA_KEY_FILE)?,
+        &old_key_data,
+        KEY_FILE_MODE,
+        Overwrite::Backup,
+    )?;
+    CACert::save_cert(&cross_signed, &path(CROSS_SIGNED_FILE)?, Overwrite::Replace)?;
+    CACert::save_cert(&new_cert, &path("ca_cert.pem")?, Overwrite::Replace)?;
+    CACert::save_key(
+        &new_key,
+        &path("ca_key.pem")?,
+        ca_passphrase.as_deref().map(str::as_bytes),
+        Overwrite::Replace,
+    )?;
+    let now: i64 = unix_now();
+    save_rotation(
+        &ca_dir,
+        &Rotation {
+            rotated_at: format_iso8601(now),
+            uninstall_after: format_iso8601(now + i64::from(grace_days) * 86400),
+        },
+    )?;
+    println!(
+        "{}: CA \"{}\" rotated from \"{}\" to \"{}\" 🔄",
+        "Note".green(),
+        name,
+        format_name(old_subject),
+        format_name(new_cert.subject_name())
+    );
+    println!(
+        "{}: New certificates come with a chain cross-signed by the previous root, so machines that only trust it keep accepting them",
+        "Note".green()
+    );
+
+    let mut success: bool = true;
+    if install {
+        println!();
+        success = install_trust(&new_cert)?;
+    }
+    if grace_days == 0 {
+        println!();
+        return Ok(finish_rotation(Some(name), true)? && success);
+    }
+    println!(
+        "{}: Run `vanish ca rotate {} --finish` after {} to stop trusting the previous root",
+        "Info".blue(),
+        name,
+        format_iso8601(now + i64::from(grace_days) * 86400)
+    );
+    println!();
+    Ok(success)
+}
+
+pub fn finish_rotation(name: Option<String>, now: bool) -> Result<bool, Box<dyn Error>> {
+    let name: String = name.unwrap_or_else(active_ca_name);
+    let Some((ca_dir, _cert)) = read_named_ca(&name)? else {
+        return Ok(false);
+    };
+    let Some(rotation) = load_rotation(&ca_dir) else {
+        eprintln!(
+            "{}: CA \"{}\" has no rotation to finish",
+            "Error".red(),
+            name
+        );
+        return Ok(false);
+    };
+    let uninstall_after: i64 = parse_iso8601(&rotation.uninstall_after).unwrap_or(0);
+    if !now && unix_now() < uninstall_after {
+        eprintln!(
+            "{}: The grace period of CA \"{}\" lasts until {}",
+            "Error".red(),
+            name,
+            rotation.uninstall_after
+        );
+        eprintln!(
+            "{}: Pass `--now` to stop trusting the previous root anyway, certificates issued before the rotation stop working then",
+            "Note".green()
+        );
+        return Ok(false);
+    }
+    let previous_cert: String = ca_dir.join(PREVIOUS_CA_CERT_FILE).display().to_string();
+    if !uninstall(Some(previous_cert))? {
+        return Ok(false);
+    }
+    // The previous root and key stay on disk, only the chain stops pointing at them
+    for file in [CROSS_SIGNED_FILE, ROTATION_FILE] {
+        let path: PathBuf = ca_dir.join(file);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    println!();
+    println!(
+        "{}: Rotation of CA \"{}\" finished 👍",
+        "Note".green(),
+        name
+    );
+    println!();
+    Ok(true)
+}
+
+fn name_entry(name: &X509NameRef, nid: Nid) -> Option<String> {
+    name.entries_by_nid(nid)
+        .next()
+        .and_then(|entry: &X509NameEntryRef| entry.data().as_utf8().ok())
+        .map(|data: OpensslString| data.to_string())
+}
+
+// `Vanish work CA 2026-10-19` becomes `Vanish work CA` again, so repeated
+// rotations don't pile up dates
+fn strip_rotation_date(common_name: &str) -> &str {
+    match common_name.rsplit_once(' ') {
+        Some((base, stamp))
+            if parse_iso8601(stamp)
+                .or_else(|| parse_iso8601(&format!("{}T00:00:00Z", stamp)))
+                .is_some() =>
+        {
+            base
+        }
+        _ => common_name,
+    }
+}
+
+fn read_named_ca(name: &str) -> Result<Option<(PathBuf, X509)>, Box<dyn Error>> {
+    if !ca_exists(name) {
+        eprintln!(
+            "{}: No CA named \"{}\", see `vanish ca list`",
+            "Error".red(),
+            name
+        );
+        return Ok(None);
+    }
+    let ca_dir: PathBuf = ca_dir(name).ok_or("Unable to get Data Directory")?;
+    let path: PathBuf = ca_dir.join("ca_cert.pem");
+    let cert: X509 = read_certificate(&fs::read(&path)?, &path.display().to_string())?;
+    Ok(Some((ca_dir, cert)))
+}
+
+fn key_type_of(key: &PKey<Private>) -> Option<KeyType> {
+    match key.id() {
+        Id::RSA if key.bits() == 2048 => Some(KeyType::Rsa2048),
+        Id::RSA if key.bits() == 4096 => Some(KeyType::Rsa4096),
+        Id::EC => {
+            let curve: Option<Nid> = key.ec_key().ok()?.group().curve_name();
+            (curve == Some(Nid::X9_62_PRIME256V1)).then_some(KeyType::EcdsaP256)
+        }
+        _ => None,
+    }
+}
+
+fn describe_ca(name: &str) -> Result<String, Box<dyn Error>> {
+    let ca_dir: PathBuf = ca_dir(name).ok_or("Unable to get Data Directory")?;
+    let path: PathBuf = ca_dir.join("ca_cert.pem");
+    let cert: X509 = read_certificate(&fs::read(&path)?, &path.display().to_string())?;
+    let public_key = cert.public_key()?;
+    let settings: CASettings = load_ca_settings(&ca_dir);
+    let mut description: String = format!(
+        "\"{}\", {}, expires {}",
+        format_name(cert.subject_name()),
+        key_description(&public_key),
+        format_iso8601(asn1_time_to_unix(cert.not_after())?)
+    );
+    if !settings.name_constraints.is_empty() {
+        description.push_str(&format!(
+            ", limited to {}",
+            settings.name_constraints.join(", ")
+        ));
+    }
+    Ok(description)
+}
+
+// A DNS name or a `.`-prefixed domain, as accepted in a dNSName constraint
+fn valid_name_constraint(constraint: &str) -> bool {
+    let name: &str = constraint.strip_prefix('.').unwrap_or(constraint);
+    !name.is_empty()
+        && name.split('.').all(|label: &str| {
+            !label.is_empty()
+                && label
+                    .chars()
+                    .all(|c: char| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
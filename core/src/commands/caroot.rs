@@ -0,0 +1,17 @@
+use crate::x509::{self, CAROOT_ENV};
+use colored::*;
+
+// Prints only the path, so scripts can use `$(vanish caroot)`
+pub fn caroot() {
+    match x509::DATA_DIR.as_ref() {
+        Some(data_dir) => println!("{}", data_dir.display()),
+        None => {
+            eprintln!(
+                "{}: Could not find the data directory, set {} or pass `--ca-dir`",
+                "Error".red(),
+                CAROOT_ENV
+            );
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::{
+    ca_store::{active_ca_dir, previous_ca_cert},
+    ledger::{Ledger, LedgerEntry, LedgerLock},
+    utils::{
+        certificate_fingerprint, format_iso8601, get_certificates_from_data_dir, parse_iso8601,
+        previous_ca_from_data_dir, unix_now,
+    },
+    x509::{
+        crl::{build_crl, save_crl, RevokedCertificate},
+        describe::format_name,
+        file::{reserve_stdout_for_pem, Overwrite, STDOUT_PATH},
+    },
+};
+use colored::*;
+use openssl::{
+    pkey::{PKey, Private},
+    x509::{X509Crl, X509},
+};
+use std::{error::Error, path::PathBuf};
+
+pub const CRL_FILE: &str = "crl.pem";
+pub const CRL_DER_FILE: &str = "crl.der";
+// Signed by the previous root while a rotation is under way
+pub const PREVIOUS_CRL_FILE: &str = "previous_crl.pem";
+pub const PREVIOUS_CRL_DER_FILE: &str = "previous_crl.der";
+
+pub fn crl(
+    output: Option<String>,
+    next_update_days: u32,
+    der: bool,
+    force: bool,
+    previous: bool,
+) -> Result<bool, Box<dyn Error>> {
+    if output.as_deref() == Some(STDOUT_PATH) {
+        reserve_stdout_for_pem()?;
+    }
+    println!();
+    let _lock: LedgerLock = Ledger::lock()?;
+    let mut ledger: Ledger = Ledger::load()?;
+    let now: i64 = unix_now();
+    let next_update: i64 = now + i64::from(next_update_days) * 86400;
+
+    if previous {
+        let Some((previous_cert, previous_key)) = previous_ca_from_data_dir()? else {
+            eprintln!(
+                "{}: The active CA has no rotation under way, so there is no previous root to sign a CRL with",
+                "Error".red()
+            );
+            return Ok(false);
+        };
+        let file: &str = if der {
+            PREVIOUS_CRL_DER_FILE
+        } else {
+            PREVIOUS_CRL_FILE
+        };
+        let Some((path, overwrite)) = crl_path(output, file, force) else {
+            return Ok(false);
+        };
+        sign_crl(
+            &mut ledger,
+            &previous_cert,
+            &previous_key,
+            &path,
+            der,
+            overwrite,
+            now,
+            next_update,
+        )?;
+    } else {
+        let Some((ca_cert, ca_key)) = get_certificates_from_data_dir() else {
+            eprintln!(
+                "{}: No CA Certificates found. Run `vanish generate` first",
+                "Error".red()
+            );
+            return Ok(false);
+        };
+        let output_is_default: bool = output.is_none();
+        let file: &str = if der { CRL_DER_FILE } else { CRL_FILE };
+        let Some((path, overwrite)) = crl_path(output, file, force) else {
+            return Ok(false);
+        };
+        sign_crl(
+            &mut ledger,
+            &ca_cert,
+            &ca_key,
+            &path,
+            der,
+            overwrite,
+            now,
+            next_update,
+        )?;
+
+        // Certificates from before a rotation are revoked by the root that
+        // issued them, on a CRL of its own next to the new one
+        if output_is_default {
+            if let Some((previous_cert, previous_key)) = previous_ca_from_data_dir()? {
+                let file: &str = if der {
+                    PREVIOUS_CRL_DER_FILE
+                } else {
+                    PREVIOUS_CRL_FILE
+                };
+                if let Some((path, overwrite)) = crl_path(None, file, force) {
+                    sign_crl(
+                        &mut ledger,
+                        &previous_cert,
+                        &previous_key,
+                        &path,
+                        der,
+                        overwrite,
+                        now,
+                        next_update,
+                    )?;
+                }
+            }
+        } else if active_ca_dir()
+            .and_then(|ca_dir: PathBuf| previous_ca_cert(&ca_dir))
+            .is_some()
+        {
+            println!(
+                "{}: Certificates issued before the rotation are listed by the previous root, publish its CRL with `vanish crl --previous -o <file>`",
+                "Info".blue()
+            );
+            println!();
+        }
+    }
+    ledger.save()?;
+
+    println!(
+        "{}: Next update is due by {}, run `vanish crl` again before then",
+        "Note".green(),
+        format_iso8601(next_update)
+    );
+    println!();
+    Ok(true)
+}
+
+// `--out` follows `--force`, the CA's own CRLs are refreshed in place
+fn crl_path(output: Option<String>, file: &str, force: bool) -> Option<(String, Overwrite)> {
+    match output {
+        Some(output) => Some((output, Overwrite::from(force))),
+        None => match active_ca_dir() {
+            Some(ca_dir) => Some((ca_dir.join(file).display().to_string(), Overwrite::Replace)),
+            None => {
+                eprintln!(
+                    "{}: Could not find the data directory, pass `--out`",
+                    "Error".red()
+                );
+                None
+            }
+        },
+    }
+}
+
+// Only what this CA issued belongs on its CRL
+#[allow(clippy::too_many_arguments)]
+fn sign_crl(
+    ledger: &mut Ledger,
+    ca_cert: &X509,
+    ca_key: &PKey<Private>,
+    path: &str,
+    der: bool,
+    overwrite: Overwrite,
+    this_update: i64,
+    next_update: i64,
+) -> Result<(), Box<dyn Error>> {
+    let ca_fingerprint: String = certificate_fingerprint(ca_cert)?;
+    let revoked: Vec<RevokedCertificate> = ledger
+        .certificates
+        .iter()
+        .filter(|entry: &&LedgerEntry| entry.ca_fingerprint == ca_fingerprint)
+        .filter_map(|entry: &LedgerEntry| {
+            let revocation = entry.revocation.as_ref()?;
+            Some(RevokedCertificate {
+                serial: entry.serial.clone(),
+                revoked_at: parse_iso8601(&revocation.revoked_at)?,
+                reason: revocation.reason,
+            })
+        })
+        .collect();
+    let crl_number: u64 = ledger.next_crl_number(&ca_fingerprint);
+    let crl: X509Crl = build_crl(
+        ca_cert,
+        ca_key,
+        &revoked,
+        this_update,
+        next_update,
+        crl_number,
+    )?;
+    save_crl(&crl, path, der, overwrite)?;
+
+    println!(
+        "Signed CRL #{} for \"{}\" with {} revoked certificate(s) 👍",
+        crl_number,
+        format_name(ca_cert.subject_name()),
+        revoked.len()
+    );
+    if path == STDOUT_PATH {
+        println!("{}: The CRL is written to standard output", "Note".green());
+    } else {
+        println!(
+            "{}: The CRL is saved at: {:?}",
+            "Note".green(),
+            PathBuf::from(path)
+        );
+    }
+    println!();
+    Ok(())
+}
@@ -1,11 +1,23 @@
 use super::utils::{
-    create_distinguished_name, generate_install, save_csr_certificate, save_pem_certificate,
-    save_pem_key_pair,
+    create_distinguished_name, install_trust, issue_for_domain, save_csr_certificate,
+    save_pem_bundles, save_pem_certificate, save_pem_key_pair, unique_certificate_file_name,
+    OutputFiles, RevocationUrls,
 };
 use crate::{
-    utils::{get_certificates_from_data_dir, save_generated_cert_key_files},
+    agent::CA_PASSPHRASE_ENV,
+    ca_store::{active_ca_dir, load_ca_settings},
+    ledger::{ledger_path, record_issued},
+    utils::{
+        ca_key_is_protected, get_certificates_from_data_dir, protect_data_dir_ca_key,
+        read_new_passphrase, save_generated_cert_key_files,
+    },
     x509::{
-        ca_cert::CACert, ca_req::CAReq, distinguished_name::DistinguishedName, leaf_cert::LeafCert,
+        bundle::BundleLayout,
+        ca_cert::{CACert, CASettings},
+        ca_req::CAReq,
+        distinguished_name::DistinguishedName,
+        file::{reserve_stdout_for_pem, Overwrite},
+        leaf_cert::LeafCert,
         Certificate,
     },
 };
@@ -15,11 +27,13 @@ use openssl::{
     x509::{X509Req, X509},
 };
 use std::{
+    collections::HashSet,
     env::current_dir,
     error::{self, Error},
     path::PathBuf,
 };
 
+#[allow(clippy::too_many_arguments)]
 pub fn generate(
     domains: Vec<String>,
     noca: bool,
@@ -29,20 +43,39 @@ pub fn generate(
     country: Option<String>,
     commonname: Option<String>,
     state: Option<String>,
-    output: Option<String>,
+    files: OutputFiles,
     request: bool,
     install: bool,
-) -> Result<(), Box<dyn error::Error>> {
+    bundles: Vec<BundleLayout>,
+    key_passphrase: Option<String>,
+    protect_ca: bool,
+    force: bool,
+    crl_url: Option<String>,
+    ocsp_url: Option<String>,
+) -> Result<bool, Box<dyn error::Error>> {
+    let key_passphrase: Option<&[u8]> = key_passphrase.as_deref().map(str::as_bytes);
+    let revocation_urls: RevocationUrls = RevocationUrls::resolve(crl_url, ocsp_url)?;
+    let overwrite: Overwrite = Overwrite::from(force);
+    if files.uses_stdout() {
+        reserve_stdout_for_pem()?;
+    }
     println!();
     if request {
+        let mut all_saved: bool = true;
         println!("Generated Certificate Requests for :");
         for domain in &domains {
             let distinguished_name: DistinguishedName =
                 create_distinguished_name(&commonname, &country, &state);
             let (ca_req_certificate, private_key) =
                 CAReq::new(distinguished_name)?.generate_certificate()?;
-            let is_saved: Result<PathBuf, Box<dyn Error>> =
-                save_csr_certificate(domain.to_string(), &output, ca_req_certificate, private_key);
+            let is_saved: Result<PathBuf, Box<dyn Error>> = save_csr_certificate(
+                domain.to_string(),
+                &files,
+                ca_req_certificate,
+                private_key,
+                key_passphrase,
+                overwrite,
+            );
             match is_saved {
                 Ok(path) => {
                     println!("   - \"{}\" ✅", domain);
@@ -53,201 +86,94 @@ pub fn generate(
                         path
                     );
                 }
-                Err(_err) => {
+                Err(err) => {
                     println!("   - \"{}\" ❌", domain);
+                    eprintln!("{}", err);
+                    all_saved = false;
                 }
             }
         }
         println!();
-        return Ok(());
+        return Ok(all_saved);
     }
 
+    let distinguished_name: DistinguishedName =
+        create_distinguished_name(&commonname, &country, &state);
+
     if let Some(certfile) = certfile {
-        if let Some(keyfile) = keyfile {
-            let (cert, pkey) = match CACert::load_ca_cert(&certfile, &keyfile) {
-                Ok(cert_pkey) => cert_pkey,
-                Err(err) => {
-                    eprintln!("{}", err);
-                    std::process::exit(1);
-                }
-            };
-            if let Some(csr) = &csr {
-                let distinguished_name: DistinguishedName =
-                    create_distinguished_name(&commonname, &country, &state);
-                let csr_object: X509Req = match CAReq::read_csr_from_file(csr) {
-                    Ok(csr) => csr,
-                    Err(err) => {
-                        eprintln!("{}", err);
-                        std::process::exit(1);
-                    }
-                };
-                let leaf_cert_object: LeafCert = LeafCert::new(distinguished_name)?;
-                println!();
-                let (leaf_certificate, _private_key) = match LeafCert::generate_certificate(
-                    leaf_cert_object,
-                    &cert,
-                    &pkey,
-                    Some(&csr_object),
-                ) {
-                    Ok((a, b)) => {
-                        println!("Generating Certificate for Signing Request Successful! 👍");
-                        (a, b)
-                    }
-                    Err(err) => {
-                        println!("Generating Certificate for Signing Request Failed! 👎");
-                        eprintln!("{}", err);
-                        std::process::exit(1);
-                    }
-                };
-                match save_pem_certificate("csr_cert.pem".to_string(), output, leaf_certificate) {
-                    Ok(()) => {}
-                    Err(err) => {
-                        println!("{}", err);
-                        std::process::exit(1);
-                    }
-                };
-            } else {
-                println!();
-                println!("Generated Certificate for : ");
-                for domain in &domains {
-                    let distinguished_name: DistinguishedName =
-                        create_distinguished_name(&commonname, &country, &state);
-                    let leaf_cert_object: LeafCert = LeafCert::new(distinguished_name)?;
-                    let (leaf_certificate, private_key) = match LeafCert::generate_certificate(
-                        leaf_cert_object,
-                        &cert,
-                        &pkey,
-                        None,
-                    ) {
-                        Ok((a, b)) => {
-                            println!("   - \"{}\" ✅", domain);
-                            (a, b)
-                        }
-                        Err(err) => {
-                            println!("   - \"{}\" ❌", domain);
-                            eprintln!("{}", err);
-                            std::process::exit(1);
-                        }
-                    };
-                    if let Some(private_key) = private_key {
-                        match save_pem_key_pair(
-                            &output,
-                            leaf_certificate,
-                            domain.to_string(),
-                            private_key,
-                        ) {
-                            Ok(()) => {}
-                            Err(err) => {
-                                println!("{}", err);
-                            }
-                        };
-                    } else {
-                        eprintln!(
-                            "{}{}{}",
-                            "Oops! We lost your private key for domain ".yellow(),
-                            domain.yellow(),
-                            ". Please try again!".yellow()
-                        )
-                    }
-                }
-                println!();
-                println!(
-                    "{}: All successful certificates and their corresponding keys are saved at: {}",
-                    "Note".green(),
-                    match output {
-                        Some(a) => a,
-                        None => current_dir()
-                            .expect("Failed to get the current directory")
-                            .to_str()
-                            .expect("Failed to convert directory to string")
-                            .to_string(),
-                    }
-                );
+        // A PKCS#12 bundle or a combined PEM carries the key next to the certificate
+        let (cert, pkey) = match CACert::load_ca_cert_or_bundle(&certfile, keyfile.as_deref()) {
+            Ok(cert_pkey) => cert_pkey,
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
             }
-            if install {}
-            println!();
-            return Ok(());
-        } else {
-            eprintln!("{}: Corresponding KeyFile Not Found", "Error".red());
+        };
+        if install {
             println!();
-            std::process::exit(1);
+            install_trust(&cert)?;
         }
+        let all_saved: bool = if let Some(csr) = &csr {
+            generate_from_csr(
+                csr,
+                &distinguished_name,
+                &cert,
+                &pkey,
+                &files,
+                &bundles,
+                overwrite,
+                &revocation_urls,
+            )?
+        } else {
+            generate_for_domains(
+                &domains,
+                &distinguished_name,
+                &cert,
+                &pkey,
+                &files,
+                &bundles,
+                key_passphrase,
+                overwrite,
+                &revocation_urls,
+            )?
+        };
+        println!();
+        return Ok(all_saved);
     }
 
     let default_cert_key_files: Option<(X509, PKey<Private>)> = get_certificates_from_data_dir();
-    if let Some((d_cert, d_pkey)) = default_cert_key_files {
+    let all_saved: bool = if let Some((d_cert, d_pkey)) = default_cert_key_files {
+        if protect_ca && !ca_key_is_protected() {
+            let ca_passphrase: String = read_new_passphrase(CA_PASSPHRASE_ENV, "the CA key")?;
+            protect_data_dir_ca_key(&d_pkey, ca_passphrase.as_bytes())?;
+        }
         if install {
             println!();
-            generate_install(&d_cert)?;
+            install_trust(&d_cert)?;
         }
         if let Some(csr) = &csr {
-            let distinguished_name: DistinguishedName =
-                create_distinguished_name(&commonname, &country, &state);
-            let csr_object: X509Req = CAReq::read_csr_from_file(csr)?;
-            let leaf_cert_object: LeafCert = LeafCert::new(distinguished_name)?;
-            println!();
-            let (leaf_certificate, _private_key) = match LeafCert::generate_certificate(
-                leaf_cert_object,
+            generate_from_csr(
+                csr,
+                &distinguished_name,
                 &d_cert,
                 &d_pkey,
-                Some(&csr_object),
-            ) {
-                Ok((a, b)) => {
-                    println!("Generating Certificate for Signing Request Successful! 👍");
-                    (a, b)
-                }
-                Err(err) => {
-                    println!("Generating Certificate for Signing Request Failed! 👎");
-                    eprintln!("{}", err);
-                    std::process::exit(1);
-                }
-            };
-            save_pem_certificate("csr_cert.pem".to_string(), output, leaf_certificate)?;
+                &files,
+                &bundles,
+                overwrite,
+                &revocation_urls,
+            )?
         } else {
-            println!();
-            println!("Generated Certificate for : ");
-            for domain in &domains {
-                let distinguished_name: DistinguishedName =
-                    create_distinguished_name(&commonname, &country, &state);
-                let leaf_cert_object: LeafCert = LeafCert::new(distinguished_name)?;
-                let (leaf_certificate, private_key) = match LeafCert::generate_certificate(
-                    leaf_cert_object,
-                    &d_cert,
-                    &d_pkey,
-                    None,
-                ) {
-                    Ok((a, b)) => {
-                        println!("   - \"{}\" ✅", domain);
-                        (a, b)
-                    }
-                    Err(err) => {
-                        println!("   - \"{}\" ❌", domain);
-                        eprintln!("{}", err);
-                        std::process::exit(1);
-                    }
-                };
-                if let Some(private_key) = private_key {
-                    save_pem_key_pair(&output, leaf_certificate, domain.to_string(), private_key)?;
-                } else {
-                    eprintln!(
-                        "Oops! We lost your private key for domain {}. Please try again!",
-                        domain
-                    )
-                }
-            }
-            println!();
-            println!(
-                "{}: All Successful Certificates and their corresponding keys are saved at : {}",
-                "Note".green(),
-                match output {
-                    Some(a) => a,
-                    None => current_dir()
-                        .expect("Failed to get the current directory")
-                        .to_str()
-                        .expect("Failed to convert directory to string")
-                        .to_string(),
-                }
-            );
+            generate_for_domains(
+                &domains,
+                &distinguished_name,
+                &d_cert,
+                &d_pkey,
+                &files,
+                &bundles,
+                key_passphrase,
+                overwrite,
+                &revocation_urls,
+            )?
         }
     } else {
         if noca {
@@ -257,100 +183,201 @@ pub fn generate(
             );
             std::process::exit(1)
         }
-        let distinguished_name: DistinguishedName =
-            create_distinguished_name(&commonname, &country, &state);
+        let settings: CASettings = active_ca_dir()
+            .map(|ca_dir: PathBuf| load_ca_settings(&ca_dir))
+            .unwrap_or_default();
         let (created_cert, created_key) =
-            CACert::new(distinguished_name)?.generate_certificate()?;
-        save_generated_cert_key_files(&created_cert, &created_key)?;
+            CACert::with_settings(distinguished_name.clone(), &settings)?.generate_certificate()?;
+        let ca_passphrase: Option<String> = if protect_ca {
+            Some(read_new_passphrase(CA_PASSPHRASE_ENV, "the CA key")?)
+        } else {
+            None
+        };
+        save_generated_cert_key_files(
+            &created_cert,
+            &created_key,
+            ca_passphrase.as_deref().map(str::as_bytes),
+            overwrite,
+        )?;
 
         if install {
             println!();
-            generate_install(&created_cert)?;
+            install_trust(&created_cert)?;
         }
 
         if let Some(csr) = &csr {
-            let distinguished_name: DistinguishedName =
-                create_distinguished_name(&commonname, &country, &state);
-            let csr_object: X509Req = CAReq::read_csr_from_file(csr)?;
-            let leaf_cert_object: LeafCert = LeafCert::new(distinguished_name)?;
-            println!();
-            let (leaf_certificate, _private_key) = match LeafCert::generate_certificate(
-                leaf_cert_object,
+            generate_from_csr(
+                csr,
+                &distinguished_name,
                 &created_cert,
                 &created_key,
-                Some(&csr_object),
-            ) {
-                Ok((a, b)) => {
-                    println!("Generating Certificate for Signing Request Successful! 👍");
-                    (a, b)
-                }
+                &files,
+                &bundles,
+                overwrite,
+                &revocation_urls,
+            )?
+        } else {
+            generate_for_domains(
+                &domains,
+                &distinguished_name,
+                &created_cert,
+                &created_key,
+                &files,
+                &bundles,
+                key_passphrase,
+                overwrite,
+                &revocation_urls,
+            )?
+        }
+    };
+    println!();
+    Ok(all_saved)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn generate_from_csr(
+    csr: &str,
+    distinguished_name: &DistinguishedName,
+    ca_cert: &X509,
+    ca_key: &PKey<Private>,
+    files: &OutputFiles,
+    bundles: &[BundleLayout],
+    overwrite: Overwrite,
+    revocation_urls: &RevocationUrls,
+) -> Result<bool, Box<dyn Error>> {
+    let csr_objects: Vec<X509Req> = match CAReq::read_csrs_from_file(csr) {
+        Ok(csrs) => csrs,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    let mut all_saved: bool = true;
+    let mut used_names: HashSet<String> = HashSet::new();
+    for csr_object in &csr_objects {
+        let leaf_cert_object: LeafCert =
+            revocation_urls.apply(LeafCert::new(distinguished_name.clone())?);
+        println!();
+        let (leaf_certificate, _private_key) = match LeafCert::generate_certificate(
+            leaf_cert_object,
+            ca_cert,
+            ca_key,
+            Some(csr_object),
+        ) {
+            Ok((a, b)) => {
+                println!("Generating Certificate for Signing Request Successful! 👍");
+                (a, b)
+            }
+            Err(err) => {
+                println!("Generating Certificate for Signing Request Failed! 👎");
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        };
+        let name: String = unique_certificate_file_name(&leaf_certificate, &mut used_names);
+        // Bundles come last, so a bundle never ends up next to a certificate
+        // that failed to save. What is on disk is recorded even if a bundle fails.
+        let saved: Result<(), Box<dyn Error>> =
+            save_pem_certificate(&name, files, leaf_certificate.clone(), overwrite).and_then(
+                |cert_path: String| {
+                    record_issued(&leaf_certificate, ca_cert, ledger_path(&cert_path), None);
+                    save_pem_bundles(
+                        files,
+                        &name,
+                        bundles,
+                        &leaf_certificate,
+                        None,
+                        ca_cert,
+                        None,
+                        overwrite,
+                    )
+                },
+            );
+        if let Err(err) = saved {
+            println!("Saving Certificate for Signing Request Failed! 👎");
+            eprintln!("{}", err);
+            all_saved = false;
+        }
+    }
+    Ok(all_saved)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn generate_for_domains(
+    domains: &[String],
+    distinguished_name: &DistinguishedName,
+    ca_cert: &X509,
+    ca_key: &PKey<Private>,
+    files: &OutputFiles,
+    bundles: &[BundleLayout],
+    key_passphrase: Option<&[u8]>,
+    overwrite: Overwrite,
+    revocation_urls: &RevocationUrls,
+) -> Result<bool, Box<dyn Error>> {
+    let mut all_saved: bool = true;
+    println!();
+    println!("Generated Certificate for : ");
+    for domain in domains {
+        let (leaf_certificate, private_key) =
+            match issue_for_domain(domain, distinguished_name, ca_cert, ca_key, revocation_urls) {
+                Ok((a, b)) => (a, b),
                 Err(err) => {
-                    println!("Generating Certificate for Signing Request Failed! 👎");
+                    println!("   - \"{}\" ❌", domain);
                     eprintln!("{}", err);
                     std::process::exit(1);
                 }
             };
-            save_pem_certificate("csr_cert.pem".to_string(), output, leaf_certificate)?;
-        } else {
-            println!();
-            println!("Generated Certificate for : ");
-            for domain in &domains {
-                let distinguished_name: DistinguishedName =
-                    create_distinguished_name(&commonname, &country, &state);
-                let leaf_cert_object: LeafCert = LeafCert::new(distinguished_name)?;
-                let (leaf_certificate, private_key) = match LeafCert::generate_certificate(
-                    leaf_cert_object,
-                    &created_cert,
-                    &created_key,
-                    None,
-                ) {
-                    Ok((a, b)) => {
-                        println!("   - \"{}\" ✅", domain);
-                        (a, b)
-                    }
-                    Err(err) => {
-                        println!("   - \"{}\" ❌", domain);
-                        eprintln!("{}", err);
-                        std::process::exit(1);
-                    }
-                };
-                if let Some(private_key) = private_key {
-                    match save_pem_key_pair(
-                        &output,
-                        leaf_certificate,
-                        domain.to_string(),
-                        private_key,
-                    ) {
-                        Ok(()) => {}
-                        Err(err) => {
-                            println!("{}", err);
-                        }
-                    };
-                } else {
-                    eprintln!(
-                        "{}{}{}",
-                        "Oops! We lost your private key for domain ".yellow(),
-                        domain.yellow(),
-                        ". Please try again!".yellow()
-                    )
-                }
-            }
-
-            println!();
-            println!(
-                "{}: All Successful Certificates and their corresponding keys are saved at : {}",
-                "Note".green(),
-                match output {
-                    Some(a) => a,
-                    None => current_dir()
-                        .expect("Failed to get the current directory")
-                        .to_str()
-                        .expect("Failed to convert directory to string")
-                        .to_string(),
-                }
+        // A domain only counts as generated once everything asked for is on
+        // disk. Bundles come last, so a bundle never ends up next to a
+        // certificate or key that failed to save, and what is on disk is
+        // recorded even if a bundle fails.
+        let saved: Result<(), Box<dyn Error>> = save_pem_key_pair(
+            files,
+            leaf_certificate.clone(),
+            domain.to_string(),
+            private_key.clone(),
+            key_passphrase,
+            overwrite,
+        )
+        .and_then(|(cert_path, key_path)| {
+            record_issued(
+                &leaf_certificate,
+                ca_cert,
+                ledger_path(&cert_path),
+                ledger_path(&key_path),
             );
-        }
+            save_pem_bundles(
+                files,
+                domain,
+                bundles,
+                &leaf_certificate,
+                Some(&private_key),
+                ca_cert,
+                key_passphrase,
+                overwrite,
+            )
+        });
+        match saved {
+            Ok(()) => println!("   - \"{}\" ✅", domain),
+            Err(err) => {
+                println!("   - \"{}\" ❌", domain);
+                eprintln!("{}", err);
+                all_saved = false;
+            }
+        };
     }
     println!();
-    Ok(())
+    println!(
+        "{}: All successful certificates and their corresponding keys are saved at: {}",
+        "Note".green(),
+        match &files.output {
+            Some(a) => a.to_string(),
+            None => current_dir()
+                .expect("Failed to get the current directory")
+                .to_str()
+                .expect("Failed to convert directory to string")
+                .to_string(),
+        }
+    );
+    Ok(all_saved)
 }
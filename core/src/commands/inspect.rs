@@ -0,0 +1,292 @@
+use crate::{
+    utils::data_dir_ca_cert,
+    x509::{
+        describe::{colon_hex, format_general_names, format_name, key_description},
+        encoding::{pem_blocks, read_pkcs12, read_private_key, Encoding},
+    },
+};
+use colored::*;
+use openssl::{
+    asn1::{Asn1Time, Asn1TimeRef, TimeDiff},
+    error::ErrorStack,
+    hash::MessageDigest,
+    pkey::{HasPublic, PKey, PKeyRef, Private, Public},
+    x509::{X509Req, X509},
+};
+use std::{error::Error, fs, io};
+
+const PEM_LABELS: [&str; 8] = [
+    "CERTIFICATE",
+    "CERTIFICATE REQUEST",
+    "NEW CERTIFICATE REQUEST",
+    "PRIVATE KEY",
+    "RSA PRIVATE KEY",
+    "EC PRIVATE KEY",
+    "ENCRYPTED PRIVATE KEY",
+    "PUBLIC KEY",
+];
+
+enum Item {
+    Certificate(X509),
+    Request(X509Req),
+    PrivateKey(PKey<Private>),
+    PublicKey(PKey<Public>),
+}
+
+pub fn inspect(files: Vec<String>) -> Result<bool, Box<dyn Error>> {
+    let ca_cert: Option<X509> = data_dir_ca_cert();
+    let mut all_read: bool = true;
+    for file in &files {
+        println!();
+        let items: Vec<Item> = match read_items(file) {
+            Ok(items) if !items.is_empty() => {
+                println!("Reading {} ✅", file);
+                items
+            }
+            Ok(_) => {
+                println!("Reading {} ❌", file);
+                eprintln!(
+                    "{}: No certificates, requests or keys found in {}",
+                    "Error".red(),
+                    file
+                );
+                all_read = false;
+                continue;
+            }
+            Err(err) => {
+                println!("Reading {} ❌", file);
+                match err.downcast_ref::<io::Error>() {
+                    Some(err) => eprintln!("{}: Reading {}: {}", "Error".red(), file, err),
+                    None => eprintln!("{}", err.to_string().trim_end()),
+                }
+                all_read = false;
+                continue;
+            }
+        };
+        let certificates: Vec<&X509> = items
+            .iter()
+            .filter_map(|item: &Item| match item {
+                Item::Certificate(cert) => Some(cert),
+                _ => None,
+            })
+            .collect();
+        for item in &items {
+            println!();
+            let lines: Vec<String> = match item {
+                Item::Certificate(cert) => certificate_lines(cert, ca_cert.as_ref())?,
+                Item::Request(req) => request_lines(req)?,
+                Item::PrivateKey(key) => private_key_lines(key, &certificates, ca_cert.as_ref())?,
+                Item::PublicKey(key) => vec!["Public Key".bold().to_string(), key_type_line(key)],
+            };
+            print_lines(&lines);
+        }
+    }
+    println!();
+    Ok(all_read)
+}
+
+fn read_items(file: &str) -> Result<Vec<Item>, Box<dyn Error>> {
+    let data: Vec<u8> = fs::read(file)?;
+    let mut items: Vec<Item> = Vec::new();
+    match Encoding::detect(&data) {
+        Encoding::Pem => {
+            for block in pem_blocks(&data, &PEM_LABELS) {
+                items.push(read_pem_block(block, file)?);
+            }
+        }
+        Encoding::Pkcs12 => {
+            let parsed = read_pkcs12(&data, file)?;
+            if let Some(cert) = parsed.cert {
+                items.push(Item::Certificate(cert));
+            }
+            if let Some(chain) = parsed.ca {
+                items.extend(chain.into_iter().map(Item::Certificate));
+            }
+            if let Some(key) = parsed.pkey {
+                items.push(Item::PrivateKey(key));
+            }
+        }
+        Encoding::Der => {
+            let item: Item = if let Ok(cert) = X509::from_der(&data) {
+                Item::Certificate(cert)
+            } else if let Ok(req) = X509Req::from_der(&data) {
+                Item::Request(req)
+            } else if let Ok(key) = PKey::public_key_from_der(&data) {
+                Item::PublicKey(key)
+            } else {
+                Item::PrivateKey(read_private_key(&data, file)?)
+            };
+            items.push(item);
+        }
+    }
+    Ok(items)
+}
+
+fn read_pem_block(block: &[u8], file: &str) -> Result<Item, Box<dyn Error>> {
+    let item: Item = if block.starts_with(b"-----BEGIN CERTIFICATE-----") {
+        Item::Certificate(X509::from_pem(block)?)
+    } else if block.starts_with(b"-----BEGIN CERTIFICATE REQUEST-----")
+        || block.starts_with(b"-----BEGIN NEW CERTIFICATE REQUEST-----")
+    {
+        Item::Request(X509Req::from_pem(block)?)
+    } else if block.starts_with(b"-----BEGIN PUBLIC KEY-----") {
+        Item::PublicKey(PKey::public_key_from_pem(block)?)
+    } else {
+        Item::PrivateKey(read_private_key(block, file)?)
+    };
+    Ok(item)
+}
+
+pub fn print_certificate(cert: &X509, ca_cert: Option<&X509>) -> Result<(), ErrorStack> {
+    print_lines(&certificate_lines(cert, ca_cert)?);
+    Ok(())
+}
+
+fn print_lines(lines: &[String]) {
+    for line in lines {
+        println!("{}", line);
+    }
+}
+
+pub fn certificate_lines(cert: &X509, ca_cert: Option<&X509>) -> Result<Vec<String>, ErrorStack> {
+    let mut lines: Vec<String> = vec![
+        "Certificate".bold().to_string(),
+        format!("   Subject      : {}", format_name(cert.subject_name())),
+        format!("   Issuer       : {}", format_name(cert.issuer_name())),
+        format!(
+            "   Serial       : {}",
+            colon_hex(&cert.serial_number().to_bn()?.to_vec())
+        ),
+        format!("   Not Before   : {}", cert.not_before()),
+        format!(
+            "   Not After    : {} ({})",
+            cert.not_after(),
+            validity(cert.not_before(), cert.not_after())?
+        ),
+    ];
+    if let Some(names) = cert.subject_alt_names() {
+        lines.push(format!(
+            "   SANs         : {}",
+            format_general_names(&names)
+        ));
+    }
+    lines.push(key_type_line(cert.public_key()?.as_ref()));
+    lines.extend(extension_lines(&String::from_utf8_lossy(&cert.to_text()?)));
+    lines.push(format!(
+        "   SHA-1        : {}",
+        colon_hex(&cert.digest(MessageDigest::sha1())?)
+    ));
+    lines.push(format!(
+        "   SHA-256      : {}",
+        colon_hex(&cert.digest(MessageDigest::sha256())?)
+    ));
+    lines.push(
+        match ca_cert {
+            Some(ca_cert) if cert.to_der()? == ca_cert.to_der()? => {
+                "   Vanish CA    : This is the active Vanish CA ✅"
+            }
+            Some(ca_cert) if issued_by(cert, ca_cert)? => {
+                "   Vanish CA    : Issued by the active Vanish CA ✅"
+            }
+            Some(_) => "   Vanish CA    : Not issued by the active Vanish CA ❌",
+            None => "   Vanish CA    : No active Vanish CA found",
+        }
+        .to_string(),
+    );
+    Ok(lines)
+}
+
+fn request_lines(req: &X509Req) -> Result<Vec<String>, ErrorStack> {
+    let public_key: PKey<Public> = req.public_key()?;
+    let signature_valid: bool = req.verify(&public_key)?;
+    let mut lines: Vec<String> = vec![
+        "Certificate Signing Request".bold().to_string(),
+        format!("   Subject      : {}", format_name(req.subject_name())),
+        key_type_line(public_key.as_ref()),
+    ];
+    lines.extend(extension_lines(&String::from_utf8_lossy(&req.to_text()?)));
+    lines.push(format!(
+        "   Signature    : {}",
+        if signature_valid {
+            "Valid ✅"
+        } else {
+            "Invalid ❌"
+        }
+    ));
+    Ok(lines)
+}
+
+fn private_key_lines(
+    key: &PKey<Private>,
+    certificates: &[&X509],
+    ca_cert: Option<&X509>,
+) -> Result<Vec<String>, ErrorStack> {
+    let mut lines: Vec<String> = vec![
+        "Private Key".bold().to_string(),
+        key_type_line(key.as_ref()),
+    ];
+    for cert in certificates {
+        if cert.public_key()?.public_eq(key) {
+            lines.push(format!(
+                "   Matches      : {} ✅",
+                format_name(cert.subject_name())
+            ));
+        }
+    }
+    if let Some(ca_cert) = ca_cert {
+        if ca_cert.public_key()?.public_eq(key) {
+            lines.push("   Vanish CA    : This is the key of the active Vanish CA ✅".to_string());
+        }
+    }
+    Ok(lines)
+}
+
+fn key_type_line<T: HasPublic>(key: &PKeyRef<T>) -> String {
+    format!("   Key          : {}", key_description(key))
+}
+
+// OpenSSL already knows how to describe every extension, so reuse its text
+// dump rather than decoding each one by hand
+fn extension_lines(text: &str) -> Vec<String> {
+    let mut lines = text
+        .lines()
+        .skip_while(|line: &&str| {
+            !line.contains("X509v3 extensions:") && !line.contains("Requested Extensions:")
+        })
+        .skip(1)
+        .take_while(|line: &&str| !line.trim_start().starts_with("Signature Algorithm"))
+        .peekable();
+    let Some(first) = lines.peek() else {
+        return Vec::new();
+    };
+    let base_indent: usize = first.len() - first.trim_start().len();
+    let mut extensions: Vec<String> = vec!["   Extensions   :".to_string()];
+    for line in lines {
+        let indent: usize = line.len() - line.trim_start().len();
+        let depth: usize = if indent > base_indent { 2 } else { 1 };
+        extensions.push(format!("{}{}", "      ".repeat(depth), line.trim()));
+    }
+    extensions
+}
+
+fn issued_by(cert: &X509, ca_cert: &X509) -> Result<bool, ErrorStack> {
+    Ok(ca_cert.issued(cert) == openssl::x509::X509VerifyResult::OK
+        && cert.verify(ca_cert.public_key()?.as_ref())?)
+}
+
+fn validity(not_before: &Asn1TimeRef, not_after: &Asn1TimeRef) -> Result<String, ErrorStack> {
+    let now: Asn1Time = Asn1Time::days_from_now(0)?;
+    if now.compare(not_before)? == std::cmp::Ordering::Less {
+        return Ok("not yet valid".yellow().to_string());
+    }
+    let remaining: TimeDiff = now.diff(not_after)?;
+    if remaining.days < 0 || (remaining.days == 0 && remaining.secs < 0) {
+        Ok(format!("expired {} days ago", -remaining.days)
+            .red()
+            .to_string())
+    } else {
+        Ok(format!("valid for {} more days", remaining.days)
+            .green()
+            .to_string())
+    }
+}
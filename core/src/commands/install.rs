@@ -0,0 +1,10 @@
+use super::utils::{install_trust, load_trust_ca};
+use openssl::x509::X509;
+use std::error::Error;
+
+// Only trusts a CA, without creating one or issuing anything
+pub fn install(ca: Option<String>) -> Result<bool, Box<dyn Error>> {
+    println!();
+    let ca_cert: X509 = load_trust_ca(&ca)?;
+    install_trust(&ca_cert)
+}
@@ -0,0 +1,140 @@
+use super::verify::host_matches;
+use crate::{
+    ca_store::{ca_dir, ca_exists},
+    ledger::{DiskStatus, Ledger, LedgerEntry},
+    utils::{certificate_fingerprint, unix_now},
+    x509::encoding::read_certificate,
+};
+use colored::*;
+use openssl::x509::X509;
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const EXPIRY_WARNING_DAYS: i64 = 30;
+
+pub fn list(
+    domain: Option<String>,
+    expiring: Option<i64>,
+    ca: Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    let ledger: Ledger = Ledger::load()?;
+    let ca_fingerprint: Option<String> = match &ca {
+        Some(ca) => Some(ca_fingerprint_filter(ca)?),
+        None => None,
+    };
+    let now: i64 = unix_now();
+    let mut entries: Vec<(&LedgerEntry, Option<i64>)> = ledger
+        .certificates
+        .iter()
+        .map(|entry: &LedgerEntry| (entry, entry.days_left(now)))
+        .filter(|(entry, days_left)| {
+            domain
+                .as_deref()
+                .is_none_or(|domain: &str| covers_domain(entry, domain))
+                && expiring.is_none_or(|days: i64| days_left.is_some_and(|left: i64| left <= days))
+                && ca_fingerprint
+                    .as_deref()
+                    .is_none_or(|fingerprint: &str| entry.ca_fingerprint.starts_with(fingerprint))
+        })
+        .collect();
+    // Entries of unknown expiry sort first, they need a look the most
+    entries.sort_by_key(|(_, days_left)| *days_left);
+
+    println!();
+    for (entry, days_left) in &entries {
+        print_entry(entry, *days_left);
+        println!();
+    }
+    println!(
+        "{}: Listed {} of the {} certificates issued by Vanish",
+        "Info".blue(),
+        entries.len(),
+        ledger.certificates.len()
+    );
+    println!();
+    Ok(())
+}
+
+fn print_entry(entry: &LedgerEntry, days_left: Option<i64>) {
+    println!("{}", entry.names().bold());
+    println!("   Serial       : {}", entry.serial);
+    match &entry.cert_path {
+        Some(cert_path) => println!(
+            "   Certificate  : {} {}",
+            cert_path,
+            match entry.disk_status(cert_path) {
+                DiskStatus::Matches => "✅".to_string(),
+                DiskStatus::Replaced =>
+                    format!("❌ {}", "replaced by a different certificate".red()),
+                DiskStatus::Missing => format!("❌ {}", "missing".red()),
+                DiskStatus::Unreadable => format!("❌ {}", "unreadable".red()),
+            }
+        ),
+        None => println!("   Certificate  : written to standard output"),
+    }
+    if let Some(key_path) = &entry.key_path {
+        println!("   Key          : {}", key_path);
+    }
+    let expiry: ColoredString = match days_left {
+        None => "unknown expiry".red(),
+        Some(days_left) if days_left < 0 => format!("expired {} days ago", -days_left).red(),
+        Some(days_left) if days_left <= EXPIRY_WARNING_DAYS => {
+            format!("{} days left", days_left).yellow()
+        }
+        Some(days_left) => format!("{} days left", days_left).green(),
+    };
+    println!("   Expires      : {} ({})", entry.not_after, expiry);
+    if let Some(revocation) = &entry.revocation {
+        println!(
+            "   Revoked      : {} ({}) ❌",
+            revocation.revoked_at,
+            revocation.reason.to_string().red()
+        );
+    }
+    println!(
+        "   CA           : {}",
+        &entry.ca_fingerprint[..entry.ca_fingerprint.len().min(16)]
+    );
+}
+
+// A domain is covered by an exact name, a wildcard over it, or any name below it
+fn covers_domain(entry: &LedgerEntry, domain: &str) -> bool {
+    let domain: String = domain.trim_end_matches('.').to_ascii_lowercase();
+    entry.subject_alt_names.iter().any(|name: &String| {
+        let value: String = name
+            .split_once(':')
+            .map_or(name.as_str(), |(_, value)| value)
+            .to_ascii_lowercase();
+        value == domain || host_matches(&value, &domain) || value.ends_with(&format!(".{}", domain))
+    })
+}
+
+// `--ca` takes a CA certificate file or a (prefix of a) SHA-256 fingerprint
+fn ca_fingerprint_filter(ca: &str) -> Result<String, Box<dyn Error>> {
+    if Path::new(ca).is_file() {
+        let data: Vec<u8> = fs::read(ca)?;
+        let ca_cert: X509 = read_certificate(&data, ca)?;
+        return Ok(certificate_fingerprint(&ca_cert)?);
+    }
+    if ca_exists(ca) {
+        if let Some(ca_dir) = ca_dir(ca) {
+            let path: PathBuf = ca_dir.join("ca_cert.pem");
+            let data: Vec<u8> = fs::read(&path)?;
+            let ca_cert: X509 = read_certificate(&data, &path.display().to_string())?;
+            return Ok(certificate_fingerprint(&ca_cert)?);
+        }
+    }
+    let fingerprint: String = ca.replace(':', "").to_ascii_lowercase();
+    if fingerprint.is_empty() || !fingerprint.chars().all(|c: char| c.is_ascii_hexdigit()) {
+        return Err(format!(
+            "{}: `--ca` must be a CA certificate file, a CA name or a SHA-256 fingerprint, got \"{}\"",
+            "Error".red(),
+            ca
+        )
+        .into());
+    }
+    Ok(fingerprint)
+}
@@ -1,2 +1,16 @@
+pub mod acme;
+pub mod ca;
+pub mod caroot;
+pub mod crl;
 pub mod generate;
-mod utils;
+pub mod inspect;
+pub mod install;
+pub mod list;
+pub mod ocsp;
+pub mod renew;
+pub mod revoke;
+pub mod serve_api;
+pub mod sign;
+pub mod uninstall;
+pub mod utils;
+pub mod verify;
@@ -0,0 +1,195 @@
+use crate::{
+    http::{percent_decode, serve, HttpRequest, HttpResponse},
+    ledger::{Ledger, LedgerEntry},
+    utils::{
+        certificate_fingerprint, format_iso8601, get_certificates_from_data_dir, parse_iso8601,
+        previous_ca_from_data_dir, unix_now,
+    },
+    x509::{
+        ca_cert::CACert,
+        describe::{asn1_time_to_unix, format_name},
+        ocsp::{
+            error_response, CertId, CertStatus, OcspRequest, OcspResponder, INTERNAL_ERROR,
+            MALFORMED_REQUEST,
+        },
+    },
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use colored::*;
+use openssl::x509::X509;
+use std::{error::Error, net::TcpListener};
+
+// Kept short so clients and staplers pick up revocations soon after `vanish revoke`
+const NEXT_UPDATE_SECS: i64 = 3600;
+
+pub fn serve_ocsp(
+    host: String,
+    port: u16,
+    responder_cert: Option<String>,
+    responder_key: Option<String>,
+    delegate: bool,
+) -> Result<(), Box<dyn Error>> {
+    println!();
+    let Some((ca_cert, ca_key)) = get_certificates_from_data_dir() else {
+        eprintln!(
+            "{}: No CA Certificates found. Run `vanish generate` first",
+            "Error".red()
+        );
+        std::process::exit(1);
+    };
+    let responder: OcspResponder = match (responder_cert, responder_key) {
+        (Some(cert_path), Some(key_path)) => {
+            let (cert, key) = CACert::load_ca_cert(&cert_path, &key_path)?;
+            OcspResponder::delegated(cert, key, &ca_cert)?
+        }
+        _ if delegate => OcspResponder::generate_delegated(&ca_cert, &ca_key)?,
+        _ => OcspResponder::ca(ca_cert.clone(), ca_key),
+    };
+    let mut authorities: Vec<Authority> = vec![Authority {
+        ca_fingerprint: certificate_fingerprint(&ca_cert)?,
+        ca_cert: ca_cert.clone(),
+        responder,
+    }];
+    // Certificates from before a rotation are answered for by the root that
+    // issued them, until `vanish ca rotate --finish`
+    if let Some((previous_cert, previous_key)) = previous_ca_from_data_dir()? {
+        let responder: OcspResponder = if delegate {
+            OcspResponder::generate_delegated(&previous_cert, &previous_key)?
+        } else {
+            OcspResponder::ca(previous_cert.clone(), previous_key)
+        };
+        authorities.push(Authority {
+            ca_fingerprint: certificate_fingerprint(&previous_cert)?,
+            ca_cert: previous_cert,
+            responder,
+        });
+    }
+    let listener: TcpListener = TcpListener::bind((host.as_str(), port))?;
+
+    println!(
+        "OCSP responder for \"{}\" listening at http://{}:{} 👂",
+        format_name(ca_cert.subject_name()),
+        host,
+        port
+    );
+    println!();
+    let responder: &OcspResponder = &authorities[0].responder;
+    if responder.cert.as_ref() == ca_cert.as_ref() {
+        println!("{}: Responses are signed with the CA key", "Note".green());
+    } else {
+        println!(
+            "{}: Responses are signed by \"{}\", valid until {}",
+            "Note".green(),
+            format_name(responder.cert.subject_name()),
+            format_iso8601(asn1_time_to_unix(responder.cert.not_after())?)
+        );
+    }
+    if let Some(previous) = authorities.get(1) {
+        println!(
+            "{}: Certificates issued before the rotation are answered for by the previous root \"{}\"",
+            "Note".green(),
+            format_name(previous.ca_cert.subject_name())
+        );
+    }
+    println!(
+        "{}: Pass `--ocsp-url http://{}:{}` to `vanish generate` or `vanish sign` to point certificates here",
+        "Note".green(),
+        host,
+        port
+    );
+    println!();
+
+    serve(listener, None, |request: &HttpRequest| {
+        answer(request, &authorities)
+    });
+    Ok(())
+}
+
+// A CA the responder answers for, with whatever signs its responses
+struct Authority {
+    ca_cert: X509,
+    ca_fingerprint: String,
+    responder: OcspResponder,
+}
+
+fn answer(request: &HttpRequest, authorities: &[Authority]) -> HttpResponse {
+    // RFC 6960 appendix A: DER in a POST body, or base64 in the GET path
+    let der: Option<Vec<u8>> = match request.method.as_str() {
+        "POST" if request.header("content-type") != Some("application/ocsp-request") => {
+            return HttpResponse::text(415, "Expected Content-Type: application/ocsp-request")
+        }
+        "POST" => Some(request.body.clone()),
+        "GET" => percent_decode(request.path.trim_start_matches('/'))
+            .and_then(|encoded: Vec<u8>| STANDARD.decode(encoded).ok()),
+        _ => {
+            return HttpResponse::text(405, "OCSP requests are sent with GET or POST")
+                .with_header("Allow", "GET, POST")
+        }
+    };
+    let Some(ocsp_request) = der.and_then(|der: Vec<u8>| OcspRequest::parse(&der).ok()) else {
+        println!("   - malformed request ❌");
+        return ocsp_response(error_response(MALFORMED_REQUEST));
+    };
+    match respond(&ocsp_request, authorities) {
+        Ok(der) => ocsp_response(der),
+        Err(err) => {
+            eprintln!("{}", err);
+            ocsp_response(error_response(INTERNAL_ERROR))
+        }
+    }
+}
+
+fn respond(request: &OcspRequest, authorities: &[Authority]) -> Result<Vec<u8>, Box<dyn Error>> {
+    // A response has a single signer, so the CA of the first known
+    // certificate answers and anything issued by another one is unknown
+    let authority: &Authority = request
+        .cert_ids
+        .iter()
+        .find_map(|cert_id: &CertId| {
+            authorities.iter().find(|authority: &&Authority| {
+                cert_id.issued_by(&authority.ca_cert).unwrap_or(false)
+            })
+        })
+        .unwrap_or(&authorities[0]);
+    // Reloaded every time so `vanish revoke` takes effect without a restart
+    let ledger: Ledger = Ledger::load()?;
+    let now: i64 = unix_now();
+    let mut statuses: Vec<CertStatus> = Vec::new();
+    for cert_id in &request.cert_ids {
+        let entry: Option<&LedgerEntry> = if cert_id.issued_by(&authority.ca_cert)? {
+            ledger.certificates.iter().find(|entry: &&LedgerEntry| {
+                entry.ca_fingerprint == authority.ca_fingerprint
+                    && entry.serial.eq_ignore_ascii_case(&cert_id.serial)
+            })
+        } else {
+            None
+        };
+        let status: CertStatus = match entry {
+            Some(entry) => match &entry.revocation {
+                Some(revocation) => {
+                    println!("   - \"{}\" ({}) revoked ❌", entry.names(), entry.serial);
+                    CertStatus::Revoked {
+                        revoked_at: parse_iso8601(&revocation.revoked_at).unwrap_or(now),
+                        reason: revocation.reason,
+                    }
+                }
+                None => {
+                    println!("   - \"{}\" ({}) good ✅", entry.names(), entry.serial);
+                    CertStatus::Good
+                }
+            },
+            None => {
+                println!("   - {} unknown ❔", cert_id.serial);
+                CertStatus::Unknown
+            }
+        };
+        statuses.push(status);
+    }
+    Ok(authority
+        .responder
+        .respond(request, &statuses, now, now + NEXT_UPDATE_SECS)?)
+}
+
+fn ocsp_response(der: Vec<u8>) -> HttpResponse {
+    HttpResponse::new(200, "application/ocsp-response", der)
+}
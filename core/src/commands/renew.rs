@@ -0,0 +1,224 @@
+use super::utils::create_distinguished_name;
+use crate::{
+    ledger::{record_issued, DiskStatus, Ledger, LedgerEntry},
+    utils::{
+        certificate_fingerprint, data_dir_ca_cert, generate_private_key_like,
+        get_certificates_from_data_dir, read_key_passphrase, unix_now,
+    },
+    x509::{
+        ca_cert::CACert,
+        describe::serial_hex,
+        encoding::{is_encrypted_pem, private_key_to_pem},
+        file::{write_file, Overwrite, KEY_FILE_MODE},
+        leaf_cert::LeafCert,
+    },
+};
+use colored::*;
+use openssl::{
+    pkey::{PKey, Private},
+    x509::X509,
+};
+use std::{
+    collections::HashSet,
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+struct Renewal {
+    cert_path: String,
+    key_path: Option<String>,
+}
+
+pub fn renew(
+    files: Vec<String>,
+    expiring: Option<i64>,
+    reuse_key: bool,
+    key_passphrase: Option<String>,
+) -> Result<bool, Box<dyn Error>> {
+    println!();
+    let ledger: Ledger = Ledger::load()?;
+    let mut renewals: Vec<Renewal> = Vec::new();
+    // Canonical certificate paths, so one certificate named twice is renewed once
+    let mut queued: HashSet<String> = HashSet::new();
+    for file in &files {
+        let cert: X509 = CACert::load_cert(file)?;
+        let key_path: Option<String> = match ledger.find_serial(&serial_hex(&cert)?) {
+            Some(entry) => entry.key_path.clone(),
+            None => sibling_key_path(file),
+        };
+        // Kept absolute so the ledger entry matches the ones `generate` writes
+        let cert_path: String = fs::canonicalize(file)?.display().to_string();
+        if queued.insert(cert_path.clone()) {
+            renewals.push(Renewal {
+                cert_path,
+                key_path,
+            });
+        }
+    }
+    if let Some(days) = expiring {
+        let now: i64 = unix_now();
+        // Leaves of other CAs are renewed with that CA active, see `VANISH_CA`
+        let ca_fingerprint: Option<String> =
+            data_dir_ca_cert().and_then(|ca_cert: X509| certificate_fingerprint(&ca_cert).ok());
+        // Only unrevoked entries whose file is still the issued certificate, so
+        // a certificate that was renewed before is not renewed again. An entry
+        // of unknown expiry is renewed too, the certificate on disk is checked.
+        for entry in ledger.certificates.iter().filter(|entry: &&LedgerEntry| {
+            entry.revocation.is_none()
+                && entry.days_left(now).is_none_or(|left: i64| left <= days)
+                && Some(&entry.ca_fingerprint) == ca_fingerprint.as_ref()
+        }) {
+            let Some(cert_path) = &entry.cert_path else {
+                continue;
+            };
+            if !matches!(entry.disk_status(cert_path), DiskStatus::Matches) {
+                continue;
+            }
+            let cert_path: String = fs::canonicalize(cert_path)
+                .map(|path: PathBuf| path.display().to_string())
+                .unwrap_or_else(|_| cert_path.clone());
+            if queued.insert(cert_path.clone()) {
+                renewals.push(Renewal {
+                    cert_path,
+                    key_path: entry.key_path.clone(),
+                });
+            }
+        }
+    }
+
+    if renewals.is_empty() {
+        println!(
+            "{}: No certificates to renew{}",
+            "Note".green(),
+            match expiring {
+                Some(days) => format!(" that expire within {} days", days),
+                None => String::new(),
+            }
+        );
+        println!();
+        return Ok(true);
+    }
+
+    // A key that was encrypted stays encrypted, so ask for a passphrase
+    // rather than write the new one in the clear
+    let mut key_passphrase: Option<String> = key_passphrase;
+    if key_passphrase.is_none() && !reuse_key {
+        let encrypted: Option<&String> = renewals
+            .iter()
+            .filter_map(|renewal: &Renewal| renewal.key_path.as_ref())
+            .find(|key_path: &&String| {
+                fs::read(key_path).is_ok_and(|key_data: Vec<u8>| is_encrypted_pem(&key_data))
+            });
+        if let Some(key_path) = encrypted {
+            println!(
+                "{}: {} is encrypted, so the renewed keys are encrypted as well",
+                "Note".green(),
+                key_path
+            );
+            key_passphrase = Some(read_key_passphrase(&None).map_err(|err: io::Error| {
+                format!(
+                    "{}: Reading passphrase for private keys: {}",
+                    "Error".red(),
+                    err
+                )
+            })?);
+        }
+    }
+
+    let Some((ca_cert, ca_key)) = get_certificates_from_data_dir() else {
+        eprintln!(
+            "{}: No CA Certificates found. Run `vanish generate` first",
+            "Error".red()
+        );
+        return Ok(false);
+    };
+
+    println!();
+    println!("Renewed Certificate for : ");
+    let mut all_renewed: bool = true;
+    for renewal in &renewals {
+        match renew_certificate(
+            renewal,
+            &ca_cert,
+            &ca_key,
+            reuse_key,
+            key_passphrase.as_deref().map(str::as_bytes),
+        ) {
+            Ok(()) => println!("   - \"{}\" ✅", renewal.cert_path),
+            Err(err) => {
+                all_renewed = false;
+                println!("   - \"{}\" ❌", renewal.cert_path);
+                eprintln!("     {}", err.to_string().trim_end());
+            }
+        }
+    }
+    println!();
+    println!(
+        "{}: Replaced certificates are kept next to the new ones as timestamped backups",
+        "Note".green()
+    );
+    println!();
+    Ok(all_renewed)
+}
+
+fn renew_certificate(
+    renewal: &Renewal,
+    ca_cert: &X509,
+    ca_key: &PKey<Private>,
+    reuse_key: bool,
+    key_passphrase: Option<&[u8]>,
+) -> Result<(), Box<dyn Error>> {
+    let old_cert: X509 = CACert::load_cert(&renewal.cert_path)?;
+    if old_cert.verify(ca_cert.public_key()?.as_ref()).ok() != Some(true) {
+        println!(
+            "{}: {} was not issued by the active Vanish CA, the renewed certificate will be",
+            "Note".green(),
+            renewal.cert_path
+        );
+    }
+    let leaf: LeafCert = LeafCert::new(create_distinguished_name(&None, &None, &None))?;
+    let (new_cert, new_key): (X509, Option<(Vec<u8>, &String)>) = if reuse_key {
+        let new_cert: X509 =
+            leaf.renew_certificate(&old_cert, old_cert.public_key()?.as_ref(), ca_cert, ca_key)?;
+        (new_cert, None)
+    } else {
+        let Some(key_path) = &renewal.key_path else {
+            return Err(format!(
+                "{}: The private key of {} is not known to Vanish, pass `--reuse-key` to keep it",
+                "Error".red(),
+                renewal.cert_path
+            )
+            .into());
+        };
+        let private_key: PKey<Private> =
+            generate_private_key_like(old_cert.public_key()?.as_ref())?;
+        let new_cert: X509 =
+            leaf.renew_certificate(&old_cert, private_key.as_ref(), ca_cert, ca_key)?;
+        let key_pem: Vec<u8> = private_key_to_pem(&private_key, key_passphrase)?;
+        (new_cert, Some((key_pem, key_path)))
+    };
+    // The certificate goes first, and is put back if the new key can't be
+    // written, so the pair on disk always matches
+    LeafCert::save_cert(&new_cert, &renewal.cert_path, Overwrite::Backup)?;
+    if let Some((key_pem, key_path)) = new_key {
+        if let Err(err) = write_file(key_path, &key_pem, KEY_FILE_MODE, Overwrite::Backup) {
+            LeafCert::save_cert(&old_cert, &renewal.cert_path, Overwrite::Replace)?;
+            return Err(err.into());
+        }
+    }
+    record_issued(
+        &new_cert,
+        ca_cert,
+        Some(&renewal.cert_path),
+        renewal.key_path.as_deref(),
+    );
+    Ok(())
+}
+
+// Certificates from before the ledger keep their key next to them as
+// `<name>-key.pem`, the way `vanish generate` names it
+fn sibling_key_path(cert_path: &str) -> Option<String> {
+    let key_path: String = format!("{}-key.pem", cert_path.strip_suffix(".pem")?);
+    Path::new(&key_path).is_file().then_some(key_path)
+}
@@ -0,0 +1,86 @@
+use crate::{
+    ca_store::{active_ca_dir, previous_ca_cert},
+    ledger::{Ledger, LedgerEntry, LedgerLock},
+    utils::data_dir_ca_cert,
+    x509::{ca_cert::CACert, crl::RevocationReason, describe::serial_hex},
+};
+use colored::*;
+use openssl::x509::X509;
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub fn revoke(target: String, reason: RevocationReason) -> Result<bool, Box<dyn Error>> {
+    println!();
+    let _lock: LedgerLock = Ledger::lock()?;
+    let mut ledger: Ledger = Ledger::load()?;
+    let serial: String = if Path::new(&target).is_file() {
+        let cert: X509 = CACert::load_cert(&target)?;
+        let serial: String = serial_hex(&cert)?;
+        if !ledger.contains_serial(&serial) {
+            // Issued before the ledger existed, so record it now if it is ours.
+            // During a rotation that includes what the previous root issued.
+            let previous_cert: Option<X509> =
+                active_ca_dir().and_then(|ca_dir: PathBuf| previous_ca_cert(&ca_dir));
+            let mut issuer: Option<X509> = None;
+            for ca_cert in data_dir_ca_cert().into_iter().chain(previous_cert) {
+                if cert.verify(ca_cert.public_key()?.as_ref())? {
+                    issuer = Some(ca_cert);
+                    break;
+                }
+            }
+            match &issuer {
+                Some(ca_cert) => {
+                    let cert_path: String = fs::canonicalize(&target)?.display().to_string();
+                    ledger.record(LedgerEntry::new(&cert, ca_cert, Some(&cert_path), None)?)?;
+                }
+                _ => {
+                    eprintln!(
+                        "{}: {} was not issued by the active Vanish CA",
+                        "Error".red(),
+                        target
+                    );
+                    return Ok(false);
+                }
+            }
+        }
+        serial
+    } else {
+        target.replace(':', "")
+    };
+
+    let Some(entry) = ledger.find_serial_mut(&serial) else {
+        eprintln!(
+            "{}: No certificate with serial {} in the ledger, run `vanish list` to see issued certificates",
+            "Error".red(),
+            serial
+        );
+        return Ok(false);
+    };
+    if let Some(revocation) = &entry.revocation {
+        eprintln!(
+            "{}: {} was already revoked on {} ({})",
+            "Error".red(),
+            entry.names(),
+            revocation.revoked_at,
+            revocation.reason
+        );
+        return Ok(false);
+    }
+    entry.revoke(reason);
+    let names: String = entry.names();
+    let serial: String = entry.serial.clone();
+    ledger.save()?;
+
+    println!("Revoked Certificate for : ");
+    println!("   - \"{}\" ({}, {}) ✅", names, serial, reason);
+    println!();
+    println!(
+        "{}: Run `vanish crl` to publish the revocation in a new CRL",
+        "Note".green()
+    );
+    println!();
+    Ok(true)
+}
@@ -0,0 +1,359 @@
+use super::utils::{
+    create_distinguished_name, issue_for_domain, serving_certificate, sign_request, RevocationUrls,
+};
+use crate::{
+    ca_store::issuing_chain,
+    http::{serve, tls_acceptor, HttpRequest, HttpResponse},
+    ledger::record_issued,
+    utils::{format_iso8601, get_certificates_from_data_dir},
+    x509::{
+        ca_req::CAReq,
+        describe::{asn1_time_to_unix, format_name, serial_hex},
+        distinguished_name::DistinguishedName,
+        encoding::{private_key_to_pem, read_certificate},
+    },
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use colored::*;
+use openssl::{
+    memcmp,
+    pkey::{PKey, Private},
+    rand::rand_bytes,
+    ssl::SslAcceptor,
+    x509::{X509Req, X509},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{env, error::Error, fs, net::TcpListener};
+
+const API_TOKEN_ENV: &str = "VANISH_API_TOKEN";
+// Every domain costs an RSA key, so one request can't hold up the others for long
+const MAX_DOMAINS: usize = 100;
+
+// The body of `POST /certificates`, mirroring the flags of `vanish generate`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CertificateSpec {
+    domains: Vec<String>,
+    common_name: Option<String>,
+    country: Option<String>,
+    state: Option<String>,
+}
+
+#[derive(Serialize)]
+struct IssuedCertificate {
+    domain: String,
+    serial: String,
+    not_after: String,
+    certificate: String,
+    private_key: String,
+}
+
+pub(crate) struct ApiServer {
+    ca_cert: X509,
+    ca_key: PKey<Private>,
+    revocation_urls: RevocationUrls,
+    token: Option<String>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn serve_api(
+    host: String,
+    port: u16,
+    tls_name: Option<String>,
+    token_file: Option<String>,
+    client_ca: Option<String>,
+    plain_http: bool,
+    crl_url: Option<String>,
+    ocsp_url: Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    println!();
+    let Some((ca_cert, ca_key)) = get_certificates_from_data_dir() else {
+        eprintln!(
+            "{}: No CA Certificates found. Run `vanish generate` first",
+            "Error".red()
+        );
+        std::process::exit(1);
+    };
+    let revocation_urls: RevocationUrls = RevocationUrls::resolve(crl_url, ocsp_url)?;
+    let client_ca: Option<X509> = match client_ca {
+        Some(path) => Some(read_client_ca(&path)?),
+        None => None,
+    };
+    let (token, generated_token): (Option<String>, bool) = match (token_file, &client_ca) {
+        (Some(path), _) => (Some(read_token(&path)?), false),
+        (None, client_ca) => match env::var(API_TOKEN_ENV) {
+            Ok(token) if !token.is_empty() => (Some(token), false),
+            // mTLS alone is enough, otherwise nobody should get in without a token
+            _ if client_ca.is_some() => (None, false),
+            _ => (Some(random_token()?), true),
+        },
+    };
+    let acceptor: Option<SslAcceptor> = if plain_http {
+        None
+    } else {
+        let (tls_cert, tls_key) =
+            serving_certificate(tls_name.as_deref().unwrap_or(&host), &ca_cert, &ca_key)?;
+        Some(tls_acceptor(
+            &tls_cert,
+            &tls_key,
+            &ca_cert,
+            client_ca.as_ref(),
+        )?)
+    };
+    let listener: TcpListener = TcpListener::bind((host.as_str(), port))?;
+
+    println!(
+        "Issuance API for \"{}\" listening at {}://{}:{} 👂",
+        format_name(ca_cert.subject_name()),
+        if plain_http { "http" } else { "https" },
+        host,
+        port
+    );
+    println!();
+    if let Some(client_ca) = &client_ca {
+        println!(
+            "{}: Clients must present a certificate issued by \"{}\"",
+            "Note".green(),
+            format_name(client_ca.subject_name())
+        );
+    }
+    match &token {
+        Some(token) if generated_token => println!(
+            "{}: Send `Authorization: Bearer {}` with every request",
+            "Note".green(),
+            token
+        ),
+        Some(_) => println!(
+            "{}: Send `Authorization: Bearer <token>` with every request",
+            "Note".green()
+        ),
+        None => {}
+    }
+    if plain_http {
+        println!(
+            "{}: Private keys are sent without TLS, only use `--plain-http` on a private network",
+            "Warning".yellow()
+        );
+    }
+    println!(
+        "{}: GET /ca, POST /certificates with {{\"domains\": [...]}}, POST /sign with a CSR",
+        "Note".green()
+    );
+    println!();
+
+    let server: ApiServer = ApiServer::new(ca_cert, ca_key, revocation_urls, token);
+    serve(listener, acceptor.as_ref(), |request: &HttpRequest| {
+        server.handle(request)
+    });
+    Ok(())
+}
+
+impl ApiServer {
+    pub(crate) fn new(
+        ca_cert: X509,
+        ca_key: PKey<Private>,
+        revocation_urls: RevocationUrls,
+        token: Option<String>,
+    ) -> ApiServer {
+        ApiServer {
+            ca_cert,
+            ca_key,
+            revocation_urls,
+            token,
+        }
+    }
+
+    pub(crate) fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        if !self.authorized(request) {
+            println!("   - {} {} unauthorized ❌", request.method, request.path);
+            return error(401, "Missing or wrong bearer token")
+                .with_header("WWW-Authenticate", "Bearer");
+        }
+        let path: &str = request.path.split('?').next().unwrap_or_default();
+        let result: Result<HttpResponse, HttpResponse> = match (request.method.as_str(), path) {
+            ("GET", "/ca") => self.ca_bundle(),
+            ("POST", "/certificates") => self.issue(request),
+            ("POST", "/sign") => self.sign(request),
+            (_, "/ca") => Err(error(405, "Use GET").with_header("Allow", "GET")),
+            (_, "/certificates" | "/sign") => {
+                Err(error(405, "Use POST").with_header("Allow", "POST"))
+            }
+            _ => Err(error(404, "No such endpoint")),
+        };
+        result.unwrap_or_else(|response: HttpResponse| response)
+    }
+
+    fn authorized(&self, request: &HttpRequest) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        match request
+            .header("authorization")
+            .and_then(|value: &str| value.strip_prefix("Bearer "))
+        {
+            Some(presented) => {
+                presented.len() == token.len() && memcmp::eq(presented.as_bytes(), token.as_bytes())
+            }
+            None => false,
+        }
+    }
+
+    fn ca_bundle(&self) -> Result<HttpResponse, HttpResponse> {
+        Ok(HttpResponse::new(
+            200,
+            "application/x-pem-file",
+            self.issuing_chain_pem()?,
+        ))
+    }
+
+    // The same chain `/sign` serves after the leaf, so clients trust what they
+    // are handed during a rotation too
+    fn issuing_chain_pem(&self) -> Result<Vec<u8>, HttpResponse> {
+        let mut chain: Vec<u8> = Vec::new();
+        for cert in issuing_chain(&self.ca_cert) {
+            chain.extend(cert.to_pem().map_err(internal)?);
+        }
+        Ok(chain)
+    }
+
+    fn issue(&self, request: &HttpRequest) -> Result<HttpResponse, HttpResponse> {
+        let spec: CertificateSpec = serde_json::from_slice(&request.body)
+            .map_err(|err| error(400, &format!("Invalid certificate spec: {}", err)))?;
+        if spec.domains.is_empty() || spec.domains.len() > MAX_DOMAINS {
+            return Err(error(
+                400,
+                &format!("Ask for between 1 and {} domains", MAX_DOMAINS),
+            ));
+        }
+        let distinguished_name: DistinguishedName =
+            create_distinguished_name(&spec.common_name, &spec.country, &spec.state);
+        // Every domain is issued before any is recorded, so a bad domain late
+        // in the list fails the request without leaving certificates in the
+        // ledger that the client never received
+        let mut issued: Vec<(X509, IssuedCertificate)> = Vec::new();
+        for domain in &spec.domains {
+            let (certificate, private_key) = issue_for_domain(
+                domain,
+                &distinguished_name,
+                &self.ca_cert,
+                &self.ca_key,
+                &self.revocation_urls,
+            )
+            .map_err(|err| {
+                println!("   - POST /certificates \"{}\" ❌", domain);
+                error(400, &err.to_string())
+            })?;
+            let details: IssuedCertificate = IssuedCertificate {
+                domain: domain.to_string(),
+                serial: serial_hex(&certificate).map_err(internal)?,
+                not_after: format_iso8601(
+                    asn1_time_to_unix(certificate.not_after()).map_err(internal)?,
+                ),
+                certificate: pem_string(certificate.to_pem().map_err(internal)?),
+                private_key: pem_string(private_key_to_pem(&private_key, None).map_err(internal)?),
+            };
+            issued.push((certificate, details));
+        }
+        let chain: String = pem_string(self.issuing_chain_pem()?);
+        let issued: Vec<IssuedCertificate> = issued
+            .into_iter()
+            .map(|(certificate, details): (X509, IssuedCertificate)| {
+                record_issued(&certificate, &self.ca_cert, None, None);
+                println!("   - POST /certificates \"{}\" ✅", details.domain);
+                details
+            })
+            .collect();
+        let body: Value = json!({
+            "certificates": issued,
+            "ca_certificate": chain,
+        });
+        Ok(HttpResponse::new(
+            201,
+            "application/json",
+            body.to_string().into(),
+        ))
+    }
+
+    // The posted CSR keeps its subject and SANs, as with `vanish sign`
+    fn sign(&self, request: &HttpRequest) -> Result<HttpResponse, HttpResponse> {
+        let csr: X509Req = match CAReq::parse_csrs(&request.body) {
+            Ok(mut csrs) if csrs.len() == 1 => csrs.remove(0),
+            Ok(csrs) if csrs.is_empty() => return Err(error(400, "No signing request found")),
+            Ok(_) => return Err(error(400, "Post one signing request at a time")),
+            Err(err) => return Err(error(400, &format!("Invalid signing request: {}", err))),
+        };
+        let certificate: X509 =
+            sign_request(&csr, &self.ca_cert, &self.ca_key, &self.revocation_urls)
+                .map_err(|err| error(400, &err.to_string()))?;
+        record_issued(&certificate, &self.ca_cert, None, None);
+        let names: String = CAReq::requested_identifiers(&csr)
+            .map(|names: Vec<String>| names.join(", "))
+            .unwrap_or_default();
+        println!("   - POST /sign \"{}\" ✅", names);
+        let mut chain: Vec<u8> = certificate.to_pem().map_err(internal)?;
+        chain.extend(self.issuing_chain_pem()?);
+        Ok(HttpResponse::new(
+            201,
+            "application/pem-certificate-chain",
+            chain,
+        ))
+    }
+}
+
+fn read_client_ca(path: &str) -> Result<X509, Box<dyn Error>> {
+    let data: Vec<u8> = match fs::read(path) {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("Reading Client CA at {} ❌", path);
+            return Err(err.into());
+        }
+    };
+    match read_certificate(&data, path) {
+        Ok(cert) => {
+            println!("Reading Client CA at {} ✅", path);
+            Ok(cert)
+        }
+        Err(err) => {
+            eprintln!("Reading Client CA at {} ❌", path);
+            Err(err.into())
+        }
+    }
+}
+
+fn read_token(path: &str) -> Result<String, Box<dyn Error>> {
+    let contents: String = fs::read_to_string(path)?;
+    let token: String = contents
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    if token.is_empty() {
+        return Err(format!("{}: The API token in {} is empty", "Error".red(), path).into());
+    }
+    Ok(token)
+}
+
+fn random_token() -> Result<String, Box<dyn Error>> {
+    let mut token: [u8; 24] = [0; 24];
+    rand_bytes(&mut token)?;
+    Ok(URL_SAFE_NO_PAD.encode(token))
+}
+
+fn pem_string(pem: Vec<u8>) -> String {
+    String::from_utf8_lossy(&pem).into_owned()
+}
+
+fn error(status: u16, message: &str) -> HttpResponse {
+    HttpResponse::new(
+        status,
+        "application/json",
+        json!({ "error": message }).to_string().into(),
+    )
+}
+
+fn internal(err: impl std::fmt::Display) -> HttpResponse {
+    eprintln!("{}", err);
+    error(500, "Internal error, see the server output")
+}
@@ -0,0 +1,137 @@
+use super::utils::{
+    resolve_output_dir, sign_request, unique_certificate_file_name, RevocationUrls,
+};
+use crate::{
+    ledger::record_issued,
+    utils::get_certificates_from_data_dir,
+    x509::{ca_cert::CACert, ca_req::CAReq, file::Overwrite, leaf_cert::LeafCert},
+};
+use colored::*;
+use openssl::{
+    pkey::{PKey, Private},
+    x509::{X509Req, X509},
+};
+use std::{
+    collections::HashSet,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub fn sign(
+    csr_dir: String,
+    output: Option<String>,
+    certfile: Option<String>,
+    keyfile: Option<String>,
+    force: bool,
+    crl_url: Option<String>,
+    ocsp_url: Option<String>,
+) -> Result<bool, Box<dyn Error>> {
+    println!();
+    let revocation_urls: RevocationUrls = RevocationUrls::resolve(crl_url, ocsp_url)?;
+    let (ca_cert, ca_key) = match certfile {
+        Some(certfile) => CACert::load_ca_cert_or_bundle(&certfile, keyfile.as_deref())?,
+        None => match get_certificates_from_data_dir() {
+            Some(cert_key) => cert_key,
+            None => {
+                eprintln!(
+                    "{}: No CA Certificates found. Run `vanish generate` first or pass `--certfile`",
+                    "Error".red()
+                );
+                return Ok(false);
+            }
+        },
+    };
+
+    let mut requests: Vec<PathBuf> = Vec::new();
+    for entry in fs::read_dir(&csr_dir)? {
+        let path: PathBuf = entry?.path();
+        let hidden: bool = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name: &str| name.starts_with('.'))
+            .unwrap_or(true);
+        if path.is_file() && !hidden {
+            requests.push(path);
+        }
+    }
+    requests.sort();
+
+    let output_path: PathBuf = resolve_output_dir(&output)?;
+    let mut used_names: HashSet<String> = HashSet::new();
+    let mut results: Vec<(PathBuf, Result<Vec<String>, String>)> = Vec::new();
+    for request in requests {
+        let result: Result<Vec<String>, String> = sign_file(
+            &request,
+            &output_path,
+            &ca_cert,
+            &ca_key,
+            &mut used_names,
+            Overwrite::from(force),
+            &revocation_urls,
+        );
+        results.push((request, result));
+    }
+
+    println!();
+    println!("Signed Certificate Requests from {} :", csr_dir);
+    let mut all_signed: bool = true;
+    for (request, result) in &results {
+        match result {
+            Ok(names) => println!("   - \"{}\" ✅ -> {}", request.display(), names.join(", ")),
+            Err(err) => {
+                all_signed = false;
+                println!("   - \"{}\" ❌", request.display());
+                eprintln!("     {}", err.trim_end());
+            }
+        }
+    }
+    println!();
+    if results.is_empty() {
+        eprintln!(
+            "{}: No files found in {} to sign",
+            "Warning".yellow(),
+            csr_dir
+        );
+    } else {
+        println!(
+            "{}: {} of {} file(s) signed, certificates are saved at: {}",
+            "Note".green(),
+            results.iter().filter(|(_, result)| result.is_ok()).count(),
+            results.len(),
+            output_path.display()
+        );
+    }
+    println!();
+    Ok(all_signed)
+}
+
+fn sign_file(
+    request: &Path,
+    output_path: &Path,
+    ca_cert: &X509,
+    ca_key: &PKey<Private>,
+    used_names: &mut HashSet<String>,
+    overwrite: Overwrite,
+    revocation_urls: &RevocationUrls,
+) -> Result<Vec<String>, String> {
+    let path: &str = request
+        .to_str()
+        .ok_or_else(|| format!("Path {:?} is not valid UTF-8", request))?;
+    let csrs: Vec<X509Req> = CAReq::read_csrs_from_file(path).map_err(|err| err.to_string())?;
+    let mut names: Vec<String> = Vec::new();
+    for csr in &csrs {
+        let leaf_certificate: X509 =
+            sign_request(csr, ca_cert, ca_key, revocation_urls).map_err(|err| err.to_string())?;
+        let name: String = unique_certificate_file_name(&leaf_certificate, used_names);
+        let file_name: PathBuf = output_path.join(format!("{}.pem", name));
+        let file_name_str: &str = file_name
+            .to_str()
+            .ok_or_else(|| format!("Path {:?} is not valid UTF-8", file_name))?;
+        LeafCert::save_cert(&leaf_certificate, file_name_str, overwrite)
+            .map_err(|err| err.to_string())?;
+        record_issued(&leaf_certificate, ca_cert, Some(file_name_str), None);
+        names.push(format!("{}.pem", name));
+    }
+    Ok(names)
+}
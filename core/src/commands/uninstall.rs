@@ -0,0 +1,55 @@
+use super::utils::{ca_pem_file, load_trust_ca};
+use crate::{
+    trust_stores::{firefox::FirefoxTrustStore, nss::NSSValue, nss_profile::NSSProfile, CAValue},
+    utils::ca_unique_name,
+    x509::describe::format_name,
+};
+use colored::*;
+use openssl::x509::X509;
+use std::{error::Error, path::PathBuf};
+use tempfile::NamedTempFile;
+
+// Removes the active or given CA from the system, NSS and Firefox, reporting every store
+pub fn uninstall(ca: Option<String>) -> Result<bool, Box<dyn Error>> {
+    println!();
+    let ca_cert: X509 = load_trust_ca(&ca)?;
+    let ca_unique_name: String = ca_unique_name(&ca_cert)?;
+    let ca_file: NamedTempFile = ca_pem_file(&ca_cert)?;
+    let caroot: String = ca_file
+        .path()
+        .to_str()
+        .ok_or("Failed to convert the CA file path to string")?
+        .to_string();
+
+    println!(
+        "Removing \"{}\" from trust stores :",
+        format_name(ca_cert.subject_name())
+    );
+    let ca_value_object: CAValue = CAValue {
+        ca_uniques_name: ca_unique_name.clone(),
+        certificate: ca_cert,
+    };
+    let mut success: bool = ca_value_object.uninstall_certificate()?;
+
+    let nss_profile_object: NSSProfile = NSSProfile::new();
+    let mkcert: NSSValue =
+        NSSValue::new(nss_profile_object, ca_unique_name.clone(), caroot.clone());
+    success &= mkcert.uninstall_nss();
+
+    let firefox_trust_store_object: FirefoxTrustStore =
+        FirefoxTrustStore::new(ca_unique_name, caroot)?;
+    let paths_with_trust_stores: Vec<PathBuf> =
+        FirefoxTrustStore::find_cert_directories(&firefox_trust_store_object)?;
+    success &= firefox_trust_store_object.uninstall_firefox_certificates(paths_with_trust_stores);
+
+    println!();
+    if success {
+        println!("The CA is no longer trusted on this machine 👋");
+    } else {
+        eprintln!(
+            "{}: The CA could not be removed from every trust store, see above",
+            "Error".red()
+        );
+    }
+    Ok(success)
+}
@@ -1,171 +1,380 @@
 use crate::{
+    ca_store::{ca_dir, ca_exists, issuing_chain},
     trust_stores::{
         firefox::FirefoxTrustStore, nss::NSSValue, nss_profile::NSSProfile,
         utils::check_if_firefox_exists, CAValue,
     },
-    utils::get_unique_hash,
-    x509::{ca_req::CAReq, distinguished_name::DistinguishedName, leaf_cert::LeafCert},
+    utils::{ca_unique_name, data_dir_ca_cert},
+    x509::{
+        bundle::BundleLayout,
+        ca_req::CAReq,
+        describe::format_name,
+        distinguished_name::DistinguishedName,
+        encoding::read_certificate,
+        file::{create_directory, Overwrite, OUTPUT_DIR_MODE, STDOUT_PATH},
+        leaf_cert::LeafCert,
+    },
 };
 use colored::*;
 use openssl::{
+    hash::MessageDigest,
+    nid::Nid,
     pkey::{PKey, Private},
     x509::{X509Req, X509},
 };
 use std::{
+    collections::HashSet,
     error::Error,
     fs,
+    io::Write,
     path::{Path, PathBuf},
 };
+use tempfile::NamedTempFile;
 
-pub fn generate_install(cert: &X509) -> Result<(), Box<dyn Error>> {
-    let caroot: String = "/home/jerry/.local/share/vanish/ca_cert.pem".to_string();
-    let ca_unique_name: String = get_unique_hash(&caroot)?;
+// Trusts the CA in the system store, NSS and Firefox, reporting every store
+pub fn install_trust(cert: &X509) -> Result<bool, Box<dyn Error>> {
+    let ca_unique_name: String = ca_unique_name(cert)?;
+    let ca_file: NamedTempFile = ca_pem_file(cert)?;
+    let caroot: String = ca_file
+        .path()
+        .to_str()
+        .ok_or("Failed to convert the CA file path to string")?
+        .to_string();
     let ca_value_object: CAValue = CAValue {
         ca_uniques_name: ca_unique_name.clone(),
         certificate: cert.clone(),
     };
-    ca_value_object.install_certificate()?;
+    let system_installed: bool = ca_value_object.install_certificate()?;
     let nss_profile_object: NSSProfile = NSSProfile::new();
     let mkcert: NSSValue =
         NSSValue::new(nss_profile_object, ca_unique_name.clone(), caroot.clone());
-    let success: bool = mkcert.install_nss();
-    let firefox_exists: bool = check_if_firefox_exists()?;
-    if firefox_exists {
+    let nss_installed: bool = mkcert.install_nss();
+    let firefox_installed: Option<bool> = if check_if_firefox_exists()? {
         let firefox_trust_store_object: FirefoxTrustStore =
             FirefoxTrustStore::new(ca_unique_name, caroot)?;
         let paths_with_trust_stores: Vec<PathBuf> =
             FirefoxTrustStore::find_cert_directories(&firefox_trust_store_object)?;
-        FirefoxTrustStore::install_firefox_certificates(
+        Some(FirefoxTrustStore::install_firefox_certificates(
             &firefox_trust_store_object,
             paths_with_trust_stores,
-        );
+        ))
+    } else {
+        None
+    };
+
+    Ok(report_trust(
+        cert,
+        &[
+            ("System trust store", Some(system_installed)),
+            ("NSS", Some(nss_installed)),
+            ("Firefox", firefox_installed),
+        ],
+    ))
+}
+
+// Lists the outcome per trust store. Every store that was found has to trust
+// the CA for the install to succeed, a missing Firefox is no failure.
+pub fn report_trust(cert: &X509, stores: &[(&str, Option<bool>)]) -> bool {
+    println!();
+    println!("Trust for \"{}\" :", format_name(cert.subject_name()));
+    for (store, installed) in stores {
+        match installed {
+            Some(true) => println!("   - {} ✅", store),
+            Some(false) => println!("   - {} ❌", store),
+            None => println!("   - {} not found ➖", store),
+        }
     }
+    let success: bool = stores
+        .iter()
+        .all(|(_, installed)| installed.unwrap_or(true));
+    println!();
     if success {
         println!("Certificate installed successfully 👍");
     } else {
-        eprintln!("Failed to install the certificate.");
+        eprintln!(
+            "{}: The certificate could not be installed in every trust store, see above",
+            "Error".red()
+        );
     }
-    Ok(())
+    success
 }
 
-pub fn save_pem_certificate(
-    name: String,
-    output: Option<String>,
-    leaf_certificate: X509,
-) -> Result<(), Box<dyn Error>> {
-    if let Some(output) = &output {
-        let output_path: &Path = Path::new(output);
-        if !output_path.exists() {
-            fs::create_dir_all(output_path)?;
-        }
-        let output_path: PathBuf = if output_path.is_absolute() {
-            output_path.to_path_buf()
-        } else {
-            std::env::current_dir()?.join(output_path)
-        };
-        let file_name: PathBuf = output_path.join(name);
-        let file_name_str: Option<&str> = file_name.to_str();
-        if let Some(file_name_str) = file_name_str {
-            match LeafCert::save_cert(&leaf_certificate, file_name_str) {
-                Ok(()) => {
-                    println!();
-                    println!(
-                        "{}: Your local certificate from request is saved at: {:?}",
-                        "Note".green(),
-                        file_name
-                    );
-                    println!(
-                        "{}: You may use the Private Key of the Certificate Provided as the Private Key of your Local Certificate.",
-                        "Note".green()
-                    );
-                }
-                Err(err) => {
-                    eprintln!("{}", err);
+// The CA to install or remove: a given file, or the one in the data directory
+pub fn load_trust_ca(ca: &Option<String>) -> Result<X509, Box<dyn Error>> {
+    match ca {
+        Some(ca) => {
+            // A file of the same name wins over a named CA
+            let path: String = match ca_dir(ca) {
+                Some(ca_dir) if !Path::new(ca).exists() && ca_exists(ca) => {
+                    ca_dir.join("ca_cert.pem").display().to_string()
                 }
+                _ => ca.to_string(),
             };
-        } else {
-            eprintln!(
-                "{}: Error creating file for Generated Certificate :",
-                "Error".red()
-            );
+            let path: &str = &path;
+            let cert: Result<X509, Box<dyn Error>> = fs::read(path)
+                .map_err(|err| -> Box<dyn Error> { err.into() })
+                .and_then(|data: Vec<u8>| Ok(read_certificate(&data, path)?));
+            match &cert {
+                Ok(_) => println!("Reading Certificate at {} ✅", path),
+                Err(_) => eprintln!("Reading Certificate at {} ❌", path),
+            }
+            cert
         }
-    } else {
-        let output_path: PathBuf = std::env::current_dir()?;
-        let file_name: PathBuf = output_path.join("csr_cert.pem");
-        let file_name_str: Option<&str> = file_name.to_str();
-        if let Some(file_name_str) = file_name_str {
-            match LeafCert::save_cert(&leaf_certificate, file_name_str) {
-                Ok(()) => {
-                    println!();
-                    println!(
-                        "{}: Your local certificate from request is saved at: {:?}",
-                        "Note".green(),
-                        file_name
-                    );
-                    println!(
-                        "{}: You may use the Private Key of the Certificate Provided as the Private Key of your Local Certificate.",
-                        "Note".green()
-                    );
-                }
-                Err(err) => {
-                    eprintln!("{}", err);
-                }
-            };
-        } else {
-            eprintln!(
-                "{}: Error creating file for Generated Certificate",
+        None => data_dir_ca_cert().ok_or_else(|| {
+            format!(
+                "{}: No CA Certificates found. Run `vanish generate` first or pass `--ca`",
                 "Error".red()
-            );
+            )
+            .into()
+        }),
+    }
+}
+
+// certutil only imports from a file, and the CA in use may live in a PKCS#12
+// bundle or next to its key
+pub fn ca_pem_file(cert: &X509) -> Result<NamedTempFile, Box<dyn Error>> {
+    let mut ca_file: NamedTempFile = NamedTempFile::new()?;
+    ca_file.write_all(&cert.to_pem()?)?;
+    Ok(ca_file)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OutputFiles {
+    pub output: Option<String>,
+    pub cert_file: Option<String>,
+    pub key_file: Option<String>,
+}
+
+impl OutputFiles {
+    pub fn uses_stdout(&self) -> bool {
+        [&self.cert_file, &self.key_file]
+            .iter()
+            .any(|template: &&Option<String>| template.as_deref() == Some(STDOUT_PATH))
+    }
+
+    pub fn cert_path(&self, domain: &str, default_name: &str) -> Result<String, Box<dyn Error>> {
+        self.render(&self.cert_file, domain, default_name)
+    }
+
+    pub fn key_path(&self, domain: &str, default_name: &str) -> Result<String, Box<dyn Error>> {
+        self.render(&self.key_file, domain, default_name)
+    }
+
+    // Relative names land in the output directory, `{domain}` is replaced in
+    // templates and any directories they name are created on the way
+    fn render(
+        &self,
+        template: &Option<String>,
+        domain: &str,
+        default_name: &str,
+    ) -> Result<String, Box<dyn Error>> {
+        let file_name: String = match template {
+            Some(template) if template == STDOUT_PATH => return Ok(STDOUT_PATH.to_string()),
+            Some(template) => template.replace("{domain}", domain),
+            None => default_name.to_string(),
+        };
+        let path: PathBuf = resolve_output_dir(&self.output)?.join(file_name);
+        if let Some(parent) = path.parent() {
+            if !parent.exists() {
+                create_directory(parent, OUTPUT_DIR_MODE)?;
+            }
         }
+        path.into_os_string()
+            .into_string()
+            .map_err(|path| format!("Path {:?} is not valid UTF-8", path).into())
     }
-    Ok(())
+}
+
+pub fn save_pem_certificate(
+    name: &str,
+    files: &OutputFiles,
+    leaf_certificate: X509,
+    overwrite: Overwrite,
+) -> Result<String, Box<dyn Error>> {
+    let file_name: String = files.cert_path(name, &format!("{}.pem", name))?;
+    LeafCert::save_cert(&leaf_certificate, &file_name, overwrite)?;
+    println!();
+    if file_name == STDOUT_PATH {
+        println!(
+            "{}: Your local certificate from request is written to standard output",
+            "Note".green()
+        );
+    } else {
+        println!(
+            "{}: Your local certificate from request is saved at: {:?}",
+            "Note".green(),
+            file_name
+        );
+    }
+    println!(
+        "{}: You may use the Private Key of the Certificate Provided as the Private Key of your Local Certificate.",
+        "Note".green()
+    );
+    Ok(file_name)
 }
 
 pub fn save_pem_key_pair(
-    output: &Option<String>,
+    files: &OutputFiles,
     leaf_certificate: X509,
     name: String,
     private_key: PKey<Private>,
-) -> Result<(), Box<dyn Error>> {
-    if let Some(output) = &output {
-        let output_path: &Path = Path::new(output);
-        if !output_path.exists() {
-            fs::create_dir_all(output_path)?;
-        }
-        let output_path: PathBuf = if output_path.is_absolute() {
-            output_path.to_path_buf()
-        } else {
-            std::env::current_dir()?.join(output_path)
+    key_passphrase: Option<&[u8]>,
+    overwrite: Overwrite,
+) -> Result<(String, String), Box<dyn Error>> {
+    let file_name: String = files.cert_path(&name, &format!("{}.pem", name))?;
+    LeafCert::save_cert(&leaf_certificate, &file_name, overwrite)?;
+    let key_file_name: String = files.key_path(&name, &format!("{}-key.pem", name))?;
+    LeafCert::save_key(&private_key, &key_file_name, key_passphrase, overwrite)?;
+    Ok((file_name, key_file_name))
+}
+
+// Where relying parties look up whether an issued leaf was revoked
+#[derive(Debug, Default)]
+pub struct RevocationUrls {
+    pub crl: Option<String>,
+    pub ocsp: Option<String>,
+}
+
+impl RevocationUrls {
+    pub fn resolve(crl: Option<String>, ocsp: Option<String>) -> Result<Self, Box<dyn Error>> {
+        let crl: Option<String> = match crl {
+            Some(crl) => Some(crl_distribution_point_uri(&crl)?),
+            None => None,
         };
-        let file_name: PathBuf = output_path.join(format!("{}.pem", name));
-        let file_name_str: Option<&str> = file_name.to_str();
-        if let Some(file_name_str) = file_name_str {
-            LeafCert::save_cert(&leaf_certificate, file_name_str)?;
-        } else {
-            eprintln!("Error: Error creating file for generated Certificate :");
-        }
-        let key_file_name: PathBuf = output_path.join(format!("{}-key.pem", name));
-        let key_file_name_str: Option<&str> = key_file_name.to_str();
-        if let Some(key_file_name_str) = key_file_name_str {
-            LeafCert::save_key(&private_key, key_file_name_str)?;
-        } else {
-            eprintln!("Error: Error creating file for key : {}", name);
+        if let Some(ocsp) = &ocsp {
+            if !ocsp.starts_with("http://") && !ocsp.starts_with("https://") {
+                return Err(format!(
+                    "{}: The OCSP responder must be an http:// or https:// URL, not {}",
+                    "Error".red(),
+                    ocsp
+                )
+                .into());
+            }
         }
+        Ok(RevocationUrls { crl, ocsp })
+    }
+
+    pub fn apply(&self, leaf_cert: LeafCert) -> LeafCert {
+        leaf_cert
+            .with_crl_distribution_point(self.crl.as_deref())
+            .with_ocsp_responder(self.ocsp.as_deref())
+    }
+}
+
+// Issues a leaf for a signing request, keeping its subject and SANs
+pub fn sign_request(
+    csr: &X509Req,
+    ca_cert: &X509,
+    ca_key: &PKey<Private>,
+    revocation_urls: &RevocationUrls,
+) -> Result<X509, Box<dyn Error>> {
+    let leaf_cert_object: LeafCert = revocation_urls.apply(LeafCert::new(
+        create_distinguished_name(&None, &None, &None),
+    )?);
+    let (certificate, _private_key) =
+        leaf_cert_object.generate_certificate(ca_cert, ca_key, Some(csr))?;
+    Ok(certificate)
+}
+
+// Issues a leaf with a fresh key for one domain, as `vanish generate` does
+pub fn issue_for_domain(
+    domain: &str,
+    distinguished_name: &DistinguishedName,
+    ca_cert: &X509,
+    ca_key: &PKey<Private>,
+    revocation_urls: &RevocationUrls,
+) -> Result<(X509, PKey<Private>), Box<dyn Error>> {
+    let leaf_cert_object: LeafCert =
+        revocation_urls.apply(LeafCert::new(distinguished_name.clone())?.with_domain(domain));
+    let (certificate, private_key) =
+        leaf_cert_object.generate_certificate(ca_cert, ca_key, None)?;
+    Ok((
+        certificate,
+        private_key.expect("a key is generated when there is no signing request"),
+    ))
+}
+
+// A certificate for the TLS of the local servers themselves, which is never
+// saved or recorded in the ledger
+pub fn serving_certificate(
+    host: &str,
+    ca_cert: &X509,
+    ca_key: &PKey<Private>,
+) -> Result<(X509, PKey<Private>), Box<dyn Error>> {
+    issue_for_domain(
+        host,
+        &create_distinguished_name(&None, &None, &None),
+        ca_cert,
+        ca_key,
+        &RevocationUrls::default(),
+    )
+}
+
+// A URL is used as is, anything else is a local file turned into a `file://` URL
+fn crl_distribution_point_uri(location: &str) -> Result<String, Box<dyn Error>> {
+    if location.contains("://") {
+        return Ok(location.to_string());
+    }
+    let path: &Path = Path::new(location);
+    let path: PathBuf = if path.is_absolute() {
+        path.to_path_buf()
     } else {
-        let output_path: PathBuf = std::env::current_dir()?;
-        let file_name: PathBuf = output_path.join(format!("{}.pem", name));
+        std::env::current_dir()?.join(path)
+    };
+    Ok(format!("file://{}", path.display()))
+}
+
+pub fn resolve_output_dir(output: &Option<String>) -> Result<PathBuf, Box<dyn Error>> {
+    match output {
+        Some(output) => {
+            let output_path: &Path = Path::new(output);
+            if !output_path.exists() {
+                create_directory(output_path, OUTPUT_DIR_MODE)?;
+            }
+            if output_path.is_absolute() {
+                Ok(output_path.to_path_buf())
+            } else {
+                Ok(std::env::current_dir()?.join(output_path))
+            }
+        }
+        None => Ok(std::env::current_dir()?),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn save_pem_bundles(
+    files: &OutputFiles,
+    name: &str,
+    bundles: &[BundleLayout],
+    leaf_certificate: &X509,
+    private_key: Option<&PKey<Private>>,
+    ca_certificate: &X509,
+    key_passphrase: Option<&[u8]>,
+    overwrite: Overwrite,
+) -> Result<(), Box<dyn Error>> {
+    if bundles.is_empty() {
+        return Ok(());
+    }
+    let output_path: PathBuf = resolve_output_dir(&files.output)?;
+    let chain: Vec<X509> = issuing_chain(ca_certificate);
+    for bundle in bundles {
+        if bundle.needs_key() && private_key.is_none() {
+            eprintln!(
+                "{}: Skipping {:?} bundle for {} as its private key is not known to Vanish",
+                "Warning".yellow(),
+                bundle,
+                name
+            );
+            continue;
+        }
+        let file_name: PathBuf = output_path.join(bundle.file_name(name));
         let file_name_str: Option<&str> = file_name.to_str();
         if let Some(file_name_str) = file_name_str {
-            LeafCert::save_cert(&leaf_certificate, file_name_str)?;
-        } else {
-            eprintln!("Error: Error creating file for generated Certificate");
-        }
-        let key_file_name: PathBuf = output_path.join(format!("{}-key.pem", name));
-        let key_file_name_str: Option<&str> = key_file_name.to_str();
-        if let Some(key_file_name_str) = key_file_name_str {
-            LeafCert::save_key(&private_key, key_file_name_str)?;
+            let contents: Vec<u8> =
+                bundle.build(leaf_certificate, private_key, &chain, key_passphrase)?;
+            bundle.save_bundle(&contents, file_name_str, overwrite)?;
         } else {
-            eprintln!("Error: Error creating file for key : {}", name);
+            eprintln!("Error: Error creating bundle file for : {}", name);
         }
     }
     Ok(())
@@ -173,53 +382,64 @@ pub fn save_pem_key_pair(
 
 pub fn save_csr_certificate(
     name: String,
-    output: &Option<String>,
+    files: &OutputFiles,
     ca_req_certificate: X509Req,
     private_key: PKey<Private>,
+    key_passphrase: Option<&[u8]>,
+    overwrite: Overwrite,
 ) -> Result<PathBuf, Box<dyn Error>> {
-    if let Some(output) = &output {
-        let output_path: &Path = Path::new(output);
-        if !output_path.exists() {
-            fs::create_dir_all(output_path)?;
-        }
-        let output_path: PathBuf = if output_path.is_absolute() {
-            output_path.to_path_buf()
-        } else {
-            std::env::current_dir()?.join(output_path)
-        };
-        let file_name: PathBuf = output_path.join(format!("csr-{}.pem", name));
-        let file_name_str: Option<&str> = file_name.to_str();
-        if let Some(file_name_str) = file_name_str {
-            CAReq::save_certificate_to_file(&ca_req_certificate, file_name_str)?;
-        } else {
-            eprintln!("Error: Error creating file for domain : {}", name);
-        }
-        let key_file_name: PathBuf = output_path.join(format!("csr-{}-key.pem", name));
-        let key_file_name_str: Option<&str> = key_file_name.to_str();
-        if let Some(key_file_name_str) = key_file_name_str {
-            CAReq::save_key(&private_key, key_file_name_str)?;
-        } else {
-            eprintln!("Error: Error creating file for key : {}", name);
-        }
-        return Ok(output_path);
-    } else {
-        let output_path: PathBuf = std::env::current_dir()?;
-        let file_name: PathBuf = output_path.join(format!("csr-{}.pem", name));
-        let file_name_str: Option<&str> = file_name.to_str();
-        if let Some(file_name_str) = file_name_str {
-            CAReq::save_certificate_to_file(&ca_req_certificate, file_name_str)?;
-        } else {
-            eprintln!("Error: Error creating file for domain : {}", name);
-        }
-        let key_file_name: PathBuf = output_path.join(format!("csr-{}-key.pem", name));
-        let key_file_name_str: Option<&str> = key_file_name.to_str();
-        if let Some(key_file_name_str) = key_file_name_str {
-            CAReq::save_key(&private_key, key_file_name_str)?;
-        } else {
-            eprintln!("Error: Error creating file for key : {}", name);
-        }
-        Ok(output_path)
+    let file_name: String = files.cert_path(&name, &format!("csr-{}.pem", name))?;
+    CAReq::save_certificate_to_file(&ca_req_certificate, &file_name, overwrite)?;
+    let key_file_name: String = files.key_path(&name, &format!("csr-{}-key.pem", name))?;
+    CAReq::save_key(&private_key, &key_file_name, key_passphrase, overwrite)?;
+    resolve_output_dir(&files.output)
+}
+
+pub fn certificate_file_name(cert: &X509) -> String {
+    let dns_name: Option<String> = cert.subject_alt_names().and_then(|names| {
+        names
+            .iter()
+            .find_map(|name| name.dnsname().map(|dns: &str| dns.to_string()))
+    });
+    let common_name: Option<String> = cert
+        .subject_name()
+        .entries_by_nid(Nid::COMMONNAME)
+        .next()
+        .and_then(|entry| entry.data().as_utf8().ok())
+        .map(|common_name| common_name.to_string());
+    let name: String = match dns_name.or(common_name) {
+        Some(name) => name.replace('*', "_wildcard"),
+        None => match cert.digest(MessageDigest::sha256()) {
+            Ok(digest) => format!(
+                "csr_cert-{}",
+                digest[..8]
+                    .iter()
+                    .map(|byte: &u8| format!("{:02x}", byte))
+                    .collect::<String>()
+            ),
+            Err(_) => "csr_cert".to_string(),
+        },
+    };
+    name.chars()
+        .map(|c: char| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+pub fn unique_certificate_file_name(cert: &X509, used_names: &mut HashSet<String>) -> String {
+    let base_name: String = certificate_file_name(cert);
+    let mut name: String = base_name.clone();
+    let mut suffix: usize = 2;
+    while !used_names.insert(name.clone()) {
+        name = format!("{}-{}", base_name, suffix);
+        suffix += 1;
     }
+    name
 }
 
 pub fn create_distinguished_name(
@@ -0,0 +1,213 @@
+use crate::{
+    utils::data_dir_ca_cert,
+    x509::{
+        ca_cert::CACert,
+        describe::{format_general_names, format_name, ip_from_bytes},
+        encoding::{pem_blocks, read_certificate, Encoding},
+    },
+};
+use colored::*;
+use openssl::{
+    asn1::{Asn1Time, Asn1TimeRef},
+    error::ErrorStack,
+    pkey::{PKey, Private},
+    stack::Stack,
+    x509::{
+        store::{X509Store, X509StoreBuilder},
+        verify::X509VerifyFlags,
+        GeneralNameRef, X509StoreContext, X509StoreContextRef, X509VerifyResult, X509,
+    },
+};
+use std::{cmp::Ordering, error::Error, fs, net::IpAddr};
+
+pub fn verify(
+    cert_path: String,
+    host: Option<String>,
+    key_path: Option<String>,
+    ca_path: Option<String>,
+) -> Result<bool, Box<dyn Error>> {
+    println!();
+    let cert_data: Vec<u8> = fs::read(&cert_path)?;
+    let cert: X509 = read_certificate(&cert_data, &cert_path)?;
+    // Intermediates bundled after the leaf, as in a fullchain file
+    let mut intermediates: Stack<X509> = Stack::new()?;
+    if Encoding::detect(&cert_data) == Encoding::Pem {
+        for block in pem_blocks(&cert_data, &["CERTIFICATE"]).into_iter().skip(1) {
+            intermediates.push(X509::from_pem(block)?)?;
+        }
+    }
+
+    let ca_cert: Option<X509> = match &ca_path {
+        Some(ca_path) => Some(CACert::load_cert(ca_path)?),
+        None => data_dir_ca_cert(),
+    };
+    let key: Option<PKey<Private>> = match &key_path {
+        Some(key_path) => Some(CACert::load_key(key_path)?),
+        None => None,
+    };
+
+    let mut checks: Vec<(String, Result<String, String>)> = Vec::new();
+    let issuer: &str = if ca_path.is_some() {
+        "given CA"
+    } else {
+        "Vanish CA"
+    };
+    checks.push((
+        format!("Chains to the {}", issuer),
+        match &ca_cert {
+            Some(ca_cert) => check_chain(&cert, &intermediates, ca_cert)?,
+            None => Err("No Vanish CA found, run `vanish generate` or pass `--ca`".to_string()),
+        },
+    ));
+    checks.push((
+        "Currently valid".to_string(),
+        check_validity(&cert, "Certificate")?,
+    ));
+    if let Some(ca_cert) = &ca_cert {
+        checks.push((
+            format!("{} currently valid", issuer),
+            check_validity(ca_cert, "CA certificate")?,
+        ));
+    }
+    if let Some(host) = &host {
+        checks.push((
+            format!("Matches host \"{}\"", host),
+            check_host(&cert, host),
+        ));
+    }
+    if let Some(key) = &key {
+        checks.push((
+            "Pairs with the private key".to_string(),
+            check_key(&cert, key)?,
+        ));
+    }
+
+    println!();
+    println!("Verifying {} :", cert_path);
+    let mut all_passed: bool = true;
+    for (name, result) in &checks {
+        match result {
+            Ok(detail) if detail.is_empty() => println!("   - {} ✅", name),
+            Ok(detail) => println!("   - {} ✅ {}", name, detail.dimmed()),
+            Err(reason) => {
+                all_passed = false;
+                println!("   - {} ❌", name);
+                println!("     {}", reason.red());
+            }
+        }
+    }
+    println!();
+    if all_passed {
+        println!("{}: All checks passed 👍", "Note".green());
+    } else {
+        eprintln!(
+            "{}: Some checks failed, see the reasons above",
+            "Error".red()
+        );
+    }
+    println!();
+    Ok(all_passed)
+}
+
+fn check_chain(
+    cert: &X509,
+    intermediates: &Stack<X509>,
+    ca_cert: &X509,
+) -> Result<Result<String, String>, ErrorStack> {
+    let mut store_builder: X509StoreBuilder = X509StoreBuilder::new()?;
+    store_builder.add_cert(ca_cert.clone())?;
+    // Validity is reported on its own, so keep it out of the chain result
+    store_builder.set_flags(X509VerifyFlags::NO_CHECK_TIME)?;
+    let store: X509Store = store_builder.build();
+    let mut context: X509StoreContext = X509StoreContext::new()?;
+    let result: X509VerifyResult = context.init(
+        &store,
+        cert,
+        intermediates,
+        |context: &mut X509StoreContextRef| {
+            context.verify_cert()?;
+            Ok(context.error())
+        },
+    )?;
+    if result == X509VerifyResult::OK {
+        return Ok(Ok(String::new()));
+    }
+    let reason: String = if cert.issuer_name().try_cmp(ca_cert.subject_name())? != Ordering::Equal {
+        format!(
+            "The certificate was issued by a different CA: {}",
+            format_name(cert.issuer_name())
+        )
+    } else if intermediates.is_empty() && !cert.verify(ca_cert.public_key()?.as_ref())? {
+        "The issuer name matches but the signature does not, the certificate comes from another CA with the same name (was the CA regenerated?)".to_string()
+    } else {
+        result.error_string().to_string()
+    };
+    Ok(Err(reason))
+}
+
+fn check_validity(cert: &X509, what: &str) -> Result<Result<String, String>, ErrorStack> {
+    let now: Asn1Time = Asn1Time::days_from_now(0)?;
+    let not_before: &Asn1TimeRef = cert.not_before();
+    let not_after: &Asn1TimeRef = cert.not_after();
+    if now.compare(not_before)? == Ordering::Less {
+        return Ok(Err(format!("{} is not valid until {}", what, not_before)));
+    }
+    if now.compare(not_after)? != Ordering::Less {
+        return Ok(Err(format!("{} expired on {}", what, not_after)));
+    }
+    Ok(Ok(format!("(until {})", not_after)))
+}
+
+fn check_host(cert: &X509, host: &str) -> Result<String, String> {
+    let Some(names) = cert.subject_alt_names() else {
+        return Err(
+            "The certificate has no Subject Alternative Names, browsers ignore the Common Name"
+                .to_string(),
+        );
+    };
+    let host_ip: Option<IpAddr> = host.parse::<IpAddr>().ok();
+    let matched: bool = names.iter().any(|name: &GeneralNameRef| match host_ip {
+        Some(host_ip) => name.ipaddress().and_then(ip_from_bytes) == Some(host_ip),
+        None => name
+            .dnsname()
+            .is_some_and(|pattern: &str| host_matches(pattern, host)),
+    });
+    if matched {
+        Ok(String::new())
+    } else {
+        Err(format!(
+            "\"{}\" is not covered by the certificate names: {}",
+            host,
+            format_general_names(&names)
+        ))
+    }
+}
+
+// RFC 6125: a wildcard may only be the whole left-most label, stands for
+// exactly one label, and needs at least two labels after it
+pub fn host_matches(pattern: &str, host: &str) -> bool {
+    let pattern: String = pattern.trim_end_matches('.').to_ascii_lowercase();
+    let host: String = host.trim_end_matches('.').to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => {
+            if suffix.contains('*') || suffix.split('.').count() < 2 {
+                return false;
+            }
+            match host.split_once('.') {
+                Some((label, rest)) => !label.is_empty() && rest == suffix,
+                None => false,
+            }
+        }
+        None => !pattern.contains('*') && pattern == host,
+    }
+}
+
+fn check_key(cert: &X509, key: &PKey<Private>) -> Result<Result<String, String>, ErrorStack> {
+    if cert.public_key()?.public_eq(key) {
+        Ok(Ok(String::new()))
+    } else {
+        Ok(Err(
+            "The private key does not belong to this certificate".to_string()
+        ))
+    }
+}
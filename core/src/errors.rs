@@ -6,9 +6,12 @@ pub type CertKeyResult<T> = Result<T, CertKeyPairError>;
 pub type SerialNumberResult<T> = Result<T, SerialNumberError>;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum CertKeyPairError {
     RSAGenerationError(ErrorStack),
+    ECGenerationError(ErrorStack),
     PKeyCreationError(ErrorStack),
+    UnsupportedKeyError(String),
 }
 
 impl fmt::Display for CertKeyPairError {
@@ -25,6 +28,17 @@ impl fmt::Display for CertKeyPairError {
                     err
                 )
             }
+            Self::ECGenerationError(err) => {
+                write!(f, "{}: Generating EC Key : {}", "Error".red(), err)
+            }
+            Self::UnsupportedKeyError(algorithm) => {
+                write!(
+                    f,
+                    "{}: Generating a {} key is not supported",
+                    "Error".red(),
+                    algorithm
+                )
+            }
         }
     }
 }
@@ -32,10 +46,12 @@ impl fmt::Display for CertKeyPairError {
 impl Error for CertKeyPairError {}
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum SerialNumberError {
     BigNumberInitializationError(ErrorStack),
     RandomBigNumberGenerationError(ErrorStack),
     ConvertBigNumberToASN1Error(ErrorStack),
+    SerialNumberCollisionError,
 }
 
 impl fmt::Display for SerialNumberError {
@@ -52,6 +68,13 @@ impl fmt::Display for SerialNumberError {
                     err
                 )
             }
+            Self::SerialNumberCollisionError => {
+                write!(
+                    f,
+                    "{}: Could not find a serial number that is not already in the ledger",
+                    "Error".red()
+                )
+            }
             Self::ConvertBigNumberToASN1Error(err) => {
                 write!(
                     f,
@@ -65,3 +88,81 @@ impl fmt::Display for SerialNumberError {
 }
 
 impl Error for SerialNumberError {}
+
+pub type LedgerResult<T> = Result<T, LedgerError>;
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum LedgerError {
+    NoDataDirectory,
+    ReadLedgerError(std::io::Error, String),
+    ParseLedgerError(serde_json::Error, String),
+    WriteLedgerError(Box<dyn Error>, String),
+    LockLedgerError(std::io::Error, String),
+    CertificateDetailsError(ErrorStack),
+    DuplicateSerialNumber(String),
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoDataDirectory => {
+                write!(f, "{}: Unable to get Data Directory", "Error".red())
+            }
+            Self::ReadLedgerError(err, path) => {
+                write!(
+                    f,
+                    "{}: Reading certificate ledger at {} : {}",
+                    "Error".red(),
+                    path,
+                    err
+                )
+            }
+            Self::ParseLedgerError(err, path) => {
+                write!(
+                    f,
+                    "{}: Certificate ledger at {} is not valid JSON : {}",
+                    "Error".red(),
+                    path,
+                    err
+                )
+            }
+            Self::WriteLedgerError(err, path) => {
+                write!(
+                    f,
+                    "{}: Writing certificate ledger at {} : {}",
+                    "Error".red(),
+                    path,
+                    err
+                )
+            }
+            Self::LockLedgerError(err, path) => {
+                write!(
+                    f,
+                    "{}: Locking certificate ledger with {} : {}",
+                    "Error".red(),
+                    path,
+                    err
+                )
+            }
+            Self::CertificateDetailsError(err) => {
+                write!(
+                    f,
+                    "{}: Reading certificate details for the ledger : {}",
+                    "Error".red(),
+                    err
+                )
+            }
+            Self::DuplicateSerialNumber(serial) => {
+                write!(
+                    f,
+                    "{}: Serial number {} was already issued",
+                    "Error".red(),
+                    serial
+                )
+            }
+        }
+    }
+}
+
+impl Error for LedgerError {}
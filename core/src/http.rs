@@ -0,0 +1,302 @@
+use colored::*;
+use openssl::{
+    error::ErrorStack,
+    pkey::{PKey, Private},
+    ssl::{SslAcceptor, SslAcceptorBuilder, SslMethod, SslVerifyMode},
+    x509::X509,
+};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+// Just enough HTTP/1.1 for the local responders: one request per connection,
+// bodies delimited by Content-Length
+const MAX_HEADER_BYTES: usize = 64 * 1024;
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    pub fn read<S: Read>(stream: S) -> io::Result<HttpRequest> {
+        let mut reader: BufReader<S> = BufReader::new(stream);
+        let mut header_bytes: usize = 0;
+        let mut request_line: String = String::new();
+        header_bytes += reader.read_line(&mut request_line)?;
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+            return Err(invalid("malformed request line"));
+        };
+        let (method, path): (String, String) = (method.to_string(), path.to_string());
+
+        let mut headers: Vec<(String, String)> = Vec::new();
+        loop {
+            let mut line: String = String::new();
+            let read: usize = reader.read_line(&mut line)?;
+            header_bytes += read;
+            if header_bytes > MAX_HEADER_BYTES {
+                return Err(invalid("headers too large"));
+            }
+            let line: &str = line.trim_end_matches(['\r', '\n']);
+            if read == 0 || line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+            }
+        }
+
+        let content_length: usize = headers
+            .iter()
+            .find(|(name, _)| name == "content-length")
+            .map(|(_, value)| value.parse::<usize>())
+            .transpose()
+            .map_err(|_| invalid("invalid Content-Length"))?
+            .unwrap_or(0);
+        if content_length > MAX_BODY_BYTES {
+            return Err(invalid("body too large"));
+        }
+        let mut body: Vec<u8> = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        Ok(HttpRequest {
+            method,
+            path,
+            headers,
+            body,
+        })
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn new(status: u16, content_type: &str, body: Vec<u8>) -> Self {
+        HttpResponse {
+            status,
+            headers: vec![("Content-Type".to_string(), content_type.to_string())],
+            body,
+        }
+    }
+
+    pub fn empty(status: u16) -> Self {
+        HttpResponse {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn text(status: u16, body: &str) -> Self {
+        HttpResponse::new(
+            status,
+            "text/plain; charset=utf-8",
+            format!("{}\n", body).into(),
+        )
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn write<S: Write>(&self, stream: &mut S) -> io::Result<()> {
+        let mut head: String = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str(&format!(
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.body.len()
+        ));
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(&self.body)?;
+        stream.flush()
+    }
+}
+
+// Answers connections one at a time, which is plenty for a developer machine
+// and keeps every handler free of locking
+pub fn serve<F: FnMut(&HttpRequest) -> HttpResponse>(
+    listener: TcpListener,
+    acceptor: Option<&SslAcceptor>,
+    mut handle: F,
+) {
+    for stream in listener.incoming() {
+        let stream: TcpStream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("{}: Accepting connection: {}", "Warning".yellow(), err);
+                continue;
+            }
+        };
+        if let Err(err) = stream.set_read_timeout(Some(READ_TIMEOUT)) {
+            eprintln!("{}: Accepting connection: {}", "Warning".yellow(), err);
+            continue;
+        }
+        let result: io::Result<()> = match acceptor {
+            Some(acceptor) => match acceptor.accept(stream) {
+                Ok(mut stream) => answer(&mut stream, &mut handle),
+                Err(err) => Err(io::Error::other(err.to_string())),
+            },
+            None => answer(&mut &stream, &mut handle),
+        };
+        if let Err(err) = result {
+            eprintln!("{}: Answering request: {}", "Warning".yellow(), err);
+        }
+    }
+}
+
+fn answer<S: Read + Write, F: FnMut(&HttpRequest) -> HttpResponse>(
+    stream: &mut S,
+    handle: &mut F,
+) -> io::Result<()> {
+    let response: HttpResponse = match HttpRequest::read(&mut *stream) {
+        Ok(request) => handle(&request),
+        Err(err) => HttpResponse::text(400, &err.to_string()),
+    };
+    response.write(stream)
+}
+
+// TLS with a certificate from the Vanish CA, which the clients already trust.
+// With a client CA, only clients holding a certificate it issued get through
+pub fn tls_acceptor(
+    cert: &X509,
+    key: &PKey<Private>,
+    ca_cert: &X509,
+    client_ca: Option<&X509>,
+) -> Result<SslAcceptor, ErrorStack> {
+    let mut acceptor: SslAcceptorBuilder =
+        SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server())?;
+    acceptor.set_certificate(cert)?;
+    acceptor.set_private_key(key)?;
+    acceptor.add_extra_chain_cert(ca_cert.clone())?;
+    acceptor.check_private_key()?;
+    if let Some(client_ca) = client_ca {
+        acceptor.cert_store_mut().add_cert(client_ca.clone())?;
+        acceptor.add_client_ca(client_ca)?;
+        acceptor.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    }
+    Ok(acceptor.build())
+}
+
+// A plain HTTP GET for challenge validation, returning the status and body
+pub fn get(host: &str, port: u16, path: &str) -> io::Result<(u16, Vec<u8>)> {
+    let address: SocketAddr = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| invalid("host does not resolve"))?;
+    let mut stream: TcpStream = TcpStream::connect_timeout(&address, READ_TIMEOUT)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: {}\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        path, host
+    )?;
+    let mut response: Vec<u8> = Vec::new();
+    stream
+        .take(MAX_BODY_BYTES as u64)
+        .read_to_end(&mut response)?;
+
+    let end: usize = response
+        .windows(4)
+        .position(|window: &[u8]| window == b"\r\n\r\n")
+        .ok_or_else(|| invalid("truncated response"))?;
+    let head: String = String::from_utf8_lossy(&response[..end]).to_string();
+    let status: u16 = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status: &str| status.parse::<u16>().ok())
+        .ok_or_else(|| invalid("malformed status line"))?;
+    let body: &[u8] = &response[end + 4..];
+    let chunked: bool = head.lines().any(|line: &str| {
+        line.split_once(':').is_some_and(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("transfer-encoding")
+                && value.trim().eq_ignore_ascii_case("chunked")
+        })
+    });
+    if chunked {
+        return Ok((status, dechunk(body)?));
+    }
+    Ok((status, body.to_vec()))
+}
+
+fn dechunk(mut body: &[u8]) -> io::Result<Vec<u8>> {
+    let mut decoded: Vec<u8> = Vec::new();
+    loop {
+        let line_end: usize = body
+            .windows(2)
+            .position(|window: &[u8]| window == b"\r\n")
+            .ok_or_else(|| invalid("truncated chunk"))?;
+        let size: &str =
+            std::str::from_utf8(&body[..line_end]).map_err(|_| invalid("invalid chunk size"))?;
+        let size: usize = usize::from_str_radix(size.split(';').next().unwrap_or("").trim(), 16)
+            .map_err(|_| invalid("invalid chunk size"))?;
+        if size == 0 {
+            return Ok(decoded);
+        }
+        let chunk: &[u8] = body
+            .get(line_end + 2..line_end + 2 + size)
+            .ok_or_else(|| invalid("truncated chunk"))?;
+        decoded.extend_from_slice(chunk);
+        body = body.get(line_end + 4 + size..).unwrap_or_default();
+    }
+}
+
+pub fn percent_decode(value: &str) -> Option<Vec<u8>> {
+    let bytes: &[u8] = value.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut index: usize = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'%' => {
+                let hex: &str = value.get(index + 1..index + 3)?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                index += 3;
+            }
+            byte => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    Some(decoded)
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        415 => "Unsupported Media Type",
+        500 => "Internal Server Error",
+        _ => "",
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
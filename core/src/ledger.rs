@@ -0,0 +1,298 @@
+use crate::{
+    errors::{LedgerError, LedgerResult},
+    utils::{certificate_fingerprint, format_iso8601, parse_iso8601, unix_now},
+    x509::{
+        self,
+        crl::RevocationReason,
+        describe::{
+            asn1_time_to_unix, format_general_names, format_name, key_description, serial_hex,
+        },
+        encoding::read_certificate,
+        file::{
+            create_directory, write_file, Overwrite, DATA_DIR_MODE, KEY_FILE_MODE, STDOUT_PATH,
+        },
+    },
+};
+use colored::*;
+use openssl::{error::ErrorStack, x509::X509};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+pub const LEDGER_FILE: &str = "index.json";
+// The ledger itself is replaced on every save, so the lock lives next to it
+pub const LEDGER_LOCK_FILE: &str = "index.json.lock";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub serial: String,
+    pub subject: String,
+    pub subject_alt_names: Vec<String>,
+    pub not_before: String,
+    pub not_after: String,
+    pub key_algorithm: String,
+    pub ca_fingerprint: String,
+    pub cert_path: Option<String>,
+    pub key_path: Option<String>,
+    pub issued_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revocation: Option<Revocation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revocation {
+    pub revoked_at: String,
+    pub reason: RevocationReason,
+}
+
+impl LedgerEntry {
+    pub fn new(
+        cert: &X509,
+        ca_cert: &X509,
+        cert_path: Option<&str>,
+        key_path: Option<&str>,
+    ) -> Result<Self, ErrorStack> {
+        let subject_alt_names: Vec<String> = match cert.subject_alt_names() {
+            Some(names) => format_general_names(&names)
+                .split(", ")
+                .map(|name: &str| name.to_string())
+                .collect(),
+            None => Vec::new(),
+        };
+        Ok(LedgerEntry {
+            serial: serial_hex(cert)?,
+            subject: format_name(cert.subject_name()),
+            subject_alt_names,
+            not_before: format_iso8601(asn1_time_to_unix(cert.not_before())?),
+            not_after: format_iso8601(asn1_time_to_unix(cert.not_after())?),
+            key_algorithm: key_description(cert.public_key()?.as_ref()),
+            ca_fingerprint: certificate_fingerprint(ca_cert)?,
+            cert_path: cert_path.map(|path: &str| path.to_string()),
+            key_path: key_path.map(|path: &str| path.to_string()),
+            issued_at: format_iso8601(unix_now()),
+            revocation: None,
+        })
+    }
+
+    // SAN values without their `DNS:`/`IP:` prefixes, or the subject if none
+    pub fn names(&self) -> String {
+        if self.subject_alt_names.is_empty() {
+            return self.subject.clone();
+        }
+        self.subject_alt_names
+            .iter()
+            .map(|name: &String| {
+                name.split_once(':')
+                    .map_or(name.as_str(), |(_, value)| value)
+            })
+            .collect::<Vec<&str>>()
+            .join(", ")
+    }
+
+    pub fn revoke(&mut self, reason: RevocationReason) {
+        self.revocation = Some(Revocation {
+            revoked_at: format_iso8601(unix_now()),
+            reason,
+        });
+    }
+
+    // Whole days until `not_after`, negative once expired, or None if the
+    // ledger was edited into a date that does not parse
+    pub fn days_left(&self, now: i64) -> Option<i64> {
+        parse_iso8601(&self.not_after).map(|not_after: i64| (not_after - now).div_euclid(86400))
+    }
+
+    // Whether `cert_path` still holds the certificate this entry records
+    pub fn disk_status(&self, cert_path: &str) -> DiskStatus {
+        let data: Vec<u8> = match fs::read(cert_path) {
+            Ok(data) => data,
+            Err(_) if !Path::new(cert_path).exists() => return DiskStatus::Missing,
+            Err(_) => return DiskStatus::Unreadable,
+        };
+        match read_certificate(&data, cert_path).map(|cert: X509| serial_hex(&cert)) {
+            Ok(Ok(serial)) if serial.eq_ignore_ascii_case(&self.serial) => DiskStatus::Matches,
+            Ok(Ok(_)) => DiskStatus::Replaced,
+            _ => DiskStatus::Unreadable,
+        }
+    }
+}
+
+pub enum DiskStatus {
+    Matches,
+    Replaced,
+    Missing,
+    Unreadable,
+}
+
+// Every leaf Vanish issues, kept as `index.json` in the data directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Ledger {
+    // Number of the last CRL signed before they were numbered per CA, so no CA
+    // starts below a number it may already have published
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub crl_number: u64,
+    // Number of the last CRL each CA signed, by CA certificate fingerprint
+    #[serde(default)]
+    pub crl_numbers: BTreeMap<String, u64>,
+    pub certificates: Vec<LedgerEntry>,
+}
+
+fn is_zero(number: &u64) -> bool {
+    *number == 0
+}
+
+impl Ledger {
+    pub fn path() -> LedgerResult<PathBuf> {
+        x509::DATA_DIR
+            .as_ref()
+            .map(|data_dir: &PathBuf| data_dir.join(LEDGER_FILE))
+            .ok_or(LedgerError::NoDataDirectory)
+    }
+
+    pub fn load() -> LedgerResult<Ledger> {
+        Ledger::load_from(&Ledger::path()?)
+    }
+
+    pub fn load_from(path: &Path) -> LedgerResult<Ledger> {
+        let path_str: String = path.display().to_string();
+        match fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|err: serde_json::Error| LedgerError::ParseLedgerError(err, path_str)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Ledger::default()),
+            Err(err) => Err(LedgerError::ReadLedgerError(err, path_str)),
+        }
+    }
+
+    pub fn save(&self) -> LedgerResult<()> {
+        self.save_to(&Ledger::path()?)
+    }
+
+    pub fn save_to(&self, path: &Path) -> LedgerResult<()> {
+        let path_str: String = path.display().to_string();
+        let mut data: Vec<u8> =
+            serde_json::to_vec_pretty(self).map_err(|err: serde_json::Error| {
+                LedgerError::ParseLedgerError(err, path_str.clone())
+            })?;
+        data.push(b'\n');
+        // Paths in the ledger say where keys live, so keep it private like them
+        write_file(&path_str, &data, KEY_FILE_MODE, Overwrite::Replace)
+            .map_err(|err| LedgerError::WriteLedgerError(Box::new(err), path_str))
+    }
+
+    // Held from loading the ledger until it is saved again, so that other
+    // Vanish processes wait instead of writing back an older copy
+    pub fn lock() -> LedgerResult<LedgerLock> {
+        LedgerLock::acquire(&Ledger::path()?)
+    }
+
+    // Loads, changes and saves the ledger while holding its lock
+    pub fn update<T>(change: impl FnOnce(&mut Ledger) -> LedgerResult<T>) -> LedgerResult<T> {
+        Ledger::update_at(&Ledger::path()?, change)
+    }
+
+    pub fn update_at<T>(
+        path: &Path,
+        change: impl FnOnce(&mut Ledger) -> LedgerResult<T>,
+    ) -> LedgerResult<T> {
+        let _lock: LedgerLock = LedgerLock::acquire(path)?;
+        let mut ledger: Ledger = Ledger::load_from(path)?;
+        let result: T = change(&mut ledger)?;
+        ledger.save_to(path)?;
+        Ok(result)
+    }
+
+    pub fn find_serial(&self, serial: &str) -> Option<&LedgerEntry> {
+        self.certificates
+            .iter()
+            .find(|entry: &&LedgerEntry| entry.serial.eq_ignore_ascii_case(serial))
+    }
+
+    pub fn find_serial_mut(&mut self, serial: &str) -> Option<&mut LedgerEntry> {
+        self.certificates
+            .iter_mut()
+            .find(|entry: &&mut LedgerEntry| entry.serial.eq_ignore_ascii_case(serial))
+    }
+
+    pub fn contains_serial(&self, serial: &str) -> bool {
+        self.find_serial(serial).is_some()
+    }
+
+    // Each CA numbers its CRLs on its own, so other CAs' activity leaves no gaps
+    pub fn next_crl_number(&mut self, ca_fingerprint: &str) -> u64 {
+        let crl_number: &mut u64 = self
+            .crl_numbers
+            .entry(ca_fingerprint.to_string())
+            .or_insert(self.crl_number);
+        *crl_number += 1;
+        *crl_number
+    }
+
+    pub fn record(&mut self, entry: LedgerEntry) -> LedgerResult<()> {
+        if self.contains_serial(&entry.serial) {
+            return Err(LedgerError::DuplicateSerialNumber(entry.serial));
+        }
+        self.certificates.push(entry);
+        Ok(())
+    }
+}
+
+// An exclusive `flock` on `index.json.lock`, released when dropped
+pub struct LedgerLock {
+    _file: fs::File,
+}
+
+impl LedgerLock {
+    pub fn acquire(ledger_path: &Path) -> LedgerResult<LedgerLock> {
+        let lock_path: PathBuf = ledger_path.with_file_name(LEDGER_LOCK_FILE);
+        let lock_error = |err: io::Error| -> LedgerError {
+            LedgerError::LockLedgerError(err, lock_path.display().to_string())
+        };
+        if let Some(parent) = lock_path.parent() {
+            create_directory(parent, DATA_DIR_MODE).map_err(lock_error)?;
+        }
+        let file: fs::File = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(lock_error)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+            // Blocks until the process holding the lock is done with the ledger
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+                return Err(lock_error(io::Error::last_os_error()));
+            }
+        }
+        Ok(LedgerLock { _file: file })
+    }
+}
+
+// Certificates written to standard output have no path worth remembering
+pub fn ledger_path(path: &str) -> Option<&str> {
+    (path != STDOUT_PATH).then_some(path)
+}
+
+pub fn serial_in_use(serial: &str) -> bool {
+    Ledger::load()
+        .map(|ledger: Ledger| ledger.contains_serial(serial))
+        .unwrap_or(false)
+}
+
+// The certificate is already on disk at this point, so a ledger failure is
+// reported without failing the whole run
+pub fn record_issued(cert: &X509, ca_cert: &X509, cert_path: Option<&str>, key_path: Option<&str>) {
+    let result: LedgerResult<()> = LedgerEntry::new(cert, ca_cert, cert_path, key_path)
+        .map_err(|err: ErrorStack| LedgerError::CertificateDetailsError(err))
+        .and_then(|entry: LedgerEntry| Ledger::update(|ledger: &mut Ledger| ledger.record(entry)));
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        eprintln!(
+            "{}: The certificate was saved but is missing from the ledger",
+            "Warning".yellow()
+        );
+    }
+}
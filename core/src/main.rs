@@ -1,13 +1,44 @@
+mod acme;
+mod agent;
+mod ca_store;
 mod commands;
 mod errors;
+mod http;
+mod ledger;
 mod trust_stores;
 mod utils;
 mod x509;
+use agent::{lock_agent, run_agent};
+use ca_store::CA_NAME_ENV;
 use clap::{Parser, Subcommand};
-use commands::generate::generate;
+use commands::{
+    acme::serve_acme,
+    ca::{
+        create_ca, delete_ca, export_ca, finish_rotation, import_ca, list_cas, rotate_ca,
+        set_default, show_ca,
+    },
+    caroot::caroot,
+    crl::crl,
+    generate::generate,
+    inspect::inspect,
+    install::install,
+    list::list,
+    ocsp::serve_ocsp,
+    renew::renew,
+    revoke::revoke,
+    serve_api::serve_api,
+    sign::sign,
+    uninstall::uninstall,
+    utils::OutputFiles,
+    verify::verify,
+};
 use std::env;
+use utils::read_key_passphrase;
+use x509::{bundle::BundleLayout, ca_cert::KeyType, crl::RevocationReason, CAROOT_ENV};
 #[cfg(test)]
 mod utils_tests;
+#[cfg(test)]
+mod x509_tests;
 
 #[derive(Parser)]
 #[clap(
@@ -15,12 +46,16 @@ mod utils_tests;
     version = "0.2.1",
     author = "Shubham Singh"
 )]
-struct CLI {
+struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+
+    #[arg(long = "ca-dir", value_name = "DIR", global = true)]
+    ca_dir: Option<String>,
 }
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     #[clap(name = "generate")]
     Generate {
@@ -51,11 +86,366 @@ enum Commands {
         #[arg(short = 'o', long = "output")]
         output: Option<String>,
 
+        #[arg(long = "cert-file")]
+        cert_file: Option<String>,
+
+        #[arg(long = "key-file")]
+        key_file: Option<String>,
+
         #[arg(long = "req-only")]
         request: bool,
 
         #[arg(short = 'i', long = "install")]
         install: bool,
+
+        #[arg(long = "bundle", value_enum)]
+        bundles: Vec<BundleLayout>,
+
+        #[arg(long = "encrypt-key")]
+        encrypt_key: bool,
+
+        #[arg(long = "passphrase-file")]
+        passphrase_file: Option<String>,
+
+        #[arg(long = "protect-ca")]
+        protect_ca: bool,
+
+        #[arg(long = "force")]
+        force: bool,
+
+        #[arg(long = "crl-url", value_name = "URL|FILE")]
+        crl_url: Option<String>,
+
+        #[arg(long = "ocsp-url", value_name = "URL")]
+        ocsp_url: Option<String>,
+
+        #[arg(long = "ca", value_name = "NAME", conflicts_with = "certfile")]
+        ca: Option<String>,
+    },
+
+    #[clap(name = "sign")]
+    Sign {
+        #[arg(long = "csr-dir")]
+        csr_dir: String,
+
+        #[arg(short = 'o', long = "out", alias = "output")]
+        output: Option<String>,
+
+        #[arg(name = "certfile", long)]
+        certfile: Option<String>,
+
+        #[arg(name = "keyfile", long)]
+        keyfile: Option<String>,
+
+        #[arg(long = "force")]
+        force: bool,
+
+        #[arg(long = "crl-url", value_name = "URL|FILE")]
+        crl_url: Option<String>,
+
+        #[arg(long = "ocsp-url", value_name = "URL")]
+        ocsp_url: Option<String>,
+    },
+
+    #[clap(name = "inspect")]
+    Inspect {
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+
+    #[clap(name = "verify")]
+    Verify {
+        cert: String,
+
+        #[arg(long = "host")]
+        host: Option<String>,
+
+        #[arg(long = "key")]
+        key: Option<String>,
+
+        #[arg(long = "ca")]
+        ca: Option<String>,
+    },
+
+    #[clap(name = "list")]
+    List {
+        #[arg(short = 'd', long = "domain")]
+        domain: Option<String>,
+
+        #[arg(long = "expiring", value_name = "DAYS")]
+        expiring: Option<i64>,
+
+        #[arg(long = "ca")]
+        ca: Option<String>,
+    },
+
+    #[clap(name = "renew")]
+    Renew {
+        files: Vec<String>,
+
+        #[arg(long = "expiring", value_name = "DAYS")]
+        expiring: Option<i64>,
+
+        #[arg(long = "reuse-key")]
+        reuse_key: bool,
+
+        #[arg(long = "encrypt-key")]
+        encrypt_key: bool,
+
+        #[arg(long = "passphrase-file")]
+        passphrase_file: Option<String>,
+    },
+
+    #[clap(name = "revoke")]
+    Revoke {
+        #[arg(value_name = "SERIAL|FILE")]
+        target: String,
+
+        #[arg(long = "reason", value_enum, default_value_t = RevocationReason::Unspecified)]
+        reason: RevocationReason,
+    },
+
+    #[clap(name = "crl")]
+    Crl {
+        #[arg(short = 'o', long = "out")]
+        output: Option<String>,
+
+        #[arg(long = "next-update", value_name = "DAYS", default_value_t = 7)]
+        next_update: u32,
+
+        #[arg(long = "der")]
+        der: bool,
+
+        #[arg(long = "force")]
+        force: bool,
+
+        #[arg(long = "previous")]
+        previous: bool,
+    },
+
+    #[clap(name = "ocsp")]
+    Ocsp {
+        #[command(subcommand)]
+        command: OcspCommands,
+    },
+
+    #[clap(name = "acme")]
+    Acme {
+        #[command(subcommand)]
+        command: AcmeCommands,
+    },
+
+    #[clap(name = "serve-api")]
+    ServeApi {
+        #[arg(long = "host", default_value = "127.0.0.1")]
+        host: String,
+
+        #[arg(short = 'p', long = "port", default_value_t = 9443)]
+        port: u16,
+
+        #[arg(long = "tls-name", value_name = "NAME", conflicts_with = "plain_http")]
+        tls_name: Option<String>,
+
+        #[arg(long = "token-file", value_name = "FILE")]
+        token_file: Option<String>,
+
+        #[arg(long = "client-ca", value_name = "FILE", conflicts_with = "plain_http")]
+        client_ca: Option<String>,
+
+        #[arg(long = "plain-http")]
+        plain_http: bool,
+
+        #[arg(long = "crl-url", value_name = "URL|FILE")]
+        crl_url: Option<String>,
+
+        #[arg(long = "ocsp-url", value_name = "URL")]
+        ocsp_url: Option<String>,
+    },
+
+    #[clap(name = "install")]
+    Install {
+        #[arg(long = "ca", value_name = "NAME|FILE")]
+        ca: Option<String>,
+    },
+
+    #[clap(name = "uninstall")]
+    Uninstall {
+        #[arg(long = "ca", value_name = "NAME|FILE")]
+        ca: Option<String>,
+    },
+
+    #[clap(name = "agent")]
+    Agent {
+        #[arg(long = "ttl", default_value_t = 900)]
+        ttl: u64,
+
+        #[arg(long = "lock")]
+        lock: bool,
+    },
+
+    #[clap(name = "caroot")]
+    Caroot,
+
+    #[clap(name = "ca")]
+    Ca {
+        #[command(subcommand)]
+        command: CaCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum CaCommands {
+    #[clap(name = "create")]
+    Create {
+        name: String,
+
+        #[arg(long = "key-type", value_enum, default_value_t = KeyType::Rsa2048)]
+        key_type: KeyType,
+
+        #[arg(long = "validity-days", value_name = "DAYS", default_value_t = 730)]
+        validity_days: u32,
+
+        #[arg(long = "name-constraint", value_name = "DOMAIN")]
+        name_constraints: Vec<String>,
+
+        #[arg(short = 'c', long = "country")]
+        country: Option<String>,
+
+        #[arg(long = "cn")]
+        commonname: Option<String>,
+
+        #[arg(short = 's', long = "state")]
+        state: Option<String>,
+
+        #[arg(long = "default")]
+        make_default: bool,
+
+        #[arg(short = 'i', long = "install")]
+        install: bool,
+
+        #[arg(long = "protect-ca")]
+        protect_ca: bool,
+    },
+
+    #[clap(name = "list")]
+    List,
+
+    #[clap(name = "default")]
+    Default { name: String },
+
+    #[clap(name = "show")]
+    Show { name: Option<String> },
+
+    #[clap(name = "export")]
+    Export {
+        name: Option<String>,
+
+        #[arg(short = 'o', long = "out", value_name = "FILE")]
+        output: Option<String>,
+
+        #[arg(long = "der")]
+        der: bool,
+
+        #[arg(long = "force")]
+        force: bool,
+    },
+
+    #[clap(name = "import")]
+    Import {
+        name: String,
+
+        #[arg(long = "certfile", value_name = "FILE")]
+        certfile: String,
+
+        #[arg(long = "keyfile", value_name = "FILE")]
+        keyfile: Option<String>,
+
+        #[arg(long = "default")]
+        make_default: bool,
+
+        #[arg(short = 'i', long = "install")]
+        install: bool,
+
+        #[arg(long = "protect-ca")]
+        protect_ca: bool,
+    },
+
+    #[clap(name = "delete")]
+    Delete {
+        name: String,
+
+        #[arg(long = "yes")]
+        yes: bool,
+    },
+
+    #[clap(name = "rotate")]
+    Rotate {
+        name: Option<String>,
+
+        #[arg(long = "cn", conflicts_with = "finish")]
+        commonname: Option<String>,
+
+        #[arg(
+            long = "grace-days",
+            value_name = "DAYS",
+            default_value_t = 30,
+            conflicts_with = "finish"
+        )]
+        grace_days: u32,
+
+        #[arg(long = "no-install", conflicts_with = "finish")]
+        no_install: bool,
+
+        #[arg(long = "finish")]
+        finish: bool,
+
+        #[arg(long = "now", requires = "finish")]
+        now: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum OcspCommands {
+    #[clap(name = "serve")]
+    Serve {
+        #[arg(long = "host", default_value = "127.0.0.1")]
+        host: String,
+
+        #[arg(short = 'p', long = "port", default_value_t = 8888)]
+        port: u16,
+
+        #[arg(long = "responder-cert", requires = "responder_key")]
+        responder_cert: Option<String>,
+
+        #[arg(long = "responder-key", requires = "responder_cert")]
+        responder_key: Option<String>,
+
+        #[arg(long = "delegate", conflicts_with = "responder_cert")]
+        delegate: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum AcmeCommands {
+    #[clap(name = "serve")]
+    Serve {
+        #[arg(long = "host", default_value = "127.0.0.1")]
+        host: String,
+
+        #[arg(short = 'p', long = "port", default_value_t = 14000)]
+        port: u16,
+
+        #[arg(long = "http-01-port", value_name = "PORT", default_value_t = 80)]
+        http_01_port: u16,
+
+        #[arg(long = "dns-01")]
+        dns_01: bool,
+
+        #[arg(long = "crl-url", value_name = "URL|FILE")]
+        crl_url: Option<String>,
+
+        #[arg(long = "ocsp-url", value_name = "URL")]
+        ocsp_url: Option<String>,
     },
 }
 
@@ -67,7 +457,11 @@ fn main() {
         }
     }
 
-    let args: CLI = CLI::parse_from(args);
+    let args: Cli = Cli::parse_from(args);
+    // Must happen before anything reads DATA_DIR
+    if let Some(ca_dir) = &args.ca_dir {
+        env::set_var(CAROOT_ENV, ca_dir);
+    }
 
     if let Some(command) = args.command {
         match command {
@@ -81,16 +475,24 @@ fn main() {
                 commonname,
                 state,
                 output,
+                cert_file,
+                key_file,
                 request,
                 install,
+                bundles,
+                encrypt_key,
+                passphrase_file,
+                protect_ca,
+                force,
+                crl_url,
+                ocsp_url,
+                ca,
             } => {
-                if certfile.is_some() != keyfile.is_some() {
-                    if certfile.is_some() {
-                        eprintln!(
-                            "Error: Please provide corresponding `--keyfile` to the certificate provided"
-                        );
-                        std::process::exit(1);
-                    }
+                // Like `--ca-dir`, this has to be in place before the CA is looked up
+                if let Some(ca) = &ca {
+                    env::set_var(CA_NAME_ENV, ca);
+                }
+                if keyfile.is_some() && certfile.is_none() {
                     eprintln!(
                         "Error: Please provide corresponding `--certfile` to the keyfile provided"
                     );
@@ -107,17 +509,385 @@ fn main() {
                     std::process::exit(1);
                 }
 
+                // A fixed file name would make every domain overwrite the previous one
+                for (flag, template) in [("--cert-file", &cert_file), ("--key-file", &key_file)] {
+                    if let Some(template) = template {
+                        if domains.len() > 1 && template != "-" && !template.contains("{domain}") {
+                            eprintln!("Error: `{}` needs a `{{domain}}` placeholder when generating for several domains.", flag);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+
                 if request && install {
                     //CORRECT THIS
                     eprintln!("Error: `--req-only` and `install` are incompatible. You can't generate requests from a request certificate.");
                     std::process::exit(1);
                 }
 
-                let _ = generate(
-                    domains, noca, csr, certfile, keyfile, country, commonname, state, output,
-                    request, install,
-                );
+                let key_passphrase: Option<String> = if encrypt_key || passphrase_file.is_some() {
+                    match read_key_passphrase(&passphrase_file) {
+                        Ok(passphrase) => Some(passphrase),
+                        Err(err) => {
+                            eprintln!("Error: Reading passphrase for private keys: {}", err);
+                            std::process::exit(1);
+                        }
+                    }
+                } else {
+                    None
+                };
+
+                match generate(
+                    domains,
+                    noca,
+                    csr,
+                    certfile,
+                    keyfile,
+                    country,
+                    commonname,
+                    state,
+                    OutputFiles {
+                        output,
+                        cert_file,
+                        key_file,
+                    },
+                    request,
+                    install,
+                    bundles,
+                    key_passphrase,
+                    protect_ca,
+                    force,
+                    crl_url,
+                    ocsp_url,
+                ) {
+                    Ok(true) => {}
+                    Ok(false) => std::process::exit(1),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        std::process::exit(1);
+                    }
+                }
             }
+            Commands::Sign {
+                csr_dir,
+                output,
+                certfile,
+                keyfile,
+                force,
+                crl_url,
+                ocsp_url,
+            } => {
+                if keyfile.is_some() && certfile.is_none() {
+                    eprintln!(
+                        "Error: Please provide corresponding `--certfile` to the keyfile provided"
+                    );
+                    std::process::exit(1);
+                }
+
+                match sign(csr_dir, output, certfile, keyfile, force, crl_url, ocsp_url) {
+                    Ok(true) => {}
+                    Ok(false) => std::process::exit(1),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            Commands::Inspect { files } => match inspect(files) {
+                Ok(true) => {}
+                Ok(false) => std::process::exit(1),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            },
+            Commands::Verify {
+                cert,
+                host,
+                key,
+                ca,
+            } => match verify(cert, host, key, ca) {
+                Ok(true) => {}
+                Ok(false) => std::process::exit(1),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            },
+            Commands::List {
+                domain,
+                expiring,
+                ca,
+            } => {
+                if let Err(err) = list(domain, expiring, ca) {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+            Commands::Renew {
+                files,
+                expiring,
+                reuse_key,
+                encrypt_key,
+                passphrase_file,
+            } => {
+                if files.is_empty() && expiring.is_none() {
+                    eprintln!("Error: Pass the certificates to renew or `--expiring <DAYS>`.");
+                    std::process::exit(1);
+                }
+
+                let encrypt: bool = encrypt_key || passphrase_file.is_some();
+                if reuse_key && encrypt {
+                    eprintln!("Error: `--reuse-key` keeps the existing key files, so `--encrypt-key` and `--passphrase-file` do not apply.");
+                    std::process::exit(1);
+                }
+
+                let key_passphrase: Option<String> = if encrypt {
+                    match read_key_passphrase(&passphrase_file) {
+                        Ok(passphrase) => Some(passphrase),
+                        Err(err) => {
+                            eprintln!("Error: Reading passphrase for private keys: {}", err);
+                            std::process::exit(1);
+                        }
+                    }
+                } else {
+                    None
+                };
+
+                match renew(files, expiring, reuse_key, key_passphrase) {
+                    Ok(true) => {}
+                    Ok(false) => std::process::exit(1),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            Commands::Revoke { target, reason } => match revoke(target, reason) {
+                Ok(true) => {}
+                Ok(false) => std::process::exit(1),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            },
+            Commands::Crl {
+                output,
+                next_update,
+                der,
+                force,
+                previous,
+            } => match crl(output, next_update, der, force, previous) {
+                Ok(true) => {}
+                Ok(false) => std::process::exit(1),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            },
+            Commands::Ocsp {
+                command:
+                    OcspCommands::Serve {
+                        host,
+                        port,
+                        responder_cert,
+                        responder_key,
+                        delegate,
+                    },
+            } => {
+                if let Err(err) = serve_ocsp(host, port, responder_cert, responder_key, delegate) {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+            Commands::Acme {
+                command:
+                    AcmeCommands::Serve {
+                        host,
+                        port,
+                        http_01_port,
+                        dns_01,
+                        crl_url,
+                        ocsp_url,
+                    },
+            } => {
+                if let Err(err) = serve_acme(host, port, http_01_port, dns_01, crl_url, ocsp_url) {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+            Commands::ServeApi {
+                host,
+                port,
+                tls_name,
+                token_file,
+                client_ca,
+                plain_http,
+                crl_url,
+                ocsp_url,
+            } => {
+                if let Err(err) = serve_api(
+                    host, port, tls_name, token_file, client_ca, plain_http, crl_url, ocsp_url,
+                ) {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+            Commands::Install { ca } => match install(ca) {
+                Ok(true) => {}
+                Ok(false) => std::process::exit(1),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            },
+            Commands::Uninstall { ca } => match uninstall(ca) {
+                Ok(true) => {}
+                Ok(false) => std::process::exit(1),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            },
+            Commands::Agent { ttl, lock } => {
+                if lock {
+                    match lock_agent() {
+                        Ok(()) => println!("Forgot all keys cached in vanish agent 🔒"),
+                        Err(err) => {
+                            eprintln!("Error: Reaching vanish agent: {}", err);
+                            std::process::exit(1);
+                        }
+                    }
+                } else if let Err(err) = run_agent(ttl) {
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                }
+            }
+            Commands::Caroot => caroot(),
+            Commands::Ca { command } => match command {
+                CaCommands::Create {
+                    name,
+                    key_type,
+                    validity_days,
+                    name_constraints,
+                    country,
+                    commonname,
+                    state,
+                    make_default,
+                    install,
+                    protect_ca,
+                } => {
+                    // The new CA is saved as the active one
+                    env::set_var(CA_NAME_ENV, &name);
+                    match create_ca(
+                        name,
+                        key_type,
+                        validity_days,
+                        name_constraints,
+                        country,
+                        commonname,
+                        state,
+                        make_default,
+                        install,
+                        protect_ca,
+                    ) {
+                        Ok(true) => {}
+                        Ok(false) => std::process::exit(1),
+                        Err(err) => {
+                            eprintln!("{}", err);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                CaCommands::List => {
+                    if let Err(err) = list_cas() {
+                        eprintln!("{}", err);
+                        std::process::exit(1);
+                    }
+                }
+                CaCommands::Default { name } => match set_default(name) {
+                    Ok(true) => {}
+                    Ok(false) => std::process::exit(1),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        std::process::exit(1);
+                    }
+                },
+                CaCommands::Show { name } => match show_ca(name) {
+                    Ok(true) => {}
+                    Ok(false) => std::process::exit(1),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        std::process::exit(1);
+                    }
+                },
+                CaCommands::Export {
+                    name,
+                    output,
+                    der,
+                    force,
+                } => match export_ca(name, output, der, force) {
+                    Ok(true) => {}
+                    Ok(false) => std::process::exit(1),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        std::process::exit(1);
+                    }
+                },
+                CaCommands::Import {
+                    name,
+                    certfile,
+                    keyfile,
+                    make_default,
+                    install,
+                    protect_ca,
+                } => {
+                    // The imported CA is saved as the active one
+                    env::set_var(CA_NAME_ENV, &name);
+                    match import_ca(name, certfile, keyfile, make_default, install, protect_ca) {
+                        Ok(true) => {}
+                        Ok(false) => std::process::exit(1),
+                        Err(err) => {
+                            eprintln!("{}", err);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                CaCommands::Rotate {
+                    name,
+                    commonname,
+                    grace_days,
+                    no_install,
+                    finish,
+                    now,
+                } => {
+                    // The key of the CA being rotated is loaded as the active one
+                    if let Some(name) = &name {
+                        env::set_var(CA_NAME_ENV, name);
+                    }
+                    let result: Result<bool, Box<dyn std::error::Error>> = if finish {
+                        finish_rotation(name, now)
+                    } else {
+                        rotate_ca(name, commonname, grace_days, !no_install)
+                    };
+                    match result {
+                        Ok(true) => {}
+                        Ok(false) => std::process::exit(1),
+                        Err(err) => {
+                            eprintln!("{}", err);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                CaCommands::Delete { name, yes } => match delete_ca(name, yes) {
+                    Ok(true) => {}
+                    Ok(false) => std::process::exit(1),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        std::process::exit(1);
+                    }
+                },
+            },
         }
     }
 }
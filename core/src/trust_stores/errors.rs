@@ -3,6 +3,7 @@ use openssl::error::ErrorStack;
 use std::{env::VarError, error::Error, fmt, io};
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum TrustStoreError {
     PEMFileCreationError(io::Error),
     PEMEncodingError(ErrorStack),
@@ -138,15 +138,19 @@ impl FirefoxTrustStore {
             }
         }
 
-        if cert_dirs.is_empty() {
+        Ok(cert_dirs)
+    }
+
+    pub fn install_firefox_certificates(&self, cert_paths: Vec<PathBuf>) -> bool {
+        if cert_paths.is_empty() {
             eprintln!("No directories containing certificate databases were found for any of your Firefox Profiles.");
-            std::process::exit(1);
-        } else {
-            Ok(cert_dirs)
+            return false;
         }
-    }
+        let Some(path) = &self.certutil_path else {
+            eprintln!("{}: No certutil found. Please install!", "Error".red());
+            return false;
+        };
 
-    pub fn install_firefox_certificates(&self, cert_paths: Vec<PathBuf>) {
         let all_installed: bool = cert_paths.iter().all(|cert_dir: &PathBuf| {
             match self.is_certificate_installed(cert_dir) {
                 Ok(true) => true,
@@ -160,42 +164,90 @@ impl FirefoxTrustStore {
                 "{}: Certificate already installed in all Firefox profiles ✅.",
                 "Info".blue()
             );
-            return;
-        } else {
-            match &self.certutil_path {
-                Some(path) => {
-                    for cert_dir in cert_paths {
-                        if let Ok(true) = self.is_certificate_installed(&cert_dir) {
-                            continue;
-                        }
+            return true;
+        }
 
-                        let cmd_result: Result<ExitStatus, io::Error> = Command::new(path)
-                            .arg("-A")
-                            .arg("-d")
-                            .arg(cert_dir.to_str().unwrap())
-                            .arg("-t")
-                            .arg("C,,")
-                            .arg("-n")
-                            .arg(&self.ca_unique_name)
-                            .arg("-i")
-                            .arg(&self.vanish_ca_path)
-                            .stdout(Stdio::null())
-                            .status();
-
-                        match cmd_result {
-                            Ok(_) => {}
-                            Err(err) => {
-                                eprintln!("{}: executing certutil: {:?}", "Error".red(), err);
-                            }
-                        }
-                    }
-                    println!("Certificate successfully installed in all Firefox profiles ✅.");
+        let mut success: bool = true;
+        for cert_dir in cert_paths {
+            if let Ok(true) = self.is_certificate_installed(&cert_dir) {
+                continue;
+            }
+
+            let cmd_result: Result<ExitStatus, io::Error> = Command::new(path)
+                .arg("-A")
+                .arg("-d")
+                .arg(cert_dir.to_str().unwrap())
+                .arg("-t")
+                .arg("C,,")
+                .arg("-n")
+                .arg(&self.ca_unique_name)
+                .arg("-i")
+                .arg(&self.vanish_ca_path)
+                .stdout(Stdio::null())
+                .status();
+
+            match cmd_result {
+                Ok(status) if status.success() => {}
+                Ok(_) => {
+                    eprintln!(
+                        "{}: certutil could not add the certificate to {}",
+                        "Error".red(),
+                        cert_dir.display()
+                    );
+                    success = false;
                 }
-                None => {
-                    eprint!("No certutil found. Please install!");
-                    exit(1);
+                Err(err) => {
+                    eprintln!("{}: executing certutil: {:?}", "Error".red(), err);
+                    success = false;
                 }
-            };
+            }
+        }
+        if success {
+            println!("Certificate successfully installed in all Firefox profiles ✅.");
+        }
+        success
+    }
+
+    pub fn uninstall_firefox_certificates(&self, cert_paths: Vec<PathBuf>) -> bool {
+        let Some(certutil) = &self.certutil_path else {
+            println!("   - Firefox: certutil not found, nothing to remove ➖");
+            return true;
+        };
+
+        let mut success: bool = true;
+        let mut removed: usize = 0;
+        for cert_dir in cert_paths {
+            if !matches!(self.is_certificate_installed(&cert_dir), Ok(true)) {
+                continue;
+            }
+            let cmd_result: Result<ExitStatus, io::Error> = Command::new(certutil)
+                .arg("-D")
+                .arg("-d")
+                .arg(cert_dir.to_str().unwrap())
+                .arg("-n")
+                .arg(&self.ca_unique_name)
+                .stdout(Stdio::null())
+                .status();
+
+            match cmd_result {
+                Ok(status) if status.success() => {
+                    println!("   - Firefox {}: removed ✅", cert_dir.display());
+                    removed += 1;
+                }
+                Ok(_) => {
+                    println!("   - Firefox {}: removing ❌", cert_dir.display());
+                    success = false;
+                }
+                Err(err) => {
+                    println!("   - Firefox {}: removing ❌ {:?}", cert_dir.display(), err);
+                    success = false;
+                }
+            }
+        }
+
+        if removed == 0 && success {
+            println!("   - Firefox: not installed ➖");
         }
+        success
     }
 }
@@ -1,4 +1,5 @@
 use super::errors::TrustStoreError;
+use crate::x509::encoding::read_certificate;
 use colored::*;
 use openssl::error::ErrorStack;
 use openssl::x509::X509;
@@ -22,13 +23,9 @@ impl CAValue {
             PossibleStores::Other,
         ];
 
-        for store in stores {
-            if fs::metadata(store.get_path()).is_ok() {
-                return Some(store);
-            }
-        }
-
-        None
+        stores
+            .into_iter()
+            .find(|store: &PossibleStores| fs::metadata(store.get_path()).is_ok())
     }
 
     fn is_certificate_installed(&self, pem_path: &Path) -> bool {
@@ -44,29 +41,78 @@ impl CAValue {
         }
     }
 
-    pub fn install_certificate(&self) -> Result<(), TrustStoreError> {
+    pub fn install_certificate(&self) -> Result<bool, TrustStoreError> {
         let store: Option<PossibleStores> = CAValue::get_available_path();
         match store {
             Some(store) => {
                 let path: String = store.get_path();
                 let pem_path: PathBuf =
-                    Path::new(&path).join(format!("ca_{}.pem", self.ca_uniques_name));
+                    Path::new(&path).join(store.anchor_file_name(&self.ca_uniques_name));
 
                 if self.is_certificate_installed(&pem_path) {
                     println!("{}: Certificate already installed  ✅.", "Info".blue(),);
-                    return Ok(());
+                    return Ok(true);
                 }
 
                 self.write_certificate_with_tee(&pem_path)?;
 
-                self.run_update_certs_command(store);
+                Ok(self.run_update_certs_command(store))
             }
             None => {
                 eprintln!("Your system is not supported by Vanish yet.");
+                Ok(false)
             }
         }
+    }
 
-        Ok(())
+    // Anchors are matched by content, so copies under any file name are found
+    pub fn uninstall_certificate(&self) -> Result<bool, TrustStoreError> {
+        let certificate_der: Vec<u8> = self
+            .certificate
+            .to_der()
+            .map_err(|err: ErrorStack| TrustStoreError::PEMEncodingError(err))?;
+        let mut success: bool = true;
+        let mut found: bool = false;
+        for store in CAValue::get_available_stores() {
+            let anchors: Vec<PathBuf> =
+                matching_anchors(Path::new(&store.get_path()), &certificate_der);
+            if anchors.is_empty() {
+                continue;
+            }
+            found = true;
+            for anchor in &anchors {
+                let removed: bool = Command::new("sudo")
+                    .arg("rm")
+                    .arg("-f")
+                    .arg(anchor)
+                    .status()
+                    .map(|status: ExitStatus| status.success())
+                    .unwrap_or(false);
+                if removed {
+                    println!("   - System trust store: removed {} ✅", anchor.display());
+                } else {
+                    println!("   - System trust store: removing {} ❌", anchor.display());
+                    success = false;
+                }
+            }
+            success &= self.run_update_certs_command(store);
+        }
+        if !found {
+            println!("   - System trust store: not installed ➖");
+        }
+        Ok(success)
+    }
+
+    fn get_available_stores() -> Vec<PossibleStores> {
+        [
+            PossibleStores::RedHat,
+            PossibleStores::Debian,
+            PossibleStores::SuSE,
+            PossibleStores::Other,
+        ]
+        .into_iter()
+        .filter(|store: &PossibleStores| fs::metadata(store.get_path()).is_ok())
+        .collect()
     }
 
     fn write_certificate_with_tee(&self, pem_path: &Path) -> Result<(), TrustStoreError> {
@@ -97,7 +143,7 @@ impl CAValue {
             ))?
             .write_all(&cert_pem)
             .map_err(|err: io::Error| {
-                TrustStoreError::PEMFileCreationError(io::Error::new(io::ErrorKind::Other, err))
+                TrustStoreError::PEMFileCreationError(io::Error::other(err))
             })?;
 
         let status = tee_cmd.wait().map_err(|err| {
@@ -117,12 +163,12 @@ impl CAValue {
         Ok(())
     }
 
-    fn run_update_certs_command(&self, store: PossibleStores) {
+    fn run_update_certs_command(&self, store: PossibleStores) -> bool {
         let (cmd, arg) = match store {
             PossibleStores::RedHat => ("sudo", "update-ca-trust"),
             PossibleStores::Debian => ("sudo", "update-ca-certificates"),
             PossibleStores::SuSE => ("sudo", "update-ca-certificates"),
-            _ => return,
+            _ => return true,
         };
 
         let output: Result<ExitStatus, io::Error> =
@@ -130,18 +176,44 @@ impl CAValue {
 
         match output {
             Ok(status) if status.success() => {
-                println!("{}: {} completed successfully", "Success".green(), arg)
+                println!("{}: {} completed successfully", "Success".green(), arg);
+                true
+            }
+            Ok(_) => {
+                eprintln!(
+                    "{}: {} failed. Please try running the command with elevated permissions.",
+                    "Error".red(),
+                    arg
+                );
+                false
+            }
+            Err(err) => {
+                eprintln!("{}: Failed to run {}: {:?}", "Error".red(), arg, err);
+                false
             }
-            Ok(_) => eprintln!(
-                "{}: {} failed. Please try running the command with elevated permissions.",
-                "Error".red(),
-                arg
-            ),
-            Err(err) => eprintln!("{}: Failed to run {}: {:?}", "Error".red(), arg, err),
         }
     }
 }
 
+// Files in `anchors_dir` holding the certificate, in PEM or DER and under any name
+pub fn matching_anchors(anchors_dir: &Path, certificate_der: &[u8]) -> Vec<PathBuf> {
+    let entries: fs::ReadDir = match fs::read_dir(anchors_dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|entry: Result<fs::DirEntry, io::Error>| entry.ok())
+        .map(|entry: fs::DirEntry| entry.path())
+        .filter(|anchor: &PathBuf| {
+            fs::read(anchor)
+                .ok()
+                .and_then(|data: Vec<u8>| read_certificate(&data, &anchor.to_string_lossy()).ok())
+                .and_then(|cert: X509| cert.to_der().ok())
+                .is_some_and(|der: Vec<u8>| der == certificate_der)
+        })
+        .collect()
+}
+
 enum PossibleStores {
     RedHat,
     Debian,
@@ -150,6 +222,14 @@ enum PossibleStores {
 }
 
 impl PossibleStores {
+    // update-ca-certificates on Debian only picks up `.crt` files
+    fn anchor_file_name(&self, ca_unique_name: &str) -> String {
+        match self {
+            PossibleStores::Debian => format!("ca_{}.crt", ca_unique_name),
+            _ => format!("ca_{}.pem", ca_unique_name),
+        }
+    }
+
     fn get_path(&self) -> String {
         match self {
             PossibleStores::RedHat => "/etc/pki/ca-trust/source/anchors/".to_string(),
@@ -1,25 +1,60 @@
-use openssl::error;
+use super::errors::TrustStoreError;
+use openssl::error::{self, ErrorStack};
 use openssl::x509::X509;
 use plist::{from_bytes, to_writer_xml, Value};
 use std::fs::{self, File};
-use std::io::{self, Read};
+use std::io::{self, Read, Write};
 use std::process::Command;
+use tempfile::NamedTempFile;
 
 pub struct CAValue {
-    ca_cert: X509,
-    ca_root: String,
+    pub ca_uniques_name: String,
+    pub certificate: X509,
 }
 
 impl CAValue {
-    pub fn install_platform(&self) -> Result<bool, String> {
-        let cert_path = format!("{}/{}", self.ca_root, "rootCA.pem");
+    pub fn install_certificate(&self) -> Result<bool, TrustStoreError> {
+        let cert_file: NamedTempFile = self.write_certificate()?;
+        self.install_platform(&cert_file.path().to_string_lossy())
+            .map_err(TrustStoreError::CommandError)
+    }
+
+    pub fn uninstall_certificate(&self) -> Result<bool, TrustStoreError> {
+        let cert_file: NamedTempFile = self.write_certificate()?;
+        match self.uninstall_platform(&cert_file.path().to_string_lossy()) {
+            Ok(_) => {
+                println!("   - System keychain: removed ✅");
+                Ok(true)
+            }
+            Err(err) => {
+                println!("   - System keychain: removing ❌ {}", err);
+                Ok(false)
+            }
+        }
+    }
+
+    // `security` takes the certificate as a file
+    fn write_certificate(&self) -> Result<NamedTempFile, TrustStoreError> {
+        let cert_pem: Vec<u8> = self
+            .certificate
+            .to_pem()
+            .map_err(|err: ErrorStack| TrustStoreError::PEMEncodingError(err))?;
+        let mut cert_file: NamedTempFile =
+            NamedTempFile::new().map_err(TrustStoreError::PEMFileCreationError)?;
+        cert_file
+            .write_all(&cert_pem)
+            .map_err(TrustStoreError::PEMFileCreationError)?;
+        Ok(cert_file)
+    }
+
+    fn install_platform(&self, cert_path: &str) -> Result<bool, String> {
         let output = Command::new("sudo")
             .arg("security")
             .arg("add-trusted-cert")
             .arg("-d")
             .arg("-k")
             .arg("/Library/Keychains/System.keychain")
-            .arg(&cert_path)
+            .arg(cert_path)
             .output()
             .map_err(|e| format!("Failed to execute security add-trusted-cert: {}", e))?;
 
@@ -60,7 +95,7 @@ impl CAValue {
             .map_err(|e: plist::Error| format!("Failed to parse trust settings plist: {}", e))?;
 
         let root_subject_asn1: Vec<u8> = self
-            .ca_cert
+            .certificate
             .subject_name()
             .to_der()
             .map_err(|e: error::ErrorStack| format!("Failed to marshal CA subject: {}", e))?;
@@ -156,13 +191,12 @@ impl CAValue {
         Ok(true)
     }
 
-    pub fn uninstall_platform(&self) -> Result<bool, String> {
-        let cert_path = format!("{}/{}", self.ca_root, "rootCA.pem");
+    fn uninstall_platform(&self, cert_path: &str) -> Result<bool, String> {
         let output = Command::new("sudo")
             .arg("security")
             .arg("remove-trusted-cert")
             .arg("-d")
-            .arg(&cert_path)
+            .arg(cert_path)
             .output()
             .map_err(|e| format!("Failed to execute security remove-trusted-cert: {}", e))?;
 
@@ -87,6 +87,17 @@ impl NSSValue {
     }
 
     pub fn install_nss(&self) -> bool {
+        if !self.profile.has_certutil {
+            if self.for_each_nss_profile(|_profile: &str| {}) == 0 {
+                return true;
+            }
+            eprintln!(
+                "{}: NSS databases were found but certutil is missing. Please install the NSS tools.",
+                "Error".red()
+            );
+            return false;
+        }
+
         let mut all_installed: bool = true;
         let mut any_installed: bool = false;
 
@@ -138,15 +149,25 @@ impl NSSValue {
 
         if installed && self.check_nss() {
             println!("Certificate successfully installed in all NSS (Browser) profiles ✅.");
-            return true;
+            true
         } else {
             eprintln!("{}: Installing in NSS failed. Please report the issue with details about your environment.", "Error".red());
-            return false;
+            false
         }
     }
 
-    pub fn _uninstall_nss(&self) {
+    pub fn uninstall_nss(&self) -> bool {
+        if !self.profile.has_certutil {
+            println!("   - NSS: certutil not found, nothing to remove ➖");
+            return true;
+        }
+
+        let mut success: bool = true;
+        let mut removed: usize = 0;
         self.for_each_nss_profile(|profile: &str| {
+            if !matches!(self.is_certificate_installed(Path::new(profile)), Ok(true)) {
+                return;
+            }
             let cmd: Result<ExitStatus, io::Error> =
                 Command::new(self.profile.certutil_path.as_ref().unwrap())
                     .arg("-D")
@@ -157,10 +178,26 @@ impl NSSValue {
                     .stdout(Stdio::null())
                     .status();
 
-            if let Err(err) = cmd {
-                eprintln!("Error: {:?}", err);
+            match cmd {
+                Ok(status) if status.success() => {
+                    println!("   - NSS {}: removed ✅", profile);
+                    removed += 1;
+                }
+                Ok(_) => {
+                    println!("   - NSS {}: removing ❌", profile);
+                    success = false;
+                }
+                Err(err) => {
+                    println!("   - NSS {}: removing ❌ {:?}", profile, err);
+                    success = false;
+                }
             }
         });
+
+        if removed == 0 && success {
+            println!("   - NSS: not installed ➖");
+        }
+        success
     }
 
     fn for_each_nss_profile<F>(&self, mut f: F) -> usize
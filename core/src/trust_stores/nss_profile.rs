@@ -1,4 +1,4 @@
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use std::process::Command;
 
 pub struct NSSProfile {
@@ -13,13 +13,13 @@ impl NSSProfile {
         let nss_dbs: Vec<String> = vec![
             dirs::home_dir()
                 .map(|p| p.join(".pki/nssdb"))
-                .unwrap_or_else(|| PathBuf::new())
+                .unwrap_or_default()
                 .to_str()
                 .unwrap()
                 .to_string(),
             dirs::home_dir()
                 .map(|p| p.join("snap/chromium/current/.pki/nssdb"))
-                .unwrap_or_else(|| PathBuf::new())
+                .unwrap_or_default()
                 .to_str()
                 .unwrap()
                 .to_string(),
@@ -52,11 +52,9 @@ impl NSSProfile {
                     has_certutil = true;
                 }
             }
-        } else if cfg!(target_os = "linux") {
-            if Command::new("certutil").output().is_ok() {
-                certutil_path = Some("certutil".to_string());
-                has_certutil = true;
-            }
+        } else if cfg!(target_os = "linux") && Command::new("certutil").output().is_ok() {
+            certutil_path = Some("certutil".to_string());
+            has_certutil = true;
         }
 
         Self {
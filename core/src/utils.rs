@@ -1,6 +1,15 @@
 use crate::{
+    agent::unlock_ca_key,
+    ca_store::{
+        active_ca_dir, active_ca_name, previous_ca_cert, DEFAULT_CA_NAME, PREVIOUS_CA_KEY_FILE,
+    },
     errors::{CertKeyPairError, CertKeyResult, SerialNumberError, SerialNumberResult},
-    x509::{self, ca_cert::CACert},
+    ledger::serial_in_use,
+    x509::{
+        ca_cert::{CACert, KeyType},
+        encoding::{is_encrypted_pem, read_certificate},
+        file::{create_directory, loose_permissions, Overwrite, DATA_DIR_MODE},
+    },
 };
 use base64::engine::general_purpose::URL_SAFE;
 use base64::Engine;
@@ -8,18 +17,20 @@ use colored::*;
 use openssl::{
     asn1::Asn1Integer,
     bn::BigNum,
+    ec::{EcGroup, EcKey},
     error::ErrorStack,
-    pkey::{PKey, Private},
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{HasPublic, Id, PKey, PKeyRef, Private},
     rsa::Rsa,
     sha::Sha256,
     x509::X509,
 };
 use std::{
-    error,
-    fs::{self, File},
-    io::{self, Read},
+    env, error, fs, io,
     path::Path,
     process::Output,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use std::{path::PathBuf, process::Command};
 
@@ -31,21 +42,152 @@ pub fn generate_cert_key_pair() -> CertKeyResult<(Rsa<Private>, PKey<Private>)>
     Ok((rsa, pkey))
 }
 
+pub fn generate_private_key(key_type: KeyType) -> CertKeyResult<PKey<Private>> {
+    let rsa_bits: u32 = match key_type {
+        KeyType::Rsa2048 => 2048,
+        KeyType::Rsa4096 => 4096,
+        KeyType::EcdsaP256 => {
+            let group: EcGroup = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)
+                .map_err(|err: ErrorStack| CertKeyPairError::ECGenerationError(err))?;
+            let ec_key: EcKey<Private> = EcKey::generate(&group)
+                .map_err(|err: ErrorStack| CertKeyPairError::ECGenerationError(err))?;
+            return PKey::from_ec_key(ec_key)
+                .map_err(|err: ErrorStack| CertKeyPairError::PKeyCreationError(err));
+        }
+    };
+    let rsa: Rsa<Private> = Rsa::generate(rsa_bits)
+        .map_err(|err: ErrorStack| CertKeyPairError::RSAGenerationError(err))?;
+    PKey::from_rsa(rsa).map_err(|err: ErrorStack| CertKeyPairError::PKeyCreationError(err))
+}
+
+// A new key of the same algorithm and size as an existing one, so renewing a
+// certificate does not quietly change its key type
+pub fn generate_private_key_like<T: HasPublic>(key: &PKeyRef<T>) -> CertKeyResult<PKey<Private>> {
+    match key.id() {
+        Id::RSA => {
+            let rsa: Rsa<Private> = Rsa::generate(key.bits())
+                .map_err(|err: ErrorStack| CertKeyPairError::RSAGenerationError(err))?;
+            PKey::from_rsa(rsa).map_err(|err: ErrorStack| CertKeyPairError::PKeyCreationError(err))
+        }
+        Id::EC => {
+            let ec_key: EcKey<Private> = key
+                .ec_key()
+                .and_then(|public_key: EcKey<T>| EcKey::generate(public_key.group()))
+                .map_err(|err: ErrorStack| CertKeyPairError::ECGenerationError(err))?;
+            PKey::from_ec_key(ec_key)
+                .map_err(|err: ErrorStack| CertKeyPairError::PKeyCreationError(err))
+        }
+        Id::ED25519 => PKey::generate_ed25519()
+            .map_err(|err: ErrorStack| CertKeyPairError::PKeyCreationError(err)),
+        id => Err(CertKeyPairError::UnsupportedKeyError(format!(
+            "key type {}",
+            id.as_raw()
+        ))),
+    }
+}
+
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration: Duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+// (year, month, day, hour, minute, second) in UTC for seconds since the epoch
+pub fn civil_time(seconds: i64) -> (i64, i64, i64, i64, i64, i64) {
+    let days: i64 = seconds.div_euclid(86400) + 719468;
+    let era: i64 = days.div_euclid(146097);
+    let day_of_era: i64 = days.rem_euclid(146097);
+    let year_of_era: i64 =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year: i64 = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index: i64 = (5 * day_of_year + 2) / 153;
+    let day: i64 = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month: i64 = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year: i64 = year_of_era + era * 400 + i64::from(month <= 2);
+    let time_of_day: i64 = seconds.rem_euclid(86400);
+    (
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        time_of_day % 3600 / 60,
+        time_of_day % 60,
+    )
+}
+
+pub fn format_iso8601(seconds: i64) -> String {
+    let (year, month, day, hour, minute, second) = civil_time(seconds);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
+// Inverse of `format_iso8601`, for timestamps Vanish wrote itself
+pub fn parse_iso8601(timestamp: &str) -> Option<i64> {
+    let (date, time) = timestamp.strip_suffix('Z')?.split_once('T')?;
+    let date: Vec<i64> = date
+        .split('-')
+        .map(|part: &str| part.parse::<i64>().ok())
+        .collect::<Option<Vec<i64>>>()?;
+    let time: Vec<i64> = time
+        .split(':')
+        .map(|part: &str| part.parse::<i64>().ok())
+        .collect::<Option<Vec<i64>>>()?;
+    let (&[year, month, day], &[hour, minute, second]) = (&date[..], &time[..]) else {
+        return None;
+    };
+    let year: i64 = if month <= 2 { year - 1 } else { year };
+    let era: i64 = year.div_euclid(400);
+    let year_of_era: i64 = year.rem_euclid(400);
+    let month_index: i64 = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year: i64 = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era: i64 = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days: i64 = era * 146097 + day_of_era - 719468;
+    Some(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
 pub fn generate_certificate_serial_number() -> SerialNumberResult<Asn1Integer> {
     let mut serial_number: BigNum = BigNum::new()
         .map_err(|err: ErrorStack| SerialNumberError::BigNumberInitializationError(err))?;
-    serial_number
-        .rand(128, openssl::bn::MsbOption::MAYBE_ZERO, false)
-        .map_err(|err: ErrorStack| SerialNumberError::RandomBigNumberGenerationError(err))?;
-    Ok(serial_number
-        .to_asn1_integer()
-        .map_err(|err: ErrorStack| SerialNumberError::ConvertBigNumberToASN1Error(err))?)
+    // A 128 bit collision is not expected, but the ledger must never hold a serial twice
+    for _ in 0..8 {
+        serial_number
+            .rand(128, openssl::bn::MsbOption::MAYBE_ZERO, false)
+            .map_err(|err: ErrorStack| SerialNumberError::RandomBigNumberGenerationError(err))?;
+        let serial_hex: String = serial_number
+            .to_hex_str()
+            .map_err(|err: ErrorStack| SerialNumberError::ConvertBigNumberToASN1Error(err))?
+            .to_string();
+        if !serial_in_use(&serial_hex) {
+            return serial_number
+                .to_asn1_integer()
+                .map_err(|err: ErrorStack| SerialNumberError::ConvertBigNumberToASN1Error(err));
+        }
+    }
+    Err(SerialNumberError::SerialNumberCollisionError)
 }
 
 pub fn get_certificates_from_data_dir() -> Option<(X509, PKey<Private>)> {
-    if let Some(ref data_dir) = *x509::DATA_DIR {
+    if let Some(ref data_dir) = active_ca_dir() {
+        let name: String = active_ca_name();
+        // Only the default CA is created on demand, a named one needs `vanish ca create`
+        if name != DEFAULT_CA_NAME && !data_dir.join("ca_cert.pem").exists() {
+            eprintln!(
+                "{}: No CA named \"{}\", create it with `vanish ca create {}`",
+                "Error".red(),
+                name,
+                name
+            );
+            std::process::exit(1);
+        }
         if !data_dir.exists() {
-            if let Err(err) = fs::create_dir_all(data_dir) {
+            if let Err(err) = create_directory(data_dir, DATA_DIR_MODE) {
                 eprintln!(
                     "{}: Failed to create data directory: {}",
                     "Error".red(),
@@ -79,11 +221,39 @@ pub fn get_certificates_from_data_dir() -> Option<(X509, PKey<Private>)> {
             }
         };
 
-        match CACert::load_ca_cert(ca_cert_file_str, ca_key_file_str) {
-            Ok((cert, pkey)) => Some((cert, pkey)),
-            Err(_err) => {
-                eprintln!("{}: Generating new certificates ", "Warning".yellow());
-                None
+        if let Some(mode) = loose_permissions(&ca_keyfile) {
+            eprintln!(
+                "{}: CA key at {} is accessible by other users (mode {:o}), run `chmod 600` on it",
+                "Warning".yellow(),
+                ca_key_file_str,
+                mode
+            );
+        }
+
+        let key_data: Vec<u8> = fs::read(&ca_keyfile).unwrap_or_default();
+        if !is_encrypted_pem(&key_data) {
+            return match CACert::load_ca_cert(ca_cert_file_str, ca_key_file_str) {
+                Ok((cert, pkey)) => Some((cert, pkey)),
+                Err(_err) => {
+                    eprintln!("{}: Generating new certificates ", "Warning".yellow());
+                    None
+                }
+            };
+        }
+
+        // A protected CA exists, so never fall back to creating a new one
+        let cert: X509 = match CACert::load_cert(ca_cert_file_str) {
+            Ok(cert) => cert,
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        };
+        match unlock_ca_key(&cert, &key_data, ca_key_file_str) {
+            Ok(pkey) => Some((cert, pkey)),
+            Err(err) => {
+                eprintln!("{}: Unlocking CA key: {}", "Error".red(), err);
+                std::process::exit(1);
             }
         }
     } else {
@@ -95,11 +265,13 @@ pub fn get_certificates_from_data_dir() -> Option<(X509, PKey<Private>)> {
 pub fn save_generated_cert_key_files(
     cert: &X509,
     key: &PKey<Private>,
+    key_passphrase: Option<&[u8]>,
+    overwrite: Overwrite,
 ) -> Result<(), Box<dyn error::Error>> {
     println!();
-    if let Some(ref data_dir) = *x509::DATA_DIR {
+    if let Some(ref data_dir) = active_ca_dir() {
         if !data_dir.exists() {
-            fs::create_dir_all(data_dir).map_err(|err| {
+            create_directory(data_dir, DATA_DIR_MODE).map_err(|err| {
                 eprintln!(
                     "{}: Failed to create data directory: {}",
                     "Error".red(),
@@ -112,6 +284,27 @@ pub fn save_generated_cert_key_files(
         let ca_certfile: PathBuf = data_dir.join("ca_cert.pem");
         let ca_keyfile: PathBuf = data_dir.join("ca_key.pem");
 
+        // Replacing the root CA invalidates every trust anchor installed from it
+        if ca_certfile.exists() || ca_keyfile.exists() {
+            if overwrite == Overwrite::Refuse {
+                eprintln!(
+                    "{}: A CA already exists in {} but could not be loaded. Replacing it invalidates every installed copy of it.",
+                    "Error".red(),
+                    data_dir.display()
+                );
+                eprintln!(
+                    "{}: Pass `--force` to replace it (the old CA is kept as a backup), then re-install the new CA with `-i`",
+                    "Note".green()
+                );
+                std::process::exit(1);
+            }
+            eprintln!(
+                "{}: Replacing the CA in {}, certificates issued by the old CA will no longer be trusted once the new one is installed",
+                "Warning".yellow(),
+                data_dir.display()
+            );
+        }
+
         let ca_cert_file_str: &str = ca_certfile.to_str().ok_or_else(|| {
             let err: String = "Failed to convert ca_certfile path to string".to_string();
             eprintln!("{}: {}", "Error".red(), err);
@@ -124,7 +317,7 @@ pub fn save_generated_cert_key_files(
             io::Error::new(io::ErrorKind::InvalidInput, err)
         })?;
 
-        match CACert::save_cert(cert, ca_cert_file_str) {
+        match CACert::save_cert(cert, ca_cert_file_str, overwrite) {
             Ok(()) => {
                 println!(
                     "{}: CA Root Certificate saved at: {} 👍",
@@ -137,12 +330,12 @@ pub fn save_generated_cert_key_files(
                 std::process::exit(1);
             }
         };
-        match CACert::save_key(key, ca_key_file_str) {
+        match CACert::save_key(key, ca_key_file_str, key_passphrase, overwrite) {
             Ok(()) => {
                 println!(
                     "{}: CA Root Private Key saved at: {} 👍",
                     "Note".green(),
-                    ca_cert_file_str
+                    ca_key_file_str
                 );
             }
             Err(err) => {
@@ -158,6 +351,67 @@ pub fn save_generated_cert_key_files(
     }
 }
 
+type CAPair = (X509, PKey<Private>);
+
+// The root and key a rotation replaced, while certificates it issued still
+// need their revocations signed by it
+pub fn previous_ca_from_data_dir() -> Result<Option<CAPair>, Box<dyn error::Error>> {
+    let Some(ca_dir) = active_ca_dir() else {
+        return Ok(None);
+    };
+    let Some(cert) = previous_ca_cert(&ca_dir) else {
+        return Ok(None);
+    };
+    let ca_keyfile: PathBuf = ca_dir.join(PREVIOUS_CA_KEY_FILE);
+    let ca_key_file_str: &str = ca_keyfile
+        .to_str()
+        .ok_or("Failed to convert ca_keyfile path to string")?;
+    let key_data: Vec<u8> = fs::read(&ca_keyfile)?;
+    let key: PKey<Private> = if is_encrypted_pem(&key_data) {
+        unlock_ca_key(&cert, &key_data, ca_key_file_str)?
+    } else {
+        CACert::load_key(ca_key_file_str)?
+    };
+    Ok(Some((cert, key)))
+}
+
+// The active CA certificate alone, without unlocking or printing anything
+pub fn data_dir_ca_cert() -> Option<X509> {
+    let ca_certfile: PathBuf = active_ca_dir()?.join("ca_cert.pem");
+    let cert_data: Vec<u8> = fs::read(&ca_certfile).ok()?;
+    read_certificate(&cert_data, ca_certfile.to_str()?).ok()
+}
+
+// Re-encrypts the existing CA key in place, the key itself does not change
+pub fn protect_data_dir_ca_key(
+    key: &PKey<Private>,
+    passphrase: &[u8],
+) -> Result<(), Box<dyn error::Error>> {
+    let ca_keyfile: PathBuf = active_ca_dir()
+        .map(|ca_dir: PathBuf| ca_dir.join("ca_key.pem"))
+        .ok_or("Unable to get Data Directory")?;
+    let ca_key_file_str: &str = ca_keyfile
+        .to_str()
+        .ok_or("Failed to convert ca_keyfile path to string")?;
+    CACert::save_key(key, ca_key_file_str, Some(passphrase), Overwrite::Replace)?;
+    println!();
+    println!(
+        "{}: CA Root Private Key at {} is now protected with a passphrase 🔒",
+        "Note".green(),
+        ca_key_file_str
+    );
+    Ok(())
+}
+
+pub fn ca_key_is_protected() -> bool {
+    match active_ca_dir() {
+        Some(ca_dir) => fs::read(ca_dir.join("ca_key.pem"))
+            .map(|key_data: Vec<u8>| is_encrypted_pem(&key_data))
+            .unwrap_or(false),
+        None => false,
+    }
+}
+
 #[allow(dead_code)]
 pub fn path_exists(path: &str) -> bool {
     Path::new(path).exists()
@@ -171,15 +425,68 @@ pub fn binary_exists(binary: &str) -> bool {
         .unwrap_or(false)
 }
 
-pub fn get_unique_hash(csr_path: &str) -> Result<String, io::Error> {
-    let mut file: File = File::open(csr_path)?;
-    let mut csr_contents: Vec<u8> = Vec::new();
-    file.read_to_end(&mut csr_contents)?;
+pub fn prompt_passphrase(prompt: &str) -> Result<String, io::Error> {
+    rpassword::prompt_password(prompt)
+}
+
+pub fn read_key_passphrase(passphrase_file: &Option<String>) -> Result<String, io::Error> {
+    match passphrase_file {
+        Some(passphrase_file) => {
+            let contents: String = fs::read_to_string(passphrase_file)?;
+            let passphrase: String = contents.lines().next().unwrap_or_default().to_string();
+            if passphrase.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Passphrase for private keys can not be empty",
+                ));
+            }
+            Ok(passphrase)
+        }
+        None => read_new_passphrase("VANISH_KEY_PASSPHRASE", "private keys"),
+    }
+}
+
+pub fn read_new_passphrase(env_var: &str, purpose: &str) -> Result<String, io::Error> {
+    let passphrase: String = match env::var(env_var) {
+        Ok(passphrase) => passphrase,
+        Err(_) => {
+            let passphrase: String =
+                prompt_passphrase(&format!("Enter passphrase for {}: ", purpose))?;
+            if prompt_passphrase("Confirm passphrase: ")? != passphrase {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Passphrases do not match",
+                ));
+            }
+            passphrase
+        }
+    };
+    if passphrase.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Passphrase for {} can not be empty", purpose),
+        ));
+    }
+    Ok(passphrase)
+}
+
+pub fn certificate_fingerprint(cert: &X509) -> Result<String, ErrorStack> {
+    Ok(cert
+        .digest(MessageDigest::sha256())?
+        .iter()
+        .map(|byte: &u8| format!("{:02x}", byte))
+        .collect())
+}
+
+// The nickname a CA is trusted under. The PEM is byte for byte what
+// `ca_cert.pem` holds, so CAs installed from that file keep their nickname
+pub fn ca_unique_name(cert: &X509) -> Result<String, ErrorStack> {
+    Ok(unique_hash(&cert.to_pem()?))
+}
+
+fn unique_hash(data: &[u8]) -> String {
     let mut hasher: Sha256 = Sha256::new();
-    hasher.update(&csr_contents);
+    hasher.update(data);
     let result: [u8; 32] = hasher.finish();
-    let mut unique_name: String = URL_SAFE.encode(result);
-    unique_name = unique_name.trim_end_matches('=').to_string();
-
-    Ok(unique_name)
+    URL_SAFE.encode(result).trim_end_matches('=').to_string()
 }
@@ -1,54 +1,60 @@
-mod get_unique_hash {
-    use crate::utils::get_unique_hash;
-    use std::io::Write;
+mod ca_unique_name {
+    use crate::{
+        utils::ca_unique_name,
+        x509::{ca_cert::CACert, distinguished_name::DistinguishedName, Certificate},
+    };
+    use base64::{engine::general_purpose::URL_SAFE, Engine};
+    use openssl::{sha::sha256, x509::X509};
+    use std::{fs, io::Write};
     use tempfile::NamedTempFile;
 
-    const CSR_DATA: &[u8; 954] = b"-----BEGIN CERTIFICATE REQUEST-----\n
-MIICfTCCAWUCAQAwETEPMA0GA1UECgwGVmFuaXNoMIIBIjANBgkqhkiG9w0BAQEF\n
-AAOCAQ8AMIIBCgKCAQEAu6yXUMzVfqCBGOJh50MziGFKShMWQt7iIYe2pAeFBoeo\n
-uuwuVq4xSdqpgTFvkhekH8tpTX8n+Y8pY7rur6Nosi/G2A6AxvvwG/ahDuHYg9Il\n
-XmTYrqKnK32n/8ALPT6W3J6wKq6Nm2y5yXhGX/fSVSrmNw78hl0osKLUa7dLmCs0\n
-zBghTfwjSz+qkUXvwEKuGPqgXLh6hirjiNhbtbR1XgV3p/w/7u3qH7fJcDlrhQDW\n
-QhJnxcdYGCMGGcIzEdGaNzTHNAlpU7lLR2FTEjk7OCYwDgzLFYy6QFdeK2swdkfn\n
-OlipnXQfzFUEbTKDQUckLafodJBzLhqyKCulDPxqoQIDAQABoCcwJQYJKoZIhvcN\n
-AQkOMRgwFjAUBgNVHREEDTALgglsb2NhbGhvc3QwDQYJKoZIhvcNAQELBQADggEB\n
-ACwI/gFmKb5oAahbq2LlnkGtAwmbV67xgxTmXXUIvJs37cz4Rde/CdSDct5g7mYh\n
-8EghzWLEfHmg9K9kMiV58HHYIkcQ421v+pPub3wDVU3yvq3/DpIqHDBs/Wz3nFrI\n
-GZEfI1cwUTqTkK9Lk2z3waUkt8adk4pvof01tiEq9A06GjK5tJfUIGef4lkOfzKE\n
-ld7i9gqfMk+9pmSjBYVG0YCfc0ye/oPhmSNtFc9dTlBb9bqcN3C0dgqdhM3E/4Gz\n
-YBni+0GjtUKZT9NsekFh/O14P6j9MrtbHsMLWU6KXgHc+t75kD+XKy2U/IPd8nzt\n
-ncq6d7m7pfbZs5a+gBnsPqA=\n
------END CERTIFICATE REQUEST-----";
-
-    #[test]
-    fn returns_ok() {
-        let mut temp_file: NamedTempFile = NamedTempFile::new().unwrap();
-        temp_file.write_all(CSR_DATA).unwrap();
-        let csr_path: &str = temp_file.path().to_str().unwrap();
-
-        let hash_result: Result<String, std::io::Error> = get_unique_hash(csr_path);
-        assert!(hash_result.is_ok(), "Expected Ok result, got an error");
+    fn ca() -> X509 {
+        let distinguished_name: DistinguishedName = DistinguishedName {
+            common_name: None,
+            organization: "Vanish".to_string(),
+            country: None,
+            state: None,
+        };
+        let (ca_cert, _ca_key) = CACert::new(distinguished_name)
+            .unwrap()
+            .generate_certificate()
+            .unwrap();
+        ca_cert
     }
 
     #[test]
     fn hash_length() {
-        let mut temp_file: NamedTempFile = NamedTempFile::new().unwrap();
-        temp_file.write_all(CSR_DATA).unwrap();
-        let csr_path: &str = temp_file.path().to_str().unwrap();
-
-        let unique_hash: String = get_unique_hash(csr_path).unwrap();
-        assert_eq!(unique_hash.len(), 43, "Expected hash length of 43");
+        let unique_name: String = ca_unique_name(&ca()).unwrap();
+        assert_eq!(unique_name.len(), 43, "Expected hash length of 43");
+        assert!(unique_name
+            .chars()
+            .all(|c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
     }
 
     #[test]
     fn consistent() {
+        let ca_cert: X509 = ca();
+        let name1: String = ca_unique_name(&ca_cert).unwrap();
+        let name2: String = ca_unique_name(&ca_cert).unwrap();
+        assert_eq!(name1, name2, "Names for the same CA should be equal");
+        assert_ne!(name1, ca_unique_name(&ca()).unwrap());
+    }
+
+    #[test]
+    fn ca_nickname_matches_installed_ca_file() {
+        let ca_cert: X509 = ca();
         let mut temp_file: NamedTempFile = NamedTempFile::new().unwrap();
-        temp_file.write_all(CSR_DATA).unwrap();
-        let csr_path: &str = temp_file.path().to_str().unwrap();
+        temp_file.write_all(&ca_cert.to_pem().unwrap()).unwrap();
+        let installed: Vec<u8> = fs::read(temp_file.path()).unwrap();
 
-        let hash1: String = get_unique_hash(csr_path).unwrap();
-        let hash2: String = get_unique_hash(csr_path).unwrap();
-        assert_eq!(hash1, hash2, "Hashes for the same file should be equal");
+        assert_eq!(
+            ca_unique_name(&ca_cert).unwrap(),
+            URL_SAFE
+                .encode(sha256(&installed))
+                .trim_end_matches('=')
+                .to_string(),
+            "CAs installed from ca_cert.pem should keep their nickname"
+        );
     }
 }
 
@@ -79,3 +85,841 @@ mod serial_number {
         );
     }
 }
+
+mod renewed_keys {
+    use crate::utils::generate_private_key_like;
+    use openssl::{
+        ec::{EcGroup, EcKey},
+        nid::Nid,
+        pkey::{Id, PKey, Private},
+        rsa::Rsa,
+    };
+
+    #[test]
+    fn keep_the_algorithm_and_size_of_the_old_key() {
+        let rsa: PKey<Private> = PKey::from_rsa(Rsa::generate(3072).unwrap()).unwrap();
+        let renewed: PKey<Private> = generate_private_key_like(&rsa).unwrap();
+        assert_eq!(renewed.id(), Id::RSA);
+        assert_eq!(renewed.bits(), 3072);
+        assert!(!renewed.public_eq(&rsa));
+
+        let group: EcGroup = EcGroup::from_curve_name(Nid::SECP384R1).unwrap();
+        let ec: PKey<Private> = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let renewed: PKey<Private> = generate_private_key_like(&ec).unwrap();
+        assert_eq!(renewed.id(), Id::EC);
+        assert_eq!(
+            renewed.ec_key().unwrap().group().curve_name(),
+            Some(Nid::SECP384R1)
+        );
+    }
+}
+
+mod output_files {
+    use crate::commands::utils::OutputFiles;
+
+    #[test]
+    fn renders_domain_templates_inside_output_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: OutputFiles = OutputFiles {
+            output: Some(dir.path().to_str().unwrap().to_string()),
+            cert_file: Some("{domain}/{domain}.crt".to_string()),
+            key_file: Some("-".to_string()),
+        };
+        let cert_path: String = files.cert_path("example.test", "unused.pem").unwrap();
+        assert_eq!(
+            cert_path,
+            dir.path()
+                .join("example.test/example.test.crt")
+                .to_str()
+                .unwrap()
+        );
+        assert!(dir.path().join("example.test").is_dir());
+        assert_eq!(files.key_path("example.test", "unused.pem").unwrap(), "-");
+        assert!(files.uses_stdout());
+    }
+}
+
+mod sign {
+    use crate::{
+        commands::sign::sign,
+        x509::{
+            ca_cert::CACert, ca_req::CAReq, distinguished_name::DistinguishedName, Certificate,
+        },
+    };
+    use std::{fs, path::Path};
+    use tempfile::TempDir;
+
+    fn distinguished_name(common_name: &str) -> DistinguishedName {
+        DistinguishedName {
+            common_name: Some(common_name.to_string()),
+            organization: "Vanish".to_string(),
+            country: None,
+            state: None,
+        }
+    }
+
+    fn request_der(common_name: &str) -> Vec<u8> {
+        let (csr, _key) = CAReq::new(distinguished_name(common_name))
+            .unwrap()
+            .generate_certificate()
+            .unwrap();
+        csr.to_der().unwrap()
+    }
+
+    fn signed_files(dir: &Path) -> Vec<String> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name: &String| name.ends_with(".pem"))
+            .collect()
+    }
+
+    #[test]
+    fn signs_each_valid_request_and_fails_the_run_for_the_rest() {
+        let ca_dir: TempDir = tempfile::tempdir().unwrap();
+        let (ca_cert, ca_key) = CACert::new(distinguished_name("Vanish Test CA"))
+            .unwrap()
+            .generate_certificate()
+            .unwrap();
+        let certfile: String = ca_dir.path().join("ca.pem").display().to_string();
+        let keyfile: String = ca_dir.path().join("ca-key.pem").display().to_string();
+        fs::write(&certfile, ca_cert.to_pem().unwrap()).unwrap();
+        fs::write(&keyfile, ca_key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+        let sign_dir = |requests: &TempDir, output: &TempDir| -> bool {
+            sign(
+                requests.path().display().to_string(),
+                Some(output.path().display().to_string()),
+                Some(certfile.clone()),
+                Some(keyfile.clone()),
+                false,
+                None,
+                None,
+            )
+            .unwrap()
+        };
+
+        let requests: TempDir = tempfile::tempdir().unwrap();
+        fs::write(requests.path().join("a.der"), request_der("a.test")).unwrap();
+        fs::write(requests.path().join("b.der"), request_der("b.test")).unwrap();
+        let output: TempDir = tempfile::tempdir().unwrap();
+        assert!(sign_dir(&requests, &output));
+        assert_eq!(signed_files(output.path()).len(), 2);
+
+        // A forged signature and a file that is no request at all fail on
+        // their own, the valid request is still signed
+        let mut forged: Vec<u8> = request_der("forged.test");
+        let last: usize = forged.len() - 1;
+        forged[last] ^= 0xff;
+        fs::write(requests.path().join("c.der"), forged).unwrap();
+        fs::write(requests.path().join("d.txt"), b"not a request").unwrap();
+        fs::remove_file(requests.path().join("b.der")).unwrap();
+        let output: TempDir = tempfile::tempdir().unwrap();
+        assert!(!sign_dir(&requests, &output));
+        assert_eq!(signed_files(output.path()).len(), 1);
+    }
+}
+
+mod verify {
+    use crate::commands::verify::host_matches;
+
+    #[test]
+    fn matches_wildcards_on_one_left_most_label() {
+        assert!(host_matches("api.test", "API.test"));
+        assert!(host_matches("*.example.test", "api.example.test"));
+        assert!(!host_matches("*.example.test", "example.test"));
+        assert!(!host_matches("*.example.test", "a.api.example.test"));
+        assert!(!host_matches("*.test", "api.test"));
+        assert!(!host_matches("api*.example.test", "api1.example.test"));
+    }
+}
+
+mod ledger {
+    use crate::{
+        ledger::{ledger_path, Ledger, LedgerEntry, LEDGER_FILE},
+        utils::parse_iso8601,
+    };
+    use std::{path::PathBuf, thread, time::Duration};
+
+    fn entry(serial: &str) -> LedgerEntry {
+        LedgerEntry {
+            serial: serial.to_string(),
+            subject: "CN=localhost, O=Vanish".to_string(),
+            subject_alt_names: vec!["DNS:localhost".to_string()],
+            not_before: "2024-01-01T00:00:00Z".to_string(),
+            not_after: "2026-01-01T00:00:00Z".to_string(),
+            key_algorithm: "RSA 2048 bits".to_string(),
+            ca_fingerprint: "00".to_string(),
+            cert_path: ledger_path("localhost.pem").map(|path: &str| path.to_string()),
+            key_path: ledger_path("-").map(|path: &str| path.to_string()),
+            issued_at: "2024-01-01T00:00:00Z".to_string(),
+            revocation: None,
+        }
+    }
+
+    #[test]
+    fn rejects_duplicate_serials() {
+        let mut ledger: Ledger = Ledger::default();
+        assert!(ledger.record(entry("0A1B")).is_ok());
+        assert!(ledger.contains_serial("0a1b"));
+        assert!(ledger.record(entry("0a1b")).is_err());
+        assert!(ledger.record(entry("0A1C")).is_ok());
+        assert_eq!(ledger.certificates.len(), 2);
+        assert_eq!(ledger.certificates[0].key_path, None);
+    }
+
+    #[test]
+    fn counts_days_left_until_expiry() {
+        let mut entry: LedgerEntry = entry("0A1B");
+        let not_after: i64 = parse_iso8601(&entry.not_after).unwrap();
+        assert_eq!(entry.days_left(not_after - 86400 * 10), Some(10));
+        assert_eq!(entry.days_left(not_after + 1), Some(-1));
+        entry.not_after = "soon".to_string();
+        assert_eq!(entry.days_left(not_after), None);
+    }
+
+    #[test]
+    fn keeps_interleaved_updates_from_every_writer() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let path: PathBuf = data_dir.path().join(LEDGER_FILE);
+        let writers: Vec<thread::JoinHandle<()>> = (0..4)
+            .map(|writer: usize| {
+                let path: PathBuf = path.clone();
+                thread::spawn(move || {
+                    for update in 0..5 {
+                        Ledger::update_at(&path, |ledger: &mut Ledger| {
+                            // Gives the other writers time to load the same copy
+                            thread::sleep(Duration::from_millis(5));
+                            ledger.record(entry(&format!("{:02X}{:02X}", writer, update)))
+                        })
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert_eq!(Ledger::load_from(&path).unwrap().certificates.len(), 20);
+    }
+
+    #[test]
+    fn numbers_crls_per_ca_above_the_shared_counter() {
+        // A ledger written when every CA shared one counter
+        let mut ledger: Ledger =
+            serde_json::from_str(r#"{"crl_number": 4, "certificates": []}"#).unwrap();
+        assert_eq!(ledger.next_crl_number("aa"), 5);
+        assert_eq!(ledger.next_crl_number("bb"), 5);
+        assert_eq!(ledger.next_crl_number("bb"), 6);
+        assert_eq!(ledger.next_crl_number("aa"), 6);
+        assert_eq!(ledger.crl_numbers["bb"], 6);
+    }
+}
+
+mod iso8601 {
+    use crate::utils::{format_iso8601, parse_iso8601};
+
+    #[test]
+    fn round_trips_timestamps() {
+        for seconds in [0, 951_782_400, 1_709_210_096, 4_102_444_799] {
+            assert_eq!(parse_iso8601(&format_iso8601(seconds)), Some(seconds));
+        }
+        assert_eq!(parse_iso8601("2024-02-29T12:00:00"), None);
+    }
+}
+
+mod acme_jwk {
+    use crate::acme::jws::{jwk_public_key, jwk_thumbprint};
+    use serde_json::{json, Value};
+
+    #[test]
+    fn thumbprints_the_rfc_7638_example_key() {
+        let jwk: Value = json!({
+            "kty": "RSA",
+            "n": "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw",
+            "e": "AQAB",
+            "alg": "RS256",
+            "kid": "2011-04-29",
+        });
+        assert_eq!(
+            jwk_thumbprint(&jwk).unwrap(),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+        assert!(jwk_public_key(&jwk).is_ok());
+        assert!(jwk_thumbprint(&json!({ "kty": "oct", "k": "AA" })).is_err());
+    }
+}
+
+mod acme_server {
+    use crate::{
+        acme::{
+            jws::{encode, jwk_thumbprint},
+            server::AcmeServer,
+        },
+        commands::utils::RevocationUrls,
+        http::{HttpRequest, HttpResponse},
+        utils::generate_cert_key_pair,
+        x509::{ca_cert::CACert, distinguished_name::DistinguishedName, Certificate},
+    };
+    use openssl::{
+        hash::{hash, MessageDigest},
+        nid::Nid,
+        pkey::{PKey, Private},
+        sign::Signer,
+        stack::Stack,
+        x509::{
+            extension::SubjectAlternativeName, X509Extension, X509NameBuilder, X509Req,
+            X509ReqBuilder,
+        },
+    };
+    use serde_json::{json, Value};
+
+    const BASE_URL: &str = "https://acme.test";
+
+    // Just enough of an RS256 ACME client to drive the server in memory
+    struct Client {
+        server: AcmeServer,
+        key: PKey<Private>,
+        kid: Option<String>,
+        nonce: String,
+    }
+
+    impl Client {
+        fn new() -> Client {
+            let (ca_cert, ca_key) = CACert::new(DistinguishedName {
+                common_name: Some("Vanish ACME Test CA".to_string()),
+                organization: "Vanish".to_string(),
+                country: None,
+                state: None,
+            })
+            .unwrap()
+            .generate_certificate()
+            .unwrap();
+            let mut server: AcmeServer = AcmeServer::new(
+                BASE_URL.to_string(),
+                ca_cert,
+                ca_key,
+                RevocationUrls::default(),
+                80,
+                true,
+            );
+            let response: HttpResponse = server.handle(&request("GET", "/acme/new-nonce", b""));
+            let (_rsa, key) = generate_cert_key_pair().unwrap();
+            Client {
+                server,
+                key,
+                kid: None,
+                nonce: header(&response, "Replay-Nonce"),
+            }
+        }
+
+        fn jwk(&self) -> Value {
+            let rsa = self.key.rsa().unwrap();
+            json!({
+                "kty": "RSA",
+                "n": encode(&rsa.n().to_vec()),
+                "e": encode(&rsa.e().to_vec()),
+            })
+        }
+
+        fn post(&mut self, url: &str, payload: Option<Value>) -> (u16, Value, HttpResponse) {
+            let mut protected: Value = json!({ "alg": "RS256", "nonce": self.nonce, "url": url });
+            match &self.kid {
+                Some(kid) => protected["kid"] = json!(kid),
+                None => protected["jwk"] = self.jwk(),
+            }
+            let protected: String = encode(protected.to_string().as_bytes());
+            let payload: String = payload
+                .map(|payload: Value| encode(payload.to_string().as_bytes()))
+                .unwrap_or_default();
+            let signature: Vec<u8> = Signer::new(MessageDigest::sha256(), &self.key)
+                .unwrap()
+                .sign_oneshot_to_vec(format!("{}.{}", protected, payload).as_bytes())
+                .unwrap();
+            let body: Value = json!({
+                "protected": protected,
+                "payload": payload,
+                "signature": encode(&signature),
+            });
+            let mut post: HttpRequest = request(
+                "POST",
+                url.strip_prefix(BASE_URL).unwrap(),
+                body.to_string().as_bytes(),
+            );
+            post.headers.push((
+                "Content-Type".to_string(),
+                "application/jose+json".to_string(),
+            ));
+            let response: HttpResponse = self.server.handle(&post);
+            self.nonce = header(&response, "Replay-Nonce");
+            let body: Value = serde_json::from_slice(&response.body).unwrap_or(Value::Null);
+            (response.status, body, response)
+        }
+    }
+
+    fn request(method: &str, path: &str, body: &[u8]) -> HttpRequest {
+        HttpRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: Vec::new(),
+            body: body.to_vec(),
+        }
+    }
+
+    fn header(response: &HttpResponse, name: &str) -> String {
+        response
+            .headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+            .unwrap()
+    }
+
+    fn csr_with_ip_san(domain: &str) -> X509Req {
+        let (_rsa, key) = generate_cert_key_pair().unwrap();
+        let mut name: X509NameBuilder = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, domain).unwrap();
+        let mut builder: X509ReqBuilder = X509ReqBuilder::new().unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.set_subject_name(&name.build()).unwrap();
+        let san: X509Extension = SubjectAlternativeName::new()
+            .dns(domain)
+            .ip("127.0.0.1")
+            .build(&builder.x509v3_context(None))
+            .unwrap();
+        let mut extensions: Stack<X509Extension> = Stack::new().unwrap();
+        extensions.push(san).unwrap();
+        builder.add_extensions(&extensions).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        builder.build()
+    }
+
+    #[test]
+    fn refuses_to_finalize_with_names_the_order_did_not_validate() {
+        let mut client: Client = Client::new();
+        let (status, _, response) = client.post(
+            &format!("{}/acme/new-account", BASE_URL),
+            Some(json!({ "termsOfServiceAgreed": true })),
+        );
+        assert_eq!(status, 201);
+        client.kid = Some(header(&response, "Location"));
+
+        let (status, order, _) = client.post(
+            &format!("{}/acme/new-order", BASE_URL),
+            Some(json!({ "identifiers": [{ "type": "dns", "value": "shop.test" }] })),
+        );
+        assert_eq!(status, 201);
+        let (_, authorization, _) = client.post(order["authorizations"][0].as_str().unwrap(), None);
+        let challenge: &Value = authorization["challenges"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|challenge: &&Value| challenge["type"] == "dns-01")
+            .unwrap();
+        let key_authorization: String = format!(
+            "{}.{}",
+            challenge["token"].as_str().unwrap(),
+            jwk_thumbprint(&client.jwk()).unwrap()
+        );
+        let txt_record: Value = json!({
+            "fqdn": "_acme-challenge.shop.test.",
+            "value": encode(&hash(MessageDigest::sha256(), key_authorization.as_bytes()).unwrap()),
+        });
+        client.server.handle(&request(
+            "POST",
+            "/dns/present",
+            txt_record.to_string().as_bytes(),
+        ));
+        let (_, challenge, _) = client.post(challenge["url"].as_str().unwrap(), Some(json!({})));
+        assert_eq!(challenge["status"], "valid");
+
+        // The DNS name was validated, the IP address next to it never was
+        let csr: X509Req = csr_with_ip_san("shop.test");
+        let (status, problem, _) = client.post(
+            order["finalize"].as_str().unwrap(),
+            Some(json!({ "csr": encode(&csr.to_der().unwrap()) })),
+        );
+        assert_eq!(status, 400);
+        assert_eq!(problem["type"], "urn:ietf:params:acme:error:badCSR");
+    }
+}
+
+#[cfg(unix)]
+mod agent {
+    use crate::{
+        agent::{agent_key, client, server},
+        utils::{certificate_fingerprint, generate_cert_key_pair},
+        x509::{ca_cert::CACert, distinguished_name::DistinguishedName, Certificate},
+    };
+    use openssl::{
+        pkey::{PKey, Private},
+        x509::X509,
+    };
+    use std::{os::unix::net::UnixListener, path::PathBuf, thread, time::Duration};
+    use tempfile::TempDir;
+
+    // An agent on a socket of its own, so tests don't meet each other or a real one
+    fn start_agent(ttl: Duration) -> (TempDir, PathBuf) {
+        let dir: TempDir = tempfile::tempdir().unwrap();
+        let socket: PathBuf = dir.path().join("agent.sock");
+        let listener: UnixListener = UnixListener::bind(&socket).unwrap();
+        thread::spawn(move || server::serve(listener, ttl));
+        (dir, socket)
+    }
+
+    fn ca() -> (X509, PKey<Private>) {
+        CACert::new(DistinguishedName {
+            common_name: Some("Vanish Test CA".to_string()),
+            organization: "Vanish".to_string(),
+            country: None,
+            state: None,
+        })
+        .unwrap()
+        .generate_certificate()
+        .unwrap()
+    }
+
+    #[test]
+    fn returns_cached_keys_until_locked() {
+        let (_dir, socket) = start_agent(Duration::from_secs(60));
+        let (_rsa, key) = generate_cert_key_pair().unwrap();
+        assert!(client::request_key(&socket, "aa").is_none());
+        client::cache_key(&socket, "aa", &key).unwrap();
+        assert!(client::request_key(&socket, "aa").unwrap().public_eq(&key));
+        assert!(client::request_key(&socket, "bb").is_none());
+        client::lock(&socket).unwrap();
+        assert!(client::request_key(&socket, "aa").is_none());
+    }
+
+    #[test]
+    fn forgets_keys_once_they_expire() {
+        let (_dir, socket) = start_agent(Duration::from_millis(200));
+        let (_rsa, key) = generate_cert_key_pair().unwrap();
+        client::cache_key(&socket, "aa", &key).unwrap();
+        assert!(client::request_key(&socket, "aa").is_some());
+        thread::sleep(Duration::from_millis(300));
+        assert!(client::request_key(&socket, "aa").is_none());
+    }
+
+    #[test]
+    fn ignores_a_cached_key_that_does_not_match_the_ca() {
+        let (_dir, socket) = start_agent(Duration::from_secs(60));
+        let (ca_cert, ca_key) = ca();
+        let fingerprint: String = certificate_fingerprint(&ca_cert).unwrap();
+        let (_rsa, other_key) = generate_cert_key_pair().unwrap();
+        client::cache_key(&socket, &fingerprint, &other_key).unwrap();
+        assert!(agent_key(&socket, &ca_cert, &fingerprint).is_none());
+        client::cache_key(&socket, &fingerprint, &ca_key).unwrap();
+        assert!(agent_key(&socket, &ca_cert, &fingerprint)
+            .unwrap()
+            .public_eq(&ca_key));
+    }
+}
+
+mod inspect {
+    use crate::{
+        commands::{
+            inspect::certificate_lines,
+            utils::{issue_for_domain, RevocationUrls},
+        },
+        x509::{
+            ca_cert::CACert, describe::colon_hex, distinguished_name::DistinguishedName,
+            Certificate,
+        },
+    };
+    use openssl::{
+        hash::MessageDigest,
+        pkey::{PKey, Private},
+        x509::X509,
+    };
+
+    fn distinguished_name(common_name: &str) -> DistinguishedName {
+        DistinguishedName {
+            common_name: Some(common_name.to_string()),
+            organization: "Vanish".to_string(),
+            country: None,
+            state: None,
+        }
+    }
+
+    fn ca(common_name: &str) -> (X509, PKey<Private>) {
+        CACert::new(distinguished_name(common_name))
+            .unwrap()
+            .generate_certificate()
+            .unwrap()
+    }
+
+    fn line<'a>(lines: &'a [String], label: &str) -> &'a str {
+        lines
+            .iter()
+            .find(|line: &&String| line.starts_with(&format!("   {:<13}:", label)))
+            .unwrap_or_else(|| panic!("No {} line in {:#?}", label, lines))
+    }
+
+    #[test]
+    fn describes_a_leaf_issued_by_the_active_ca() {
+        let (ca_cert, ca_key) = ca("Vanish Test CA");
+        let (leaf, _key) = issue_for_domain(
+            "app.test",
+            &distinguished_name("app.test"),
+            &ca_cert,
+            &ca_key,
+            &RevocationUrls::default(),
+        )
+        .unwrap();
+        let lines: Vec<String> = certificate_lines(&leaf, Some(&ca_cert)).unwrap();
+
+        assert!(line(&lines, "Subject").contains("CN=app.test"));
+        assert!(line(&lines, "Issuer").contains("CN=Vanish Test CA"));
+        assert_eq!(line(&lines, "SANs"), "   SANs         : DNS:app.test");
+        assert!(line(&lines, "Not After").contains("valid for "));
+        assert_eq!(
+            line(&lines, "SHA-1"),
+            format!(
+                "   SHA-1        : {}",
+                colon_hex(&leaf.digest(MessageDigest::sha1()).unwrap())
+            )
+        );
+        let sha256: &str = line(&lines, "SHA-256");
+        assert_eq!(sha256.len(), "   SHA-256      : ".len() + 32 * 3 - 1);
+        assert_eq!(
+            sha256,
+            format!(
+                "   SHA-256      : {}",
+                colon_hex(&leaf.digest(MessageDigest::sha256()).unwrap())
+            )
+        );
+        assert!(lines
+            .iter()
+            .any(|line: &String| line.contains("DNS:app.test") && line.starts_with("      ")));
+        assert_eq!(
+            line(&lines, "Vanish CA"),
+            "   Vanish CA    : Issued by the active Vanish CA ✅"
+        );
+    }
+
+    #[test]
+    fn describes_a_ca_and_whether_it_is_the_active_one() {
+        let (ca_cert, _ca_key) = ca("Vanish Test CA");
+        let lines: Vec<String> = certificate_lines(&ca_cert, Some(&ca_cert)).unwrap();
+        // Self-signed, so it names itself as the issuer
+        assert_eq!(
+            line(&lines, "Subject").split_once(": ").unwrap().1,
+            line(&lines, "Issuer").split_once(": ").unwrap().1
+        );
+        assert!(lines.iter().any(|line: &String| line.contains("CA:TRUE")));
+        assert_eq!(
+            line(&lines, "Vanish CA"),
+            "   Vanish CA    : This is the active Vanish CA ✅"
+        );
+
+        let (other_cert, _other_key) = ca("Other CA");
+        let lines: Vec<String> = certificate_lines(&ca_cert, Some(&other_cert)).unwrap();
+        assert_eq!(
+            line(&lines, "Vanish CA"),
+            "   Vanish CA    : Not issued by the active Vanish CA ❌"
+        );
+        let lines: Vec<String> = certificate_lines(&ca_cert, None).unwrap();
+        assert_eq!(
+            line(&lines, "Vanish CA"),
+            "   Vanish CA    : No active Vanish CA found"
+        );
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod trust_store_anchors {
+    use crate::{
+        trust_stores::matching_anchors,
+        x509::{ca_cert::CACert, distinguished_name::DistinguishedName, Certificate},
+    };
+    use openssl::x509::X509;
+    use std::{fs, path::PathBuf};
+
+    fn ca(common_name: &str) -> X509 {
+        let (cert, _key) = CACert::new(DistinguishedName {
+            common_name: Some(common_name.to_string()),
+            organization: "Vanish".to_string(),
+            country: None,
+            state: None,
+        })
+        .unwrap()
+        .generate_certificate()
+        .unwrap();
+        cert
+    }
+
+    #[test]
+    fn finds_copies_of_the_ca_under_any_name_and_encoding() {
+        let anchors = tempfile::tempdir().unwrap();
+        let cert: X509 = ca("Vanish Test CA");
+        let other: X509 = ca("Other CA");
+        fs::write(anchors.path().join("ca_vanish.pem"), cert.to_pem().unwrap()).unwrap();
+        fs::write(anchors.path().join("renamed.crt"), cert.to_der().unwrap()).unwrap();
+        fs::write(anchors.path().join("other.pem"), other.to_pem().unwrap()).unwrap();
+        fs::write(anchors.path().join("notes.txt"), b"not a certificate").unwrap();
+        fs::create_dir(anchors.path().join("ca_vanish.d")).unwrap();
+
+        let mut found: Vec<PathBuf> = matching_anchors(anchors.path(), &cert.to_der().unwrap());
+        found.sort();
+        assert_eq!(
+            found,
+            vec![
+                anchors.path().join("ca_vanish.pem"),
+                anchors.path().join("renamed.crt")
+            ]
+        );
+        assert!(
+            matching_anchors(&anchors.path().join("missing"), &cert.to_der().unwrap()).is_empty()
+        );
+    }
+}
+
+mod install {
+    use crate::{
+        ca_store::ca_dir,
+        commands::utils::{load_trust_ca, report_trust},
+        x509::{self, ca_cert::CACert, distinguished_name::DistinguishedName, Certificate},
+    };
+    use openssl::x509::X509;
+    use std::{fs, path::PathBuf};
+
+    fn ca(common_name: &str) -> X509 {
+        let (cert, _key) = CACert::new(DistinguishedName {
+            common_name: Some(common_name.to_string()),
+            organization: "Vanish".to_string(),
+            country: None,
+            state: None,
+        })
+        .unwrap()
+        .generate_certificate()
+        .unwrap();
+        cert
+    }
+
+    fn der(cert: &X509) -> Vec<u8> {
+        cert.to_der().unwrap()
+    }
+
+    #[test]
+    fn installs_the_given_ca_instead_of_the_active_one() {
+        // Tests get a data directory of their own, see `x509::DATA_DIR`
+        let data_dir: &PathBuf = x509::DATA_DIR.as_ref().unwrap();
+        fs::create_dir_all(data_dir).unwrap();
+        assert!(load_trust_ca(&None).is_err());
+
+        let active: X509 = ca("Active CA");
+        fs::write(data_dir.join("ca_cert.pem"), active.to_pem().unwrap()).unwrap();
+        assert_eq!(der(&load_trust_ca(&None).unwrap()), der(&active));
+
+        let files = tempfile::tempdir().unwrap();
+        let given: X509 = ca("Given CA");
+        let given_path: PathBuf = files.path().join("given.der");
+        fs::write(&given_path, der(&given)).unwrap();
+        let given_path: Option<String> = Some(given_path.display().to_string());
+        assert_eq!(der(&load_trust_ca(&given_path).unwrap()), der(&given));
+
+        let named: X509 = ca("Named CA");
+        let named_dir: PathBuf = ca_dir("install-test").unwrap();
+        fs::create_dir_all(&named_dir).unwrap();
+        fs::write(named_dir.join("ca_cert.pem"), named.to_pem().unwrap()).unwrap();
+        assert_eq!(
+            der(&load_trust_ca(&Some("install-test".to_string())).unwrap()),
+            der(&named)
+        );
+
+        let missing: Option<String> = Some(files.path().join("missing.pem").display().to_string());
+        assert!(load_trust_ca(&missing).is_err());
+    }
+
+    #[test]
+    fn fails_unless_every_store_found_trusts_the_ca() {
+        let cert: X509 = ca("Vanish Test CA");
+        let stores = |system: bool, nss: bool, firefox: Option<bool>| -> bool {
+            report_trust(
+                &cert,
+                &[
+                    ("System trust store", Some(system)),
+                    ("NSS", Some(nss)),
+                    ("Firefox", firefox),
+                ],
+            )
+        };
+        assert!(stores(true, true, Some(true)));
+        // Without Firefox there is nothing to install into
+        assert!(stores(true, true, None));
+        assert!(!stores(false, true, None));
+        assert!(!stores(true, false, Some(true)));
+        assert!(!stores(true, true, Some(false)));
+    }
+}
+
+mod serve_api {
+    use crate::{
+        commands::{serve_api::ApiServer, utils::RevocationUrls},
+        http::{HttpRequest, HttpResponse},
+        ledger::{Ledger, LedgerEntry},
+        x509::{ca_cert::CACert, distinguished_name::DistinguishedName, Certificate},
+    };
+    use serde_json::{json, Value};
+
+    fn server() -> ApiServer {
+        let (ca_cert, ca_key) = CACert::new(DistinguishedName {
+            common_name: Some("Vanish Test CA".to_string()),
+            organization: "Vanish".to_string(),
+            country: None,
+            state: None,
+        })
+        .unwrap()
+        .generate_certificate()
+        .unwrap();
+        ApiServer::new(ca_cert, ca_key, RevocationUrls::default(), None)
+    }
+
+    fn request(method: &str, path: &str, body: &[u8]) -> HttpRequest {
+        HttpRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: Vec::new(),
+            body: body.to_vec(),
+        }
+    }
+
+    fn recorded(domain: &str) -> bool {
+        Ledger::load()
+            .unwrap()
+            .certificates
+            .iter()
+            .any(|entry: &LedgerEntry| entry.names() == domain)
+    }
+
+    #[test]
+    fn issues_every_domain_or_none() {
+        let server: ApiServer = server();
+        let spec: Value = json!({"domains": ["first.api-all-or-none.test", "not a domain!"]});
+        let response: HttpResponse = server.handle(&request(
+            "POST",
+            "/certificates",
+            spec.to_string().as_bytes(),
+        ));
+        assert_eq!(response.status, 400);
+        assert!(!recorded("first.api-all-or-none.test"));
+
+        let spec: Value =
+            json!({"domains": ["first.api-all-or-none.test", "second.api-all-or-none.test"]});
+        let response: HttpResponse = server.handle(&request(
+            "POST",
+            "/certificates",
+            spec.to_string().as_bytes(),
+        ));
+        assert_eq!(response.status, 201);
+        let body: Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["certificates"].as_array().unwrap().len(), 2);
+        assert!(recorded("first.api-all-or-none.test"));
+        assert!(recorded("second.api-all-or-none.test"));
+
+        // Without a rotation under way the chain is just the root
+        let ca: HttpResponse = server.handle(&request("GET", "/ca", b""));
+        assert_eq!(ca.status, 200);
+        assert_eq!(body["ca_certificate"].as_str().unwrap().as_bytes(), ca.body);
+    }
+}
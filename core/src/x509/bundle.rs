@@ -0,0 +1,71 @@
+use super::{
+    encoding::private_key_to_pem,
+    errors::{X509Error, X509Result},
+    file::{write_file, Overwrite, CERT_FILE_MODE, KEY_FILE_MODE},
+};
+use clap::ValueEnum;
+use openssl::{
+    error::ErrorStack,
+    pkey::{PKey, Private},
+    x509::X509,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BundleLayout {
+    /// Private key, leaf and chain in a single file (HAProxy)
+    Combined,
+    /// Leaf followed by the chain (nginx `fullchain.pem`)
+    Fullchain,
+    /// Chain only, to go alongside the leaf certificate (Envoy)
+    Chain,
+}
+
+impl BundleLayout {
+    pub fn file_name(&self, name: &str) -> String {
+        match self {
+            Self::Combined => format!("{}-combined.pem", name),
+            Self::Fullchain => format!("{}-fullchain.pem", name),
+            Self::Chain => format!("{}-chain.pem", name),
+        }
+    }
+
+    pub fn needs_key(&self) -> bool {
+        matches!(self, Self::Combined)
+    }
+
+    pub fn build(
+        &self,
+        leaf: &X509,
+        key: Option<&PKey<Private>>,
+        chain: &[X509],
+        key_passphrase: Option<&[u8]>,
+    ) -> X509Result<Vec<u8>> {
+        let mut bundle: Vec<u8> = Vec::new();
+        if *self != Self::Chain {
+            bundle.extend(
+                leaf.to_pem()
+                    .map_err(|err: ErrorStack| X509Error::PEMEncodingError(err))?,
+            );
+        }
+        for cert in chain {
+            bundle.extend(
+                cert.to_pem()
+                    .map_err(|err: ErrorStack| X509Error::PEMEncodingError(err))?,
+            );
+        }
+        if self.needs_key() {
+            let key: &PKey<Private> = key.ok_or(X509Error::BundleMissingPrivateKey)?;
+            bundle.extend(private_key_to_pem(key, key_passphrase)?);
+        }
+        Ok(bundle)
+    }
+
+    pub fn save_bundle(&self, bundle: &[u8], path: &str, overwrite: Overwrite) -> X509Result<()> {
+        let mode: u32 = if self.needs_key() {
+            KEY_FILE_MODE
+        } else {
+            CERT_FILE_MODE
+        };
+        write_file(path, bundle, mode, overwrite)
+    }
+}
@@ -1,54 +1,82 @@
 use super::{
+    der::{certificate_extension, encode, encode_sequence, TAG_SEQUENCE},
     distinguished_name::DistinguishedName,
+    encoding::{
+        carries_private_key, private_key_to_pem, read_certificate, read_pkcs12, read_private_key,
+        Encoding,
+    },
     errors::{X509Error, X509Result},
+    file::{write_file, Overwrite, CERT_FILE_MODE, KEY_FILE_MODE},
     Certificate, X509Version,
 };
-use crate::utils::{generate_cert_key_pair, generate_certificate_serial_number};
+use crate::utils::{generate_certificate_serial_number, generate_private_key};
+use clap::ValueEnum;
 use openssl::{
-    asn1::{Asn1Integer, Asn1Time},
+    asn1::{Asn1Integer, Asn1Object, Asn1OctetString, Asn1Time, Asn1TimeRef},
     error::ErrorStack,
     hash::MessageDigest,
+    pkcs12::ParsedPkcs12_2,
     pkey::{PKey, Private},
-    rsa::Rsa,
-    x509::{extension::BasicConstraints, X509Builder, X509Name, X509},
+    x509::{
+        extension::{AuthorityKeyIdentifier, BasicConstraints, SubjectKeyIdentifier},
+        X509Builder, X509Extension, X509Name, X509,
+    },
 };
-use std::{fs, io::Write};
-use std::{fs::File, io};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, fs, io};
+
+// 2.5.29.30
+const OID_NAME_CONSTRAINTS: &[u8] = &[0x06, 0x03, 0x55, 0x1d, 0x1e];
+// [0] IMPLICIT GeneralSubtrees and the dNSName [2] IMPLICIT IA5String inside them
+const TAG_PERMITTED_SUBTREES: u8 = 0xa0;
+const TAG_DNS_NAME: u8 = 0x82;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyType {
+    /// RSA with a 2048 bit modulus
+    #[default]
+    Rsa2048,
+    /// RSA with a 4096 bit modulus
+    Rsa4096,
+    /// ECDSA on the P-256 curve
+    EcdsaP256,
+}
+
+// How a CA is created, kept next to it so that re-creating it gives the same kind of CA
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CASettings {
+    pub key_type: KeyType,
+    pub validity_days: u32,
+    // DNS names (and their subdomains) the CA may issue for, empty for no limit
+    pub name_constraints: Vec<String>,
+}
+
+impl Default for CASettings {
+    fn default() -> Self {
+        CASettings {
+            key_type: KeyType::default(),
+            validity_days: 365 * 2,
+            name_constraints: Vec::new(),
+        }
+    }
+}
 
 pub struct CACert {
-    _rsa_priv: Rsa<Private>,
     pkey: PKey<Private>,
     distinguished_name: DistinguishedName,
     version: X509Version,
     not_before: Asn1Time,
     not_after: Asn1Time,
     serial_number: Asn1Integer,
+    name_constraints: Vec<String>,
 }
 
 impl Certificate for CACert {
     type Output = (X509, PKey<Private>);
     fn new(distinguished_name: DistinguishedName) -> X509Result<Self> {
-        match generate_cert_key_pair() {
-            Ok((rsa_priv, pkey)) => match generate_certificate_serial_number() {
-                Ok(serial_number) => {
-                    let not_before: Asn1Time = Asn1Time::days_from_now(0)
-                        .map_err(|err: ErrorStack| X509Error::GenerateNotBeforeError(err))?;
-                    let not_after: Asn1Time = Asn1Time::days_from_now(365 * 2)
-                        .map_err(|err: ErrorStack| X509Error::GenerateNotAfterError(err))?;
-                    Ok(CACert {
-                        _rsa_priv: rsa_priv,
-                        pkey,
-                        distinguished_name,
-                        version: X509Version::V3,
-                        not_before,
-                        not_after,
-                        serial_number,
-                    })
-                }
-                Err(err) => Err(X509Error::InitSerialNumberGenerationError(err)),
-            },
-            Err(err) => Err(X509Error::InitCARequestCertKeyPairError(err)),
-        }
+        CACert::with_settings(distinguished_name, &CASettings::default())
     }
 
     fn generate_certificate(self) -> X509Result<Self::Output> {
@@ -101,6 +129,30 @@ impl Certificate for CACert {
             .map_err(|err: ErrorStack| {
                 X509Error::X509CertificateBuilerEntryError(err, "Basic Constraints".to_string())
             })?;
+        // Lets path builders match this root to what it signs, and to its cross-signed copy
+        let subject_key_identifier: X509Extension = SubjectKeyIdentifier::new()
+            .build(&cert_builder.x509v3_context(None, None))
+            .map_err(|err: ErrorStack| {
+                X509Error::X509CertificateBuilerEntryError(
+                    err,
+                    "Subject Key Identifier".to_string(),
+                )
+            })?;
+        cert_builder
+            .append_extension(subject_key_identifier)
+            .map_err(|err: ErrorStack| {
+                X509Error::X509CertificateBuilerEntryError(
+                    err,
+                    "Subject Key Identifier".to_string(),
+                )
+            })?;
+        if !self.name_constraints.is_empty() {
+            cert_builder
+                .append_extension(CACert::name_constraints_extension(&self.name_constraints)?)
+                .map_err(|err: ErrorStack| {
+                    X509Error::X509CertificateBuilerEntryError(err, "Name Constraints".to_string())
+                })?;
+        }
         cert_builder
             .sign(&self.pkey, MessageDigest::sha256())
             .map_err(|err: ErrorStack| {
@@ -111,63 +163,259 @@ impl Certificate for CACert {
 }
 
 impl CACert {
+    pub fn with_settings(
+        distinguished_name: DistinguishedName,
+        settings: &CASettings,
+    ) -> X509Result<Self> {
+        let pkey: PKey<Private> = generate_private_key(settings.key_type)
+            .map_err(X509Error::InitCARequestCertKeyPairError)?;
+        let serial_number: Asn1Integer = generate_certificate_serial_number()
+            .map_err(X509Error::InitSerialNumberGenerationError)?;
+        let not_before: Asn1Time = Asn1Time::days_from_now(0)
+            .map_err(|err: ErrorStack| X509Error::GenerateNotBeforeError(err))?;
+        let not_after: Asn1Time = Asn1Time::days_from_now(settings.validity_days)
+            .map_err(|err: ErrorStack| X509Error::GenerateNotAfterError(err))?;
+        Ok(CACert {
+            pkey,
+            distinguished_name,
+            version: X509Version::V3,
+            not_before,
+            not_after,
+            serial_number,
+            name_constraints: settings.name_constraints.clone(),
+        })
+    }
+
+    // The new root's name and key, vouched for by the old root, so that
+    // machines which only trust the old root still accept the new one's chains
+    pub fn cross_sign(
+        cert: &X509,
+        issuer_cert: &X509,
+        issuer_key: &PKey<Private>,
+    ) -> X509Result<X509> {
+        let entry_error = |entry: &'static str| {
+            move |err: ErrorStack| {
+                X509Error::X509CertificateBuilerEntryError(err, entry.to_string())
+            }
+        };
+        let serial_number: Asn1Integer = generate_certificate_serial_number()
+            .map_err(X509Error::InitSerialNumberGenerationError)?;
+        let not_before: Asn1Time = Asn1Time::days_from_now(0)
+            .map_err(|err: ErrorStack| X509Error::GenerateNotBeforeError(err))?;
+        // Worthless once the old root has expired anyway
+        let not_after: &Asn1TimeRef = if cert
+            .not_after()
+            .compare(issuer_cert.not_after())
+            .map_err(entry_error("Not After"))?
+            == Ordering::Less
+        {
+            cert.not_after()
+        } else {
+            issuer_cert.not_after()
+        };
+
+        let mut cert_builder: X509Builder = X509::builder()
+            .map_err(|err: ErrorStack| X509Error::X509CertificateBuilderInitializeError(err))?;
+        cert_builder
+            .set_version(X509Version::V3 as i32)
+            .map_err(entry_error("Version"))?;
+        cert_builder
+            .set_subject_name(cert.subject_name())
+            .map_err(entry_error("Subject Name"))?;
+        cert_builder
+            .set_issuer_name(issuer_cert.subject_name())
+            .map_err(entry_error("Issuer Name"))?;
+        cert_builder
+            .set_pubkey(
+                cert.public_key()
+                    .map_err(entry_error("Public Key"))?
+                    .as_ref(),
+            )
+            .map_err(entry_error("Public Key"))?;
+        cert_builder
+            .set_not_before(&not_before)
+            .map_err(entry_error("Not Before"))?;
+        cert_builder
+            .set_not_after(not_after)
+            .map_err(entry_error("Not After"))?;
+        cert_builder
+            .set_serial_number(&serial_number)
+            .map_err(entry_error("Serial Number"))?;
+        cert_builder
+            .append_extension(
+                BasicConstraints::new()
+                    .ca()
+                    .build()
+                    .map_err(|err: ErrorStack| X509Error::BasicConstraintsInitializeError(err))?,
+            )
+            .map_err(entry_error("Basic Constraints"))?;
+        // The new root's key identifier, and the old root's as the authority, so
+        // path builders tell this copy apart from the self-signed new root
+        let subject_key_identifier: X509Extension = SubjectKeyIdentifier::new()
+            .build(&cert_builder.x509v3_context(Some(issuer_cert), None))
+            .map_err(entry_error("Subject Key Identifier"))?;
+        cert_builder
+            .append_extension(subject_key_identifier)
+            .map_err(entry_error("Subject Key Identifier"))?;
+        // Old roots made before key identifiers are named by issuer and serial
+        let authority_key_identifier: X509Extension = AuthorityKeyIdentifier::new()
+            .keyid(false)
+            .issuer(false)
+            .build(&cert_builder.x509v3_context(Some(issuer_cert), None))
+            .map_err(entry_error("Authority Key Identifier"))?;
+        cert_builder
+            .append_extension(authority_key_identifier)
+            .map_err(entry_error("Authority Key Identifier"))?;
+        // The old root must not widen what the new one may issue for
+        if let Some((critical, value)) = certificate_extension(cert, OID_NAME_CONSTRAINTS)? {
+            let oid: Asn1Object =
+                Asn1Object::from_str("2.5.29.30").map_err(entry_error("Name Constraints"))?;
+            let value: Asn1OctetString =
+                Asn1OctetString::new_from_bytes(&value).map_err(entry_error("Name Constraints"))?;
+            cert_builder
+                .append_extension(
+                    X509Extension::new_from_der(&oid, critical, &value)
+                        .map_err(entry_error("Name Constraints"))?,
+                )
+                .map_err(entry_error("Name Constraints"))?;
+        }
+        cert_builder
+            .sign(issuer_key, MessageDigest::sha256())
+            .map_err(entry_error("Sign"))?;
+        Ok(cert_builder.build())
+    }
+
+    // RFC 5280 requires name constraints to be critical, so clients that can't
+    // enforce them reject the CA instead of trusting it for everything
+    fn name_constraints_extension(names: &[String]) -> X509Result<X509Extension> {
+        let subtrees: Vec<Vec<u8>> = names
+            .iter()
+            .map(|name: &String| encode_sequence(&[encode(TAG_DNS_NAME, name.as_bytes())]))
+            .collect();
+        let value: Vec<u8> = encode(
+            TAG_SEQUENCE,
+            &encode(TAG_PERMITTED_SUBTREES, &subtrees.concat()),
+        );
+        let build_error = |err: ErrorStack| {
+            X509Error::X509CertificateBuilerEntryError(err, "Name Constraints".to_string())
+        };
+        let oid: Asn1Object = Asn1Object::from_str("2.5.29.30").map_err(build_error)?;
+        let value: Asn1OctetString =
+            Asn1OctetString::new_from_bytes(&value).map_err(build_error)?;
+        X509Extension::new_from_der(&oid, true, &value).map_err(build_error)
+    }
+
     pub fn load_ca_cert(cert_path: &str, key_path: &str) -> X509Result<(X509, PKey<Private>)> {
-        let cert: X509 = match X509::from_pem(&fs::read(cert_path).map_err(|err: io::Error| {
+        let cert_data: Vec<u8> = fs::read(cert_path).map_err(|err: io::Error| {
+            X509Error::ErrorReadingCertFile(err, cert_path.to_string())
+        })?;
+
+        if cert_path == key_path && Encoding::detect(&cert_data) == Encoding::Pkcs12 {
+            let parsed: ParsedPkcs12_2 = match read_pkcs12(&cert_data, cert_path) {
+                Ok(parsed) => parsed,
+                Err(err) => {
+                    println!("Reading PKCS#12 Bundle at {} ❌", cert_path);
+                    return Err(err);
+                }
+            };
+            return match (parsed.cert, parsed.pkey) {
+                (Some(cert), Some(key)) => {
+                    println!("Reading PKCS#12 Bundle at {} ✅", cert_path);
+                    Ok((cert, key))
+                }
+                (None, _) => {
+                    println!("Reading PKCS#12 Bundle at {} ❌", cert_path);
+                    Err(X509Error::MissingFromBundle(
+                        "certificate",
+                        cert_path.to_string(),
+                    ))
+                }
+                (_, None) => {
+                    println!("Reading PKCS#12 Bundle at {} ❌", cert_path);
+                    Err(X509Error::MissingFromBundle(
+                        "private key",
+                        cert_path.to_string(),
+                    ))
+                }
+            };
+        }
+
+        let cert: X509 = CACert::parse_cert(&cert_data, cert_path)?;
+        let key: PKey<Private> = CACert::load_key(key_path)?;
+
+        Ok((cert, key))
+    }
+
+    // Without a key file the key has to come from the certificate file itself
+    pub fn load_ca_cert_or_bundle(
+        cert_path: &str,
+        key_path: Option<&str>,
+    ) -> X509Result<(X509, PKey<Private>)> {
+        if let Some(key_path) = key_path {
+            return CACert::load_ca_cert(cert_path, key_path);
+        }
+        let cert_data: Vec<u8> = fs::read(cert_path).map_err(|err: io::Error| {
+            X509Error::ErrorReadingCertFile(err, cert_path.to_string())
+        })?;
+        if !carries_private_key(&cert_data) {
+            return Err(X509Error::MissingKeyFile(cert_path.to_string()));
+        }
+        CACert::load_ca_cert(cert_path, cert_path)
+    }
+
+    pub fn load_cert(cert_path: &str) -> X509Result<X509> {
+        let cert_data: Vec<u8> = fs::read(cert_path).map_err(|err: io::Error| {
             X509Error::ErrorReadingCertFile(err, cert_path.to_string())
-        })?) {
+        })?;
+        CACert::parse_cert(&cert_data, cert_path)
+    }
+
+    fn parse_cert(cert_data: &[u8], cert_path: &str) -> X509Result<X509> {
+        match read_certificate(cert_data, cert_path) {
             Ok(certificate) => {
                 println!("Reading Certificate at {} ✅", cert_path);
-                certificate
+                Ok(certificate)
             }
             Err(err) => {
                 println!("Reading Certificate at {} ❌", cert_path);
-                return Err(X509Error::ErrorConvertingFileToData(
-                    err,
-                    cert_path.to_string(),
-                ));
+                Err(err)
             }
-        };
-
-        let key: PKey<Private> =
-            match PKey::private_key_from_pem(&std::fs::read(key_path).map_err(
-                |err: io::Error| X509Error::ErrorReadingCertFile(err, key_path.to_string()),
-            )?) {
-                Ok(key) => {
-                    println!("Reading Key at {} ✅", key_path);
-                    key
-                }
-                Err(err) => {
-                    println!("Reading Key at {} ❌", key_path);
-                    return Err(X509Error::ErrorConvertingFileToData(
-                        err,
-                        key_path.to_string(),
-                    ));
-                }
-            };
+        }
+    }
 
-        Ok((cert, key))
+    pub fn load_key(key_path: &str) -> X509Result<PKey<Private>> {
+        let key_data: Vec<u8> = fs::read(key_path)
+            .map_err(|err: io::Error| X509Error::ErrorReadingCertFile(err, key_path.to_string()))?;
+        match read_private_key(&key_data, key_path) {
+            Ok(key) => {
+                println!("Reading Key at {} ✅", key_path);
+                Ok(key)
+            }
+            Err(err) => {
+                println!("Reading Key at {} ❌", key_path);
+                Err(err)
+            }
+        }
     }
 
-    pub fn save_cert(cert: &X509, path: &str) -> X509Result<()> {
-        let mut file: File = File::create(path)
-            .map_err(|err: io::Error| X509Error::X509PEMFileCreationError(err))?;
-        file.write_all(
-            &cert
-                .to_pem()
-                .map_err(|err: ErrorStack| X509Error::PEMEncodingError(err))?,
-        )
-        .map_err(|err: io::Error| X509Error::X509WriteToFileError(err))?;
-        Ok(())
+    pub fn save_cert(cert: &X509, path: &str, overwrite: Overwrite) -> X509Result<()> {
+        let cert_pem: Vec<u8> = cert
+            .to_pem()
+            .map_err(|err: ErrorStack| X509Error::PEMEncodingError(err))?;
+        write_file(path, &cert_pem, CERT_FILE_MODE, overwrite)
     }
 
-    pub fn save_key(key: &PKey<Private>, path: &str) -> X509Result<()> {
-        let mut file: File = File::create(path)
-            .map_err(|err: io::Error| X509Error::X509PEMFileCreationError(err))?;
-        file.write_all(
-            &key.private_key_to_pem_pkcs8()
-                .map_err(|err: ErrorStack| X509Error::PKCS8EncodingError(err))?,
+    pub fn save_key(
+        key: &PKey<Private>,
+        path: &str,
+        passphrase: Option<&[u8]>,
+        overwrite: Overwrite,
+    ) -> X509Result<()> {
+        write_file(
+            path,
+            &private_key_to_pem(key, passphrase)?,
+            KEY_FILE_MODE,
+            overwrite,
         )
-        .map_err(|err: io::Error| X509Error::X509WriteToFileError(err))?;
-        Ok(())
     }
 }
@@ -1,18 +1,33 @@
-use super::{distinguished_name::DistinguishedName, errors::X509Result, Certificate};
+use super::{
+    der::{
+        find_subject_alt_name, parse_subject_alt_names, AltName, DerElement, TAG_SEQUENCE, TAG_SET,
+    },
+    distinguished_name::DistinguishedName,
+    encoding::{pem_blocks, private_key_to_pem, Encoding},
+    errors::X509Result,
+    file::{write_file, Overwrite, CERT_FILE_MODE, KEY_FILE_MODE},
+    Certificate,
+};
 use crate::{utils::generate_cert_key_pair, x509::errors::X509Error};
 use openssl::{
     error::ErrorStack,
     hash::MessageDigest,
+    nid::Nid,
     pkey::{PKey, Private},
     rsa::Rsa,
     stack::Stack,
-    x509::{extension::SubjectAlternativeName, X509Extension, X509Name, X509Req, X509ReqBuilder},
-};
-use std::io::Read;
-use std::{
-    fs::File,
-    io::{self, Write},
+    string::OpensslString,
+    x509::{
+        extension::SubjectAlternativeName, X509Extension, X509Name, X509NameEntryRef, X509Req,
+        X509ReqBuilder,
+    },
 };
+use std::{fs::File, io::Read};
+
+// 1.2.840.113549.1.9.14
+const OID_EXTENSION_REQUEST: &[u8] = &[
+    0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x0e,
+];
 
 pub struct CAReq {
     _rsa_priv: Rsa<Private>,
@@ -76,18 +91,18 @@ impl Certificate for CAReq {
 }
 
 impl CAReq {
-    pub fn save_certificate_to_file(certificate: &X509Req, file_name: &str) -> X509Result<()> {
+    pub fn save_certificate_to_file(
+        certificate: &X509Req,
+        file_name: &str,
+        overwrite: Overwrite,
+    ) -> X509Result<()> {
         let certificate_pem: Vec<u8> = certificate
             .to_pem()
             .map_err(|err: ErrorStack| X509Error::X509CSRToPEMError(err))?;
-        let mut file: File = File::create(file_name)
-            .map_err(|err: io::Error| X509Error::X509PEMFileCreationError(err))?;
-        file.write_all(&certificate_pem)
-            .map_err(|err: io::Error| X509Error::X509WriteToFileError(err))?;
-        Ok(())
+        write_file(file_name, &certificate_pem, CERT_FILE_MODE, overwrite)
     }
 
-    pub fn read_csr_from_file(file_name: &str) -> X509Result<X509Req> {
+    pub fn read_csrs_from_file(file_name: &str) -> X509Result<Vec<X509Req>> {
         println!();
         let mut file: File = match File::open(file_name) {
             Ok(f) => f,
@@ -106,10 +121,18 @@ impl CAReq {
             }
         };
 
-        match X509Req::from_pem(&buffer) {
-            Ok(csr) => {
-                println!("Reading Signing Request at {} ✅", file_name);
-                Ok(csr)
+        match CAReq::parse_csrs(&buffer) {
+            Ok(csrs) if csrs.is_empty() => {
+                eprintln!("Reading Signing Request at {} ❌", file_name);
+                Err(X509Error::NoSigningRequestsFound(file_name.to_string()))
+            }
+            Ok(csrs) => {
+                println!(
+                    "Reading {} Signing Request(s) at {} ✅",
+                    csrs.len(),
+                    file_name
+                );
+                Ok(csrs)
             }
             Err(err) => {
                 eprintln!("Reading Signing Request at {} ❌", file_name);
@@ -121,14 +144,103 @@ impl CAReq {
         }
     }
 
-    pub fn save_key(key: &PKey<Private>, path: &str) -> X509Result<()> {
-        let mut file: File = File::create(path)
-            .map_err(|err: io::Error| X509Error::X509PEMFileCreationError(err))?;
-        file.write_all(
-            &key.private_key_to_pem_pkcs8()
-                .map_err(|err: ErrorStack| X509Error::PKCS8EncodingError(err))?,
-        )
-        .map_err(|err: io::Error| X509Error::X509WriteToFileError(err))?;
+    // Every request in a PEM file, or the one in a DER file
+    pub fn parse_csrs(data: &[u8]) -> Result<Vec<X509Req>, ErrorStack> {
+        match Encoding::detect(data) {
+            Encoding::Pem => pem_blocks(data, &["CERTIFICATE REQUEST", "NEW CERTIFICATE REQUEST"])
+                .into_iter()
+                .map(X509Req::from_pem)
+                .collect(),
+            _ => X509Req::from_der(data).map(|csr: X509Req| vec![csr]),
+        }
+    }
+
+    // rust-openssl can't look inside request extensions, so walk the DER for the SAN
+    pub fn requested_subject_alt_name(csr: &X509Req) -> X509Result<Option<(bool, Vec<u8>)>> {
+        let der: Vec<u8> = csr
+            .to_der()
+            .map_err(|err: ErrorStack| X509Error::X509CSRToPEMError(err))?;
+        let request: DerElement = DerElement::parse_exact(&der)?.expect(TAG_SEQUENCE)?;
+        let request_info: DerElement = match request.children()?.first() {
+            Some(info) => info.expect(TAG_SEQUENCE)?,
+            None => return Ok(None),
+        };
+        let attributes: DerElement = match request_info.children()?.get(3) {
+            Some(attributes) if attributes.tag == 0xa0 => *attributes,
+            _ => return Ok(None),
+        };
+        for attribute in attributes.children()? {
+            let attribute: Vec<DerElement> = attribute.expect(TAG_SEQUENCE)?.children()?;
+            if attribute.len() != 2 || attribute[0].raw != OID_EXTENSION_REQUEST {
+                continue;
+            }
+            for extensions in attribute[1].expect(TAG_SET)?.children()? {
+                let san: Option<(bool, Vec<u8>)> =
+                    find_subject_alt_name(&extensions.expect(TAG_SEQUENCE)?.children()?)?;
+                if san.is_some() {
+                    return Ok(san);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // A request must be signed by the key it asks a certificate for, or anyone
+    // could have a certificate issued for someone else's public key
+    pub fn check_signature(csr: &X509Req) -> X509Result<()> {
+        let signed: bool = csr
+            .public_key()
+            .and_then(|public_key| csr.verify(&public_key))
+            .unwrap_or(false);
+        if !signed {
+            return Err(X509Error::InvalidCSRSignature);
+        }
         Ok(())
     }
+
+    // The DNS names and IP addresses of the requested SAN, refusing any other
+    // kind of name
+    pub fn requested_names(csr: &X509Req) -> X509Result<Vec<AltName>> {
+        match CAReq::requested_subject_alt_name(csr)? {
+            Some((_critical, san)) => parse_subject_alt_names(&san),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    // The CN and every requested name, lowercased and without duplicates
+    pub fn requested_identifiers(csr: &X509Req) -> X509Result<Vec<String>> {
+        let mut names: Vec<String> = csr
+            .subject_name()
+            .entries_by_nid(Nid::COMMONNAME)
+            .filter_map(|entry: &X509NameEntryRef| entry.data().as_utf8().ok())
+            .map(|common_name: OpensslString| common_name.to_string())
+            .collect();
+        names.extend(
+            CAReq::requested_names(csr)?
+                .iter()
+                .map(|name: &AltName| name.to_string()),
+        );
+        let mut unique: Vec<String> = Vec::new();
+        for name in names {
+            let name: String = name.to_ascii_lowercase();
+            if !unique.contains(&name) {
+                unique.push(name);
+            }
+        }
+        Ok(unique)
+    }
+
+    pub fn save_key(
+        key: &PKey<Private>,
+        path: &str,
+        passphrase: Option<&[u8]>,
+        overwrite: Overwrite,
+    ) -> X509Result<()> {
+        write_file(
+            path,
+            &private_key_to_pem(key, passphrase)?,
+            KEY_FILE_MODE,
+            overwrite,
+        )
+    }
 }
@@ -0,0 +1,365 @@
+use super::errors::{X509Error, X509Result};
+use crate::utils::civil_time;
+use openssl::{
+    error::ErrorStack,
+    hash::MessageDigest,
+    pkey::{Id, PKey, Private},
+    sign::Signer,
+    x509::X509,
+};
+use std::{fmt, net::IpAddr};
+
+pub const TAG_BOOLEAN: u8 = 0x01;
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_BIT_STRING: u8 = 0x03;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_ENUMERATED: u8 = 0x0a;
+pub const TAG_UTC_TIME: u8 = 0x17;
+pub const TAG_GENERALIZED_TIME: u8 = 0x18;
+pub const TAG_SEQUENCE: u8 = 0x30;
+pub const TAG_SET: u8 = 0x31;
+pub const TAG_OID: u8 = 0x06;
+// [3] EXPLICIT Extensions in a TBSCertificate
+const TAG_EXTENSIONS: u8 = 0xa3;
+
+// 2.5.29.19
+pub const OID_BASIC_CONSTRAINTS: &[u8] = &[0x06, 0x03, 0x55, 0x1d, 0x13];
+// 2.5.29.17
+pub const OID_SUBJECT_ALT_NAME: &[u8] = &[0x06, 0x03, 0x55, 0x1d, 0x11];
+// 2.5.29.31
+pub const OID_CRL_DISTRIBUTION_POINTS: &[u8] = &[0x06, 0x03, 0x55, 0x1d, 0x1f];
+// 2.5.29.37
+pub const OID_EXTENDED_KEY_USAGE: &[u8] = &[0x06, 0x03, 0x55, 0x1d, 0x25];
+// 1.3.6.1.5.5.7.1.1
+pub const OID_AUTHORITY_INFO_ACCESS: &[u8] =
+    &[0x06, 0x08, 0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x01, 0x01];
+// 1.2.840.113549.1.1.11
+const OID_SHA256_WITH_RSA: &[u8] = &[
+    0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b,
+];
+// 1.2.840.10045.4.3.2
+const OID_ECDSA_WITH_SHA256: &[u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+
+#[derive(Debug, Clone, Copy)]
+pub struct DerElement<'a> {
+    pub tag: u8,
+    pub contents: &'a [u8],
+    pub raw: &'a [u8],
+}
+
+impl<'a> DerElement<'a> {
+    pub fn parse(data: &'a [u8]) -> X509Result<(DerElement<'a>, &'a [u8])> {
+        if data.len() < 2 {
+            return Err(X509Error::DERParseError("truncated element".to_string()));
+        }
+        let tag: u8 = data[0];
+        if tag & 0x1f == 0x1f {
+            return Err(X509Error::DERParseError(
+                "multi-byte tags are not supported".to_string(),
+            ));
+        }
+        let (length, header): (usize, usize) = match data[1] {
+            length if length < 0x80 => (length as usize, 2),
+            0x80 => {
+                return Err(X509Error::DERParseError(
+                    "indefinite lengths are not allowed in DER".to_string(),
+                ))
+            }
+            marker => {
+                let octets: usize = (marker & 0x7f) as usize;
+                if octets > 4 || data.len() < 2 + octets {
+                    return Err(X509Error::DERParseError("invalid length".to_string()));
+                }
+                let length: usize = data[2..2 + octets]
+                    .iter()
+                    .fold(0, |length: usize, byte: &u8| (length << 8) | *byte as usize);
+                (length, 2 + octets)
+            }
+        };
+        let end: usize = header
+            .checked_add(length)
+            .filter(|end: &usize| *end <= data.len())
+            .ok_or_else(|| X509Error::DERParseError("element exceeds input".to_string()))?;
+        Ok((
+            DerElement {
+                tag,
+                contents: &data[header..end],
+                raw: &data[..end],
+            },
+            &data[end..],
+        ))
+    }
+
+    pub fn parse_exact(data: &'a [u8]) -> X509Result<DerElement<'a>> {
+        let (element, rest) = DerElement::parse(data)?;
+        if !rest.is_empty() {
+            return Err(X509Error::DERParseError(
+                "trailing data after element".to_string(),
+            ));
+        }
+        Ok(element)
+    }
+
+    pub fn expect(self, tag: u8) -> X509Result<Self> {
+        if self.tag != tag {
+            return Err(X509Error::DERParseError(format!(
+                "expected tag {:#04x}, found {:#04x}",
+                tag, self.tag
+            )));
+        }
+        Ok(self)
+    }
+
+    pub fn children(&self) -> X509Result<Vec<DerElement<'a>>> {
+        let mut children: Vec<DerElement<'a>> = Vec::new();
+        let mut rest: &'a [u8] = self.contents;
+        while !rest.is_empty() {
+            let (child, remaining) = DerElement::parse(rest)?;
+            children.push(child);
+            rest = remaining;
+        }
+        Ok(children)
+    }
+}
+
+// The criticality and raw value of the SAN among a list of `Extension`s, as
+// found in both certificates and signing requests
+pub fn find_subject_alt_name(extensions: &[DerElement]) -> X509Result<Option<(bool, Vec<u8>)>> {
+    find_extension(extensions, OID_SUBJECT_ALT_NAME)
+}
+
+// The kinds of name a leaf may carry in its SAN
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AltName {
+    Dns(String),
+    Ip(IpAddr),
+}
+
+impl AltName {
+    // An IP address when it parses as one, otherwise a DNS name
+    pub fn parse(name: &str) -> X509Result<AltName> {
+        if let Ok(ip) = name.parse::<IpAddr>() {
+            return Ok(AltName::Ip(ip));
+        }
+        // Also keeps commas out of the names handed to OpenSSL's SAN builder
+        let valid: bool = !name.is_empty()
+            && name.len() <= 253
+            && name
+                .chars()
+                .all(|c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '*' | '_'));
+        if !valid {
+            return Err(X509Error::InvalidSubjectAltName(name.to_string()));
+        }
+        Ok(AltName::Dns(name.to_string()))
+    }
+}
+
+impl fmt::Display for AltName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AltName::Dns(name) => write!(f, "{}", name),
+            AltName::Ip(ip) => write!(f, "{}", ip),
+        }
+    }
+}
+
+// DNS names and IP addresses from a GeneralNames value. Anything else is
+// refused rather than put into a certificate browsers trust.
+pub fn parse_subject_alt_names(value: &[u8]) -> X509Result<Vec<AltName>> {
+    let mut names: Vec<AltName> = Vec::new();
+    for name in DerElement::parse_exact(value)?
+        .expect(TAG_SEQUENCE)?
+        .children()?
+    {
+        let name: AltName = match name.tag {
+            // [2] IMPLICIT dNSName
+            0x82 => match std::str::from_utf8(name.contents) {
+                Ok(dns) if dns.parse::<IpAddr>().is_err() => AltName::parse(dns)?,
+                _ => {
+                    return Err(X509Error::InvalidSubjectAltName(
+                        String::from_utf8_lossy(name.contents).to_string(),
+                    ))
+                }
+            },
+            // [7] IMPLICIT iPAddress
+            0x87 => match name.contents.len() {
+                4 => AltName::Ip(IpAddr::from(
+                    <[u8; 4]>::try_from(name.contents).unwrap_or_default(),
+                )),
+                16 => AltName::Ip(IpAddr::from(
+                    <[u8; 16]>::try_from(name.contents).unwrap_or_default(),
+                )),
+                _ => {
+                    return Err(X509Error::InvalidSubjectAltName(
+                        name.contents
+                            .iter()
+                            .map(|byte: &u8| format!("{:02x}", byte))
+                            .collect(),
+                    ))
+                }
+            },
+            0x81 => return Err(X509Error::UnsupportedSubjectAltName("email addresses")),
+            0x86 => return Err(X509Error::UnsupportedSubjectAltName("URIs")),
+            0xa0 => return Err(X509Error::UnsupportedSubjectAltName("other names")),
+            0xa4 => return Err(X509Error::UnsupportedSubjectAltName("directory names")),
+            _ => return Err(X509Error::UnsupportedSubjectAltName("other kinds of names")),
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+pub fn find_extension(
+    extensions: &[DerElement],
+    oid: &[u8],
+) -> X509Result<Option<(bool, Vec<u8>)>> {
+    for extension in extensions {
+        let fields: Vec<DerElement> = extension.expect(TAG_SEQUENCE)?.children()?;
+        if fields.first().map(|field: &DerElement| field.raw) != Some(oid) {
+            continue;
+        }
+        let critical: bool =
+            fields.len() == 3 && fields[1].tag == TAG_BOOLEAN && fields[1].contents != [0];
+        if let Some(value) = fields.last() {
+            let value: DerElement = value.expect(TAG_OCTET_STRING)?;
+            return Ok(Some((critical, value.contents.to_vec())));
+        }
+    }
+    Ok(None)
+}
+
+// rust-openssl can't look up arbitrary certificate extensions, so walk the
+// DER for them to carry them over byte for byte
+pub fn certificate_extension(
+    certificate: &X509,
+    oid: &[u8],
+) -> X509Result<Option<(bool, Vec<u8>)>> {
+    let der: Vec<u8> = certificate
+        .to_der()
+        .map_err(|err: ErrorStack| X509Error::PEMEncodingError(err))?;
+    let certificate: DerElement = DerElement::parse_exact(&der)?.expect(TAG_SEQUENCE)?;
+    let tbs_certificate: DerElement = match certificate.children()?.first() {
+        Some(tbs_certificate) => tbs_certificate.expect(TAG_SEQUENCE)?,
+        None => return Ok(None),
+    };
+    for field in tbs_certificate.children()? {
+        if field.tag != TAG_EXTENSIONS {
+            continue;
+        }
+        if let Some(extensions) = field.children()?.first() {
+            return find_extension(&extensions.expect(TAG_SEQUENCE)?.children()?, oid);
+        }
+    }
+    Ok(None)
+}
+
+// `cA` in the Basic Constraints, which defaults to false when left out
+pub fn is_ca_certificate(certificate: &X509) -> X509Result<bool> {
+    let Some((_critical, value)) = certificate_extension(certificate, OID_BASIC_CONSTRAINTS)?
+    else {
+        return Ok(false);
+    };
+    let fields: Vec<DerElement> = DerElement::parse_exact(&value)?
+        .expect(TAG_SEQUENCE)?
+        .children()?;
+    Ok(fields.first().is_some_and(|field: &DerElement| {
+        field.tag == TAG_BOOLEAN && field.contents.first().is_some_and(|value: &u8| *value != 0)
+    }))
+}
+
+pub fn encode(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut der: Vec<u8> = vec![tag];
+    let length: usize = contents.len();
+    if length < 0x80 {
+        der.push(length as u8);
+    } else {
+        let octets: Vec<u8> = length
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|byte: &u8| *byte == 0)
+            .collect();
+        der.push(0x80 | octets.len() as u8);
+        der.extend(octets);
+    }
+    der.extend_from_slice(contents);
+    der
+}
+
+pub fn encode_sequence(elements: &[Vec<u8>]) -> Vec<u8> {
+    encode(TAG_SEQUENCE, &elements.concat())
+}
+
+pub fn encode_extension(oid: &[u8], value: Vec<u8>) -> Vec<u8> {
+    encode_sequence(&[oid.to_vec(), encode(TAG_OCTET_STRING, &value)])
+}
+
+// Big-endian magnitude as a non-negative INTEGER, minimal and with a leading
+// zero where the top bit would otherwise read as a sign
+pub fn encode_unsigned_integer(magnitude: &[u8]) -> Vec<u8> {
+    let mut contents: Vec<u8> = magnitude
+        .iter()
+        .copied()
+        .skip_while(|byte: &u8| *byte == 0)
+        .collect();
+    if contents.first().is_none_or(|byte: &u8| byte & 0x80 != 0) {
+        contents.insert(0, 0);
+    }
+    encode(TAG_INTEGER, &contents)
+}
+
+// UTCTime through 2049 and GeneralizedTime after, as RFC 5280 requires
+pub fn encode_time(seconds: i64) -> Vec<u8> {
+    let (year, month, day, hour, minute, second) = civil_time(seconds);
+    if (1950..2050).contains(&year) {
+        let time: String = format!(
+            "{:02}{:02}{:02}{:02}{:02}{:02}Z",
+            year % 100,
+            month,
+            day,
+            hour,
+            minute,
+            second
+        );
+        encode(TAG_UTC_TIME, time.as_bytes())
+    } else {
+        encode_generalized_time(seconds)
+    }
+}
+
+pub fn encode_generalized_time(seconds: i64) -> Vec<u8> {
+    let (year, month, day, hour, minute, second) = civil_time(seconds);
+    let time: String = format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}Z",
+        year, month, day, hour, minute, second
+    );
+    encode(TAG_GENERALIZED_TIME, time.as_bytes())
+}
+
+pub fn signature_algorithm(key: &PKey<Private>) -> X509Result<Vec<u8>> {
+    match key.id() {
+        Id::RSA => Ok(encode_sequence(&[
+            OID_SHA256_WITH_RSA.to_vec(),
+            vec![0x05, 0x00],
+        ])),
+        Id::EC => Ok(encode_sequence(&[OID_ECDSA_WITH_SHA256.to_vec()])),
+        id => Err(X509Error::UnsupportedSigningKey(format!("{:?}", id))),
+    }
+}
+
+// The `tbs`, algorithm and signature fields every signed X.509 structure
+// starts with, for CRLs and OCSP responses rust-openssl can't build
+pub fn sign_fields(tbs: Vec<u8>, key: &PKey<Private>) -> X509Result<Vec<Vec<u8>>> {
+    let algorithm: Vec<u8> = signature_algorithm(key)?;
+    let mut signer: Signer = Signer::new(MessageDigest::sha256(), key)
+        .map_err(|err: ErrorStack| X509Error::DERSigningError(err))?;
+    let signature: Vec<u8> = signer
+        .sign_oneshot_to_vec(&tbs)
+        .map_err(|err: ErrorStack| X509Error::DERSigningError(err))?;
+    Ok(vec![
+        tbs,
+        algorithm,
+        encode(TAG_BIT_STRING, &[&[0], signature.as_slice()].concat()),
+    ])
+}
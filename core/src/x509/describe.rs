@@ -0,0 +1,103 @@
+use openssl::{
+    asn1::{Asn1Time, Asn1TimeRef, TimeDiff},
+    error::ErrorStack,
+    nid::Nid,
+    pkey::{HasPublic, Id, PKeyRef},
+    stack::Stack,
+    x509::{GeneralName, GeneralNameRef, X509NameRef, X509},
+};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+pub fn format_name(name: &X509NameRef) -> String {
+    let entries: Vec<String> = name
+        .entries()
+        .map(|entry| {
+            let key: &str = entry.object().nid().short_name().unwrap_or("?");
+            let value: String = entry
+                .data()
+                .as_utf8()
+                .map(|value| value.to_string())
+                .unwrap_or_default();
+            format!("{}={}", key, value)
+        })
+        .collect();
+    if entries.is_empty() {
+        "(empty)".to_string()
+    } else {
+        entries.join(", ")
+    }
+}
+
+pub fn format_general_names(names: &Stack<GeneralName>) -> String {
+    names
+        .iter()
+        .map(|name: &GeneralNameRef| {
+            if let Some(dns) = name.dnsname() {
+                format!("DNS:{}", dns)
+            } else if let Some(ip) = name.ipaddress() {
+                match ip_from_bytes(ip) {
+                    Some(ip) => format!("IP:{}", ip),
+                    None => "IP:?".to_string(),
+                }
+            } else if let Some(email) = name.email() {
+                format!("email:{}", email)
+            } else if let Some(uri) = name.uri() {
+                format!("URI:{}", uri)
+            } else {
+                "other".to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+pub fn colon_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte: &u8| format!("{:02X}", byte))
+        .collect::<Vec<String>>()
+        .join(":")
+}
+
+pub fn ip_from_bytes(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => Some(IpAddr::V4(Ipv4Addr::new(
+            bytes[0], bytes[1], bytes[2], bytes[3],
+        ))),
+        16 => {
+            let mut octets: [u8; 16] = [0; 16];
+            octets.copy_from_slice(bytes);
+            Some(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+        _ => None,
+    }
+}
+
+pub fn key_description<T: HasPublic>(key: &PKeyRef<T>) -> String {
+    match key.id() {
+        Id::RSA => format!("RSA {} bits", key.bits()),
+        Id::EC => {
+            let curve: String = key
+                .ec_key()
+                .ok()
+                .and_then(|ec_key| ec_key.group().curve_name())
+                .and_then(|nid: Nid| nid.short_name().ok())
+                .unwrap_or("unknown curve")
+                .to_string();
+            format!("EC {} ({} bits)", curve, key.bits())
+        }
+        Id::ED25519 => "Ed25519".to_string(),
+        Id::ED448 => "Ed448".to_string(),
+        Id::DSA => format!("DSA {} bits", key.bits()),
+        _ => format!("{} bits", key.bits()),
+    }
+}
+
+pub fn asn1_time_to_unix(time: &Asn1TimeRef) -> Result<i64, ErrorStack> {
+    let difference: TimeDiff = Asn1Time::from_unix(0)?.diff(time)?;
+    Ok(i64::from(difference.days) * 86400 + i64::from(difference.secs))
+}
+
+pub fn serial_hex(cert: &X509) -> Result<String, ErrorStack> {
+    Ok(cert.serial_number().to_bn()?.to_hex_str()?.to_string())
+}
@@ -4,7 +4,7 @@ use openssl::{
     x509::{X509Name, X509NameBuilder},
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DistinguishedName {
     pub common_name: Option<String>,
     pub organization: String,
@@ -0,0 +1,171 @@
+use super::errors::{X509Error, X509Result};
+use crate::utils::prompt_passphrase;
+use openssl::{
+    error::ErrorStack,
+    pkcs12::{ParsedPkcs12_2, Pkcs12},
+    pkey::{PKey, Private},
+    symm::Cipher,
+    x509::X509,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Pem,
+    Der,
+    Pkcs12,
+}
+
+impl Encoding {
+    pub fn detect(data: &[u8]) -> Self {
+        if is_pem(data) {
+            Encoding::Pem
+        } else if Pkcs12::from_der(data).is_ok() {
+            Encoding::Pkcs12
+        } else {
+            Encoding::Der
+        }
+    }
+}
+
+pub fn pem_blocks<'a>(data: &'a [u8], labels: &[&str]) -> Vec<&'a [u8]> {
+    let mut blocks: Vec<&'a [u8]> = Vec::new();
+    let mut offset: usize = 0;
+    while let Some(start) = find(&data[offset..], b"-----BEGIN ") {
+        let start: usize = offset + start;
+        let label_start: usize = start + 11;
+        let Some(label_len) = find(&data[label_start..], b"-----") else {
+            break;
+        };
+        let label: &[u8] = &data[label_start..label_start + label_len];
+        let footer: String = format!("-----END {}-----", String::from_utf8_lossy(label));
+        let Some(end) = find(&data[label_start..], footer.as_bytes()) else {
+            break;
+        };
+        let end: usize = label_start + end + footer.len();
+        if labels
+            .iter()
+            .any(|wanted: &&str| wanted.as_bytes() == label)
+        {
+            blocks.push(&data[start..end]);
+        }
+        offset = end;
+    }
+    blocks
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window: &[u8]| window == needle)
+}
+
+fn is_pem(data: &[u8]) -> bool {
+    data.windows(11)
+        .any(|window: &[u8]| window == b"-----BEGIN ")
+}
+
+pub fn is_encrypted_pem(data: &[u8]) -> bool {
+    data.windows(30)
+        .any(|window: &[u8]| window == b"BEGIN ENCRYPTED PRIVATE KEY---")
+        || data
+            .windows(22)
+            .any(|window: &[u8]| window == b"Proc-Type: 4,ENCRYPTED")
+}
+
+// PKCS#12 bundles and PEM files holding a key can stand in for `--keyfile`
+pub fn carries_private_key(data: &[u8]) -> bool {
+    match Encoding::detect(data) {
+        Encoding::Pkcs12 => true,
+        Encoding::Pem => !pem_blocks(
+            data,
+            &[
+                "PRIVATE KEY",
+                "ENCRYPTED PRIVATE KEY",
+                "RSA PRIVATE KEY",
+                "EC PRIVATE KEY",
+            ],
+        )
+        .is_empty(),
+        Encoding::Der => false,
+    }
+}
+
+pub fn read_pkcs12(data: &[u8], path: &str) -> X509Result<ParsedPkcs12_2> {
+    // Bundles exported without a password still need an empty one to decrypt
+    if let Ok(parsed) = parse_pkcs12(data, path, "") {
+        return Ok(parsed);
+    }
+    let password: String = prompt_passphrase(&format!("Enter password for {}: ", path))
+        .map_err(|err| X509Error::PassphrasePromptError(err, path.to_string()))?;
+    parse_pkcs12(data, path, &password)
+}
+
+pub fn parse_pkcs12(data: &[u8], path: &str, password: &str) -> X509Result<ParsedPkcs12_2> {
+    Pkcs12::from_der(data)
+        .and_then(|pkcs12: Pkcs12| pkcs12.parse2(password))
+        .map_err(|err: ErrorStack| X509Error::ErrorConvertingFileToData(err, path.to_string()))
+}
+
+pub fn read_certificate(data: &[u8], path: &str) -> X509Result<X509> {
+    let certificate: Result<X509, ErrorStack> = match Encoding::detect(data) {
+        Encoding::Pem => X509::from_pem(data),
+        Encoding::Der => X509::from_der(data),
+        Encoding::Pkcs12 => {
+            return read_pkcs12(data, path)?
+                .cert
+                .ok_or_else(|| X509Error::MissingFromBundle("certificate", path.to_string()));
+        }
+    };
+    certificate
+        .map_err(|err: ErrorStack| X509Error::ErrorConvertingFileToData(err, path.to_string()))
+}
+
+pub fn decrypt_private_key(
+    data: &[u8],
+    path: &str,
+    passphrase: &[u8],
+) -> X509Result<PKey<Private>> {
+    match Encoding::detect(data) {
+        Encoding::Pem => PKey::private_key_from_pem_passphrase(data, passphrase),
+        _ => PKey::private_key_from_pkcs8_passphrase(data, passphrase),
+    }
+    .map_err(|err: ErrorStack| X509Error::ErrorConvertingFileToData(err, path.to_string()))
+}
+
+pub fn read_private_key(data: &[u8], path: &str) -> X509Result<PKey<Private>> {
+    let key: Result<PKey<Private>, ErrorStack> = match Encoding::detect(data) {
+        Encoding::Pem if is_encrypted_pem(data) => {
+            let passphrase: String = prompt_passphrase(&format!("Enter passphrase for {}: ", path))
+                .map_err(|err| X509Error::PassphrasePromptError(err, path.to_string()))?;
+            return decrypt_private_key(data, path, passphrase.as_bytes());
+        }
+        Encoding::Pem => PKey::private_key_from_pem(data),
+        Encoding::Der => match PKey::private_key_from_der(data) {
+            Ok(key) => Ok(key),
+            // Not a plain key, so assume an encrypted PKCS#8 structure
+            Err(_) => {
+                let passphrase: String =
+                    prompt_passphrase(&format!("Enter passphrase for {}: ", path))
+                        .map_err(|err| X509Error::PassphrasePromptError(err, path.to_string()))?;
+                PKey::private_key_from_pkcs8_passphrase(data, passphrase.as_bytes())
+            }
+        },
+        Encoding::Pkcs12 => {
+            return read_pkcs12(data, path)?
+                .pkey
+                .ok_or_else(|| X509Error::MissingFromBundle("private key", path.to_string()));
+        }
+    };
+    key.map_err(|err: ErrorStack| X509Error::ErrorConvertingFileToData(err, path.to_string()))
+}
+
+pub fn private_key_to_pem(key: &PKey<Private>, passphrase: Option<&[u8]>) -> X509Result<Vec<u8>> {
+    match passphrase {
+        // PKCS#8 v2 with PBKDF2 key derivation and AES-256
+        Some(passphrase) => {
+            key.private_key_to_pem_pkcs8_passphrase(Cipher::aes_256_cbc(), passphrase)
+        }
+        None => key.private_key_to_pem_pkcs8(),
+    }
+    .map_err(|err: ErrorStack| X509Error::PKCS8EncodingError(err))
+}
@@ -30,6 +30,22 @@ pub enum X509Error {
     SANCouldNotBuildError(ErrorStack),
     CertificateStackInitializationError(ErrorStack),
     CertificateStackPushError(ErrorStack),
+    BundleMissingPrivateKey,
+    PassphrasePromptError(io::Error, String),
+    MissingFromBundle(&'static str, String),
+    MissingKeyFile(String),
+    DERParseError(String),
+    NoSigningRequestsFound(String),
+    InsecureDirectory(String),
+    FileAlreadyExists(String),
+    CRLBuildError(ErrorStack),
+    UnsupportedSigningKey(String),
+    DERSigningError(ErrorStack),
+    OCSPResponseError(ErrorStack),
+    InvalidOCSPResponder(String),
+    InvalidSubjectAltName(String),
+    UnsupportedSubjectAltName(&'static str),
+    InvalidCSRSignature,
 }
 
 impl fmt::Display for X509Error {
@@ -193,6 +209,119 @@ impl fmt::Display for X509Error {
                     err
                 )
             }
+            Self::BundleMissingPrivateKey => {
+                write!(
+                    f,
+                    "{}: A combined bundle needs the private key of the certificate",
+                    "Error".red()
+                )
+            }
+            Self::PassphrasePromptError(err, path) => {
+                write!(
+                    f,
+                    "{}: Reading passphrase for {} : {}",
+                    "Error".red(),
+                    path,
+                    err
+                )
+            }
+            Self::MissingKeyFile(path) => {
+                write!(
+                    f,
+                    "{}: Please provide corresponding `--keyfile` to the certificate at {}, only PKCS#12 bundles and PEM files with the key in them work without one",
+                    "Error".red(),
+                    path
+                )
+            }
+            Self::MissingFromBundle(item, path) => {
+                write!(
+                    f,
+                    "{}: No {} found in the bundle at {}",
+                    "Error".red(),
+                    item,
+                    path
+                )
+            }
+            Self::DERParseError(desc) => {
+                write!(f, "{}: Parsing DER structure: {}", "Error".red(), desc)
+            }
+            Self::NoSigningRequestsFound(path) => {
+                write!(
+                    f,
+                    "{}: No Certificate Signing Requests found in {}",
+                    "Error".red(),
+                    path
+                )
+            }
+            Self::InsecureDirectory(path) => {
+                write!(
+                    f,
+                    "{}: Refusing to write into {} as other users can replace files in it",
+                    "Error".red(),
+                    path
+                )
+            }
+            Self::FileAlreadyExists(path) => {
+                write!(
+                    f,
+                    "{}: {} already exists, pass `--force` to replace it (the old file is kept as a backup)",
+                    "Error".red(),
+                    path
+                )
+            }
+            Self::CRLBuildError(err) => {
+                write!(
+                    f,
+                    "{}: Building Certificate Revocation List : {}",
+                    "Error".red(),
+                    err
+                )
+            }
+            Self::UnsupportedSigningKey(key_type) => {
+                write!(
+                    f,
+                    "{}: CRLs and OCSP responses can only be signed with RSA or EC keys, not {}",
+                    "Error".red(),
+                    key_type
+                )
+            }
+            Self::DERSigningError(err) => {
+                write!(f, "{}: Signing with the CA key : {}", "Error".red(), err)
+            }
+            Self::OCSPResponseError(err) => {
+                write!(f, "{}: Building OCSP response : {}", "Error".red(), err)
+            }
+            Self::InvalidOCSPResponder(reason) => {
+                write!(
+                    f,
+                    "{}: The OCSP responder certificate can't be used, {}",
+                    "Error".red(),
+                    reason
+                )
+            }
+            Self::InvalidSubjectAltName(name) => {
+                write!(
+                    f,
+                    "{}: \"{}\" is not a valid DNS name or IP address",
+                    "Error".red(),
+                    name
+                )
+            }
+            Self::UnsupportedSubjectAltName(kind) => {
+                write!(
+                    f,
+                    "{}: Vanish only issues certificates for DNS names and IP addresses, not {}",
+                    "Error".red(),
+                    kind
+                )
+            }
+            Self::InvalidCSRSignature => {
+                write!(
+                    f,
+                    "{}: The signing request is not signed by the key it asks a certificate for",
+                    "Error".red()
+                )
+            }
             Self::SANCouldNotBuildError(err) => {
                 write!(
                     f,
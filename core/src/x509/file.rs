@@ -0,0 +1,213 @@
+use super::errors::{X509Error, X509Result};
+use crate::utils::{civil_time, unix_now};
+use colored::*;
+use std::{
+    fs::{self, File},
+    io,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+use tempfile::{NamedTempFile, PersistError};
+
+pub const KEY_FILE_MODE: u32 = 0o600;
+pub const CERT_FILE_MODE: u32 = 0o644;
+pub const OUTPUT_DIR_MODE: u32 = 0o755;
+pub const DATA_DIR_MODE: u32 = 0o700;
+
+// Output path that streams PEM to standard output instead of a file
+pub const STDOUT_PATH: &str = "-";
+
+static PEM_STDOUT: OnceLock<File> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overwrite {
+    /// Fail if the file already exists
+    Refuse,
+    /// Move the existing file aside to a timestamped backup first
+    Backup,
+    /// Replace the existing file in place, for content that stays equivalent
+    Replace,
+}
+
+impl From<bool> for Overwrite {
+    fn from(force: bool) -> Self {
+        if force {
+            Overwrite::Backup
+        } else {
+            Overwrite::Refuse
+        }
+    }
+}
+
+// Written to a temporary file next to `path` and renamed into place, so a
+// crash never leaves a truncated certificate or key behind.
+pub fn write_file(path: &str, contents: &[u8], mode: u32, overwrite: Overwrite) -> X509Result<()> {
+    if path == STDOUT_PATH {
+        return write_stdout(contents);
+    }
+    let path: &Path = Path::new(path);
+    let directory: &Path = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    check_directory(directory)?;
+    let mut file: NamedTempFile = NamedTempFile::new_in(directory)
+        .map_err(|err: io::Error| X509Error::X509PEMFileCreationError(err))?;
+    set_mode(file.path(), mode)
+        .map_err(|err: io::Error| X509Error::X509PEMFileCreationError(err))?;
+    file.write_all(contents)
+        .map_err(|err: io::Error| X509Error::X509WriteToFileError(err))?;
+    file.as_file()
+        .sync_all()
+        .map_err(|err: io::Error| X509Error::X509WriteToFileError(err))?;
+    match overwrite {
+        Overwrite::Refuse => {
+            file.persist_noclobber(path)
+                .map_err(|err: PersistError| match err.error.kind() {
+                    io::ErrorKind::AlreadyExists => {
+                        X509Error::FileAlreadyExists(path.display().to_string())
+                    }
+                    _ => X509Error::X509WriteToFileError(err.error),
+                })?;
+        }
+        Overwrite::Backup => {
+            if path.exists() {
+                let backup: PathBuf = backup_path(path);
+                fs::rename(path, &backup)
+                    .map_err(|err: io::Error| X509Error::X509WriteToFileError(err))?;
+                println!(
+                    "{}: Existing {} backed up to {}",
+                    "Note".green(),
+                    path.display(),
+                    backup.display()
+                );
+            }
+            file.persist(path)
+                .map_err(|err: PersistError| X509Error::X509WriteToFileError(err.error))?;
+        }
+        Overwrite::Replace => {
+            file.persist(path)
+                .map_err(|err: PersistError| X509Error::X509WriteToFileError(err.error))?;
+        }
+    }
+    Ok(())
+}
+
+// `<file>.<YYYYMMDDhhmmss>.bak`, with a counter if several backups land in the same second
+fn backup_path(path: &Path) -> PathBuf {
+    let file_name: String = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let stamp: String = timestamp();
+    let mut backup: PathBuf = path.with_file_name(format!("{}.{}.bak", file_name, stamp));
+    let mut counter: usize = 2;
+    while backup.exists() {
+        backup = path.with_file_name(format!("{}.{}-{}.bak", file_name, stamp, counter));
+        counter += 1;
+    }
+    backup
+}
+
+fn timestamp() -> String {
+    let (year, month, day, hour, minute, second) = civil_time(unix_now());
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}",
+        year, month, day, hour, minute, second
+    )
+}
+
+fn write_stdout(contents: &[u8]) -> X509Result<()> {
+    match PEM_STDOUT.get() {
+        Some(mut stdout) => stdout.write_all(contents),
+        None => io::stdout().lock().write_all(contents),
+    }
+    .map_err(|err: io::Error| X509Error::X509WriteToFileError(err))
+}
+
+// Keeps the real standard output for PEM data and points file descriptor 1 at
+// standard error, so progress messages never end up in a piped certificate.
+#[cfg(unix)]
+pub fn reserve_stdout_for_pem() -> io::Result<()> {
+    use std::os::unix::io::FromRawFd;
+    if PEM_STDOUT.get().is_some() {
+        return Ok(());
+    }
+    io::stdout().flush()?;
+    // SAFETY: plain descriptor duplication; the duplicate is owned by the File below
+    let pem_fd: i32 = unsafe { libc::dup(libc::STDOUT_FILENO) };
+    if pem_fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `pem_fd` is a freshly duplicated descriptor that nothing else owns
+    let pem_stdout: File = unsafe { File::from_raw_fd(pem_fd) };
+    // SAFETY: both descriptors are valid for the lifetime of the process
+    if unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let _ = PEM_STDOUT.set(pem_stdout);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn reserve_stdout_for_pem() -> io::Result<()> {
+    Ok(())
+}
+
+pub fn create_directory(path: &Path, mode: u32) -> io::Result<()> {
+    let mut builder: fs::DirBuilder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(mode);
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    builder.create(path)
+}
+
+#[cfg(unix)]
+pub fn check_directory(path: &Path) -> X509Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let metadata: fs::Metadata =
+        fs::metadata(path).map_err(|err: io::Error| X509Error::X509PEMFileCreationError(err))?;
+    // Other users could swap files in a group or world-writable directory. The
+    // sticky bit keeps them to their own files, so /tmp-style directories are fine.
+    let mode: u32 = metadata.permissions().mode();
+    if mode & 0o022 != 0 && mode & 0o1000 == 0 {
+        let path: PathBuf = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        return Err(X509Error::InsecureDirectory(path.display().to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn check_directory(_path: &Path) -> X509Result<()> {
+    Ok(())
+}
+
+// Group or world access bits set on a file that should be private to its owner
+#[cfg(unix)]
+pub fn loose_permissions(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode: u32 = fs::metadata(path).ok()?.permissions().mode() & 0o777;
+    (mode & 0o077 != 0).then_some(mode)
+}
+
+#[cfg(not(unix))]
+pub fn loose_permissions(_path: &Path) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
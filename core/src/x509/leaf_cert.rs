@@ -1,22 +1,31 @@
 use super::{
+    ca_req::CAReq,
+    crl::crl_distribution_point,
+    der::{
+        certificate_extension, parse_subject_alt_names, AltName, OID_AUTHORITY_INFO_ACCESS,
+        OID_CRL_DISTRIBUTION_POINTS, OID_SUBJECT_ALT_NAME,
+    },
     distinguished_name::DistinguishedName,
+    encoding::private_key_to_pem,
     errors::{X509Error, X509Result},
+    file::{write_file, Overwrite, CERT_FILE_MODE, KEY_FILE_MODE},
+    ocsp::authority_info_access,
     X509Version,
 };
 use crate::utils::{generate_cert_key_pair, generate_certificate_serial_number};
 use openssl::{
-    asn1::{Asn1Integer, Asn1Time},
+    asn1::{Asn1Integer, Asn1Object, Asn1OctetString, Asn1Time},
     error::ErrorStack,
     hash::MessageDigest,
-    pkey::{PKey, Private, Public},
+    nid::Nid,
+    pkey::{HasPublic, PKey, PKeyRef, Private, Public},
     rsa::Rsa,
+    string::OpensslString,
     x509::{
         extension::{ExtendedKeyUsage, KeyUsage, SubjectAlternativeName},
-        X509Builder, X509Extension, X509Name, X509NameRef, X509Req, X509,
+        X509Builder, X509Extension, X509Name, X509NameEntryRef, X509NameRef, X509Req, X509,
     },
 };
-use std::io::Write;
-use std::{fs::File, io};
 
 pub struct LeafCert {
     _rsa_priv: Rsa<Private>,
@@ -26,6 +35,9 @@ pub struct LeafCert {
     not_before: Asn1Time,
     not_after: Asn1Time,
     serial_number: Asn1Integer,
+    domain: Option<String>,
+    crl_distribution_point: Option<Vec<u8>>,
+    authority_info_access: Option<Vec<u8>>,
 }
 
 impl LeafCert {
@@ -45,6 +57,9 @@ impl LeafCert {
                         not_before,
                         not_after,
                         serial_number,
+                        domain: None,
+                        crl_distribution_point: None,
+                        authority_info_access: None,
                     })
                 }
                 Err(err) => Err(X509Error::InitSerialNumberGenerationError(err)),
@@ -53,6 +68,28 @@ impl LeafCert {
         }
     }
 
+    // The domain becomes the only SAN, and the CN unless one was given, so the
+    // leaf is never mistaken for the CA that shares its organization
+    pub fn with_domain(mut self, domain: &str) -> Self {
+        if self.distinguished_name.common_name.is_none() {
+            self.distinguished_name.common_name = Some(domain.to_string());
+        }
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    // Points relying parties at the CRL `vanish crl` publishes
+    pub fn with_crl_distribution_point(mut self, uri: Option<&str>) -> Self {
+        self.crl_distribution_point = uri.map(crl_distribution_point);
+        self
+    }
+
+    // Points relying parties at a `vanish ocsp serve` responder
+    pub fn with_ocsp_responder(mut self, uri: Option<&str>) -> Self {
+        self.authority_info_access = uri.map(authority_info_access);
+        self
+    }
+
     pub fn generate_certificate(
         self,
         cert_file: &X509,
@@ -60,89 +97,14 @@ impl LeafCert {
         csr: Option<&X509Req>,
     ) -> X509Result<(X509, Option<PKey<Private>>)> {
         if let Some(csr) = csr {
+            CAReq::check_signature(csr)?;
             let pkey: PKey<Public> = csr
                 .public_key()
                 .map_err(|err: ErrorStack| X509Error::ErrorGettingPublicKeyFromCSR(err))?;
-            let subject_name: &X509NameRef = csr.subject_name();
-            let mut cert_builder: X509Builder = X509Builder::new()
-                .map_err(|err: ErrorStack| X509Error::X509CertificateBuilderInitializeError(err))?;
-            cert_builder
-                .set_version(self.version as i32)
-                .map_err(|err: ErrorStack| {
-                    X509Error::X509CertificateBuilerEntryError(err, "Version".to_string())
-                })?;
-            cert_builder
-                .set_subject_name(subject_name)
-                .map_err(|err: ErrorStack| {
-                    X509Error::X509CertificateBuilerEntryError(err, "Subject Name".to_string())
-                })?;
-            cert_builder
-                .set_issuer_name(cert_file.subject_name())
-                .map_err(|err: ErrorStack| {
-                    X509Error::X509CertificateBuilerEntryError(err, "Issuer Name".to_string())
-                })?;
-            cert_builder.set_pubkey(&pkey).map_err(|err: ErrorStack| {
-                X509Error::X509CertificateBuilerEntryError(err, "Public Key".to_string())
-            })?;
-            cert_builder
-                .set_not_before(&self.not_before)
-                .map_err(|err: ErrorStack| {
-                    X509Error::X509CertificateBuilerEntryError(err, "Not Before".to_string())
-                })?;
-            cert_builder
-                .set_not_after(&self.not_after)
-                .map_err(|err: ErrorStack| {
-                    X509Error::X509CertificateBuilerEntryError(err, "Not After".to_string())
-                })?;
-            cert_builder
-                .set_serial_number(&self.serial_number)
-                .map_err(|err: ErrorStack| {
-                    X509Error::X509CertificateBuilerEntryError(err, "Serial Number".to_string())
-                })?;
-            cert_builder
-                .append_extension(
-                    KeyUsage::new()
-                        .digital_signature()
-                        .key_encipherment()
-                        .build()
-                        .map_err(|err: ErrorStack| X509Error::KeyUsageBuildError(err))?,
-                )
-                .map_err(|err: ErrorStack| {
-                    X509Error::X509CertificateBuilerEntryError(err, "KeyUsage".to_string())
-                })?;
-
-            cert_builder
-                .append_extension(
-                    ExtendedKeyUsage::new()
-                        .server_auth()
-                        .client_auth()
-                        .build()
-                        .map_err(|err: ErrorStack| X509Error::ExtendedKeyUsageBuildError(err))?,
-                )
-                .map_err(|err: ErrorStack| {
-                    X509Error::X509CertificateBuilerEntryError(err, "ExtendedKeyUsage".to_string())
-                })?;
-
-            let mut san: SubjectAlternativeName = SubjectAlternativeName::new();
-            // Replace with the domain name ?
-            san.dns("localhost");
-            let san: X509Extension = san
-                .build(&cert_builder.x509v3_context(Some(&cert_file.as_ref()), None))
-                .unwrap();
-
-            cert_builder
-                .append_extension(san)
-                .map_err(|err: ErrorStack| {
-                    X509Error::X509CertificateBuilerEntryError(err, "SAN".to_string())
-                })?;
-
-            cert_builder
-                .sign(key_file, MessageDigest::sha256())
-                .map_err(|err: ErrorStack| {
-                    X509Error::X509CertificateBuilerEntryError(err, "Sign".to_string())
-                })?;
-
-            return Ok((cert_builder.build(), None));
+            let names: Vec<AltName> = CAReq::requested_names(csr)?;
+            let certificate: X509 =
+                self.issue(csr.subject_name(), &pkey, names, cert_file, key_file)?;
+            Ok((certificate, None))
         } else {
             match generate_cert_key_pair() {
                 Ok((_rsa_priv, pkey)) => {
@@ -226,12 +188,10 @@ impl LeafCert {
                             )
                         })?;
 
-                    let mut san: SubjectAlternativeName = SubjectAlternativeName::new();
-                    // Replace with the domain name ?
-                    san.dns("localhost");
-                    let san: X509Extension = san
-                        .build(&cert_builder.x509v3_context(Some(&cert_file.as_ref()), None))
-                        .unwrap();
+                    let name: AltName =
+                        AltName::parse(self.domain.as_deref().unwrap_or("localhost"))?;
+                    let san: X509Extension =
+                        LeafCert::subject_alt_name(&[name], &cert_builder, cert_file)?;
 
                     cert_builder
                         .append_extension(san)
@@ -239,39 +199,243 @@ impl LeafCert {
                             X509Error::X509CertificateBuilerEntryError(err, "SAN".to_string())
                         })?;
 
+                    LeafCert::append_revocation_pointers(
+                        &mut cert_builder,
+                        self.crl_distribution_point.as_deref(),
+                        self.authority_info_access.as_deref(),
+                    )?;
+
                     cert_builder
                         .sign(key_file, MessageDigest::sha256())
                         .map_err(|err: ErrorStack| {
                             X509Error::X509CertificateBuilerEntryError(err, "Sign".to_string())
                         })?;
 
-                    return Ok((cert_builder.build(), Some(pkey)));
+                    Ok((cert_builder.build(), Some(pkey)))
                 }
                 Err(err) => Err(X509Error::InitCARequestCertKeyPairError(err))?,
             }
         }
     }
 
-    pub fn save_cert(cert: &X509, path: &str) -> X509Result<()> {
-        let mut file: File = File::create(path)
-            .map_err(|err: io::Error| X509Error::X509PEMFileCreationError(err))?;
-        file.write_all(
-            &cert
-                .to_pem()
-                .map_err(|err: ErrorStack| X509Error::PEMEncodingError(err))?,
+    // Re-issues `certificate` with its subject and SANs for `public_key`, which
+    // is either the key it already certifies or a freshly generated one
+    pub fn renew_certificate<T: HasPublic>(
+        mut self,
+        certificate: &X509,
+        public_key: &PKeyRef<T>,
+        cert_file: &X509,
+        key_file: &PKey<Private>,
+    ) -> X509Result<X509> {
+        let names: Vec<AltName> = match certificate_extension(certificate, OID_SUBJECT_ALT_NAME)? {
+            Some((_critical, san)) => parse_subject_alt_names(&san)?,
+            None => Vec::new(),
+        };
+        if self.crl_distribution_point.is_none() {
+            self.crl_distribution_point =
+                certificate_extension(certificate, OID_CRL_DISTRIBUTION_POINTS)?
+                    .map(|(_critical, value)| value);
+        }
+        if self.authority_info_access.is_none() {
+            self.authority_info_access =
+                certificate_extension(certificate, OID_AUTHORITY_INFO_ACCESS)?
+                    .map(|(_critical, value)| value);
+        }
+        self.issue(
+            certificate.subject_name(),
+            public_key,
+            names,
+            cert_file,
+            key_file,
         )
-        .map_err(|err: io::Error| X509Error::X509WriteToFileError(err))?;
+    }
+
+    fn issue<T: HasPublic>(
+        self,
+        subject_name: &X509NameRef,
+        pkey: &PKeyRef<T>,
+        names: Vec<AltName>,
+        cert_file: &X509,
+        key_file: &PKey<Private>,
+    ) -> X509Result<X509> {
+        let mut cert_builder: X509Builder = X509Builder::new()
+            .map_err(|err: ErrorStack| X509Error::X509CertificateBuilderInitializeError(err))?;
+        cert_builder
+            .set_version(self.version as i32)
+            .map_err(|err: ErrorStack| {
+                X509Error::X509CertificateBuilerEntryError(err, "Version".to_string())
+            })?;
+        cert_builder
+            .set_subject_name(subject_name)
+            .map_err(|err: ErrorStack| {
+                X509Error::X509CertificateBuilerEntryError(err, "Subject Name".to_string())
+            })?;
+        cert_builder
+            .set_issuer_name(cert_file.subject_name())
+            .map_err(|err: ErrorStack| {
+                X509Error::X509CertificateBuilerEntryError(err, "Issuer Name".to_string())
+            })?;
+        cert_builder.set_pubkey(pkey).map_err(|err: ErrorStack| {
+            X509Error::X509CertificateBuilerEntryError(err, "Public Key".to_string())
+        })?;
+        cert_builder
+            .set_not_before(&self.not_before)
+            .map_err(|err: ErrorStack| {
+                X509Error::X509CertificateBuilerEntryError(err, "Not Before".to_string())
+            })?;
+        cert_builder
+            .set_not_after(&self.not_after)
+            .map_err(|err: ErrorStack| {
+                X509Error::X509CertificateBuilerEntryError(err, "Not After".to_string())
+            })?;
+        cert_builder
+            .set_serial_number(&self.serial_number)
+            .map_err(|err: ErrorStack| {
+                X509Error::X509CertificateBuilerEntryError(err, "Serial Number".to_string())
+            })?;
+        cert_builder
+            .append_extension(
+                KeyUsage::new()
+                    .digital_signature()
+                    .key_encipherment()
+                    .build()
+                    .map_err(|err: ErrorStack| X509Error::KeyUsageBuildError(err))?,
+            )
+            .map_err(|err: ErrorStack| {
+                X509Error::X509CertificateBuilerEntryError(err, "KeyUsage".to_string())
+            })?;
+
+        cert_builder
+            .append_extension(
+                ExtendedKeyUsage::new()
+                    .server_auth()
+                    .client_auth()
+                    .build()
+                    .map_err(|err: ErrorStack| X509Error::ExtendedKeyUsageBuildError(err))?,
+            )
+            .map_err(|err: ErrorStack| {
+                X509Error::X509CertificateBuilerEntryError(err, "ExtendedKeyUsage".to_string())
+            })?;
+
+        let names: Vec<AltName> = if names.is_empty() {
+            // Browsers ignore the CN, so carry it over as the only SAN if present
+            let common_name: Option<String> = subject_name
+                .entries_by_nid(Nid::COMMONNAME)
+                .next()
+                .and_then(|entry: &X509NameEntryRef| entry.data().as_utf8().ok())
+                .map(|common_name: OpensslString| common_name.to_string());
+            vec![AltName::parse(
+                common_name.as_deref().unwrap_or("localhost"),
+            )?]
+        } else {
+            names
+        };
+        let san: X509Extension = LeafCert::subject_alt_name(&names, &cert_builder, cert_file)?;
+
+        cert_builder
+            .append_extension(san)
+            .map_err(|err: ErrorStack| {
+                X509Error::X509CertificateBuilerEntryError(err, "SAN".to_string())
+            })?;
+
+        LeafCert::append_revocation_pointers(
+            &mut cert_builder,
+            self.crl_distribution_point.as_deref(),
+            self.authority_info_access.as_deref(),
+        )?;
+
+        cert_builder
+            .sign(key_file, MessageDigest::sha256())
+            .map_err(|err: ErrorStack| {
+                X509Error::X509CertificateBuilerEntryError(err, "Sign".to_string())
+            })?;
+        Ok(cert_builder.build())
+    }
+
+    // Rebuilt from the accepted names and never critical, whatever the request
+    // or the renewed certificate carried
+    fn subject_alt_name(
+        names: &[AltName],
+        cert_builder: &X509Builder,
+        cert_file: &X509,
+    ) -> X509Result<X509Extension> {
+        let mut san: SubjectAlternativeName = SubjectAlternativeName::new();
+        for name in names {
+            match name {
+                AltName::Dns(dns) => san.dns(dns),
+                AltName::Ip(ip) => san.ip(&ip.to_string()),
+            };
+        }
+        san.build(&cert_builder.x509v3_context(Some(cert_file.as_ref()), None))
+            .map_err(|err: ErrorStack| X509Error::SANCouldNotBuildError(err))
+    }
+
+    fn append_revocation_pointers(
+        cert_builder: &mut X509Builder,
+        crl_distribution_point: Option<&[u8]>,
+        authority_info_access: Option<&[u8]>,
+    ) -> X509Result<()> {
+        if let Some(crl_distribution_point) = crl_distribution_point {
+            LeafCert::append_raw_extension(
+                cert_builder,
+                "2.5.29.31",
+                crl_distribution_point,
+                "CRL Distribution Point",
+            )?;
+        }
+        if let Some(authority_info_access) = authority_info_access {
+            LeafCert::append_raw_extension(
+                cert_builder,
+                "1.3.6.1.5.5.7.1.1",
+                authority_info_access,
+                "Authority Information Access",
+            )?;
+        }
         Ok(())
     }
 
-    pub fn save_key(key: &PKey<Private>, path: &str) -> X509Result<()> {
-        let mut file: File = File::create(path)
-            .map_err(|err: io::Error| X509Error::X509PEMFileCreationError(err))?;
-        file.write_all(
-            &key.private_key_to_pem_pkcs8()
-                .map_err(|err: ErrorStack| X509Error::PKCS8EncodingError(err))?,
+    fn append_raw_extension(
+        cert_builder: &mut X509Builder,
+        oid: &str,
+        value: &[u8],
+        name: &str,
+    ) -> X509Result<()> {
+        let oid: Asn1Object = Asn1Object::from_str(oid).map_err(|err: ErrorStack| {
+            X509Error::X509CertificateBuilerEntryError(err, name.to_string())
+        })?;
+        let value: Asn1OctetString =
+            Asn1OctetString::new_from_bytes(value).map_err(|err: ErrorStack| {
+                X509Error::X509CertificateBuilerEntryError(err, name.to_string())
+            })?;
+        let extension: X509Extension =
+            X509Extension::new_from_der(&oid, false, &value).map_err(|err: ErrorStack| {
+                X509Error::X509CertificateBuilerEntryError(err, name.to_string())
+            })?;
+        cert_builder
+            .append_extension(extension)
+            .map_err(|err: ErrorStack| {
+                X509Error::X509CertificateBuilerEntryError(err, name.to_string())
+            })
+    }
+
+    pub fn save_cert(cert: &X509, path: &str, overwrite: Overwrite) -> X509Result<()> {
+        let cert_pem: Vec<u8> = cert
+            .to_pem()
+            .map_err(|err: ErrorStack| X509Error::PEMEncodingError(err))?;
+        write_file(path, &cert_pem, CERT_FILE_MODE, overwrite)
+    }
+
+    pub fn save_key(
+        key: &PKey<Private>,
+        path: &str,
+        passphrase: Option<&[u8]>,
+        overwrite: Overwrite,
+    ) -> X509Result<()> {
+        write_file(
+            path,
+            &private_key_to_pem(key, passphrase)?,
+            KEY_FILE_MODE,
+            overwrite,
         )
-        .map_err(|err: io::Error| X509Error::X509WriteToFileError(err))?;
-        Ok(())
     }
 }
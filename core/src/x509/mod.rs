@@ -1,15 +1,48 @@
 use distinguished_name::DistinguishedName;
 use errors::X509Result;
 use lazy_static::lazy_static;
-use std::path::PathBuf;
+use std::{env, ffi::OsString, path::PathBuf};
+pub mod bundle;
 pub mod ca_cert;
 pub mod ca_req;
+pub mod crl;
+pub mod der;
+pub mod describe;
 pub mod distinguished_name;
+pub mod encoding;
 mod errors;
+pub mod file;
 pub mod leaf_cert;
+pub mod ocsp;
 
+pub const CAROOT_ENV: &str = "VANISH_CAROOT";
+
+#[cfg(not(test))]
+lazy_static! {
+    pub static ref DATA_DIR: Option<PathBuf> = resolve_data_dir(env::var_os(CAROOT_ENV));
+}
+
+// Tests that issue or revoke certificates keep their ledger out of the real
+// data directory
+#[cfg(test)]
 lazy_static! {
-    pub static ref DATA_DIR: Option<PathBuf> = dirs::data_dir().map(|path| path.join("vanish"));
+    pub static ref DATA_DIR: Option<PathBuf> =
+        Some(env::temp_dir().join(format!("vanish-tests-{}", std::process::id())));
+}
+
+// `VANISH_CAROOT` (also set by `--ca-dir`) wins over the platform data directory
+pub fn resolve_data_dir(caroot: Option<OsString>) -> Option<PathBuf> {
+    match caroot.filter(|caroot: &OsString| !caroot.is_empty()) {
+        Some(caroot) => {
+            let caroot: PathBuf = PathBuf::from(caroot);
+            if caroot.is_absolute() {
+                Some(caroot)
+            } else {
+                env::current_dir().ok().map(|cwd: PathBuf| cwd.join(caroot))
+            }
+        }
+        None => dirs::data_dir().map(|path| path.join("vanish")),
+    }
 }
 
 #[derive(Debug)]
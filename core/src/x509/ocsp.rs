@@ -0,0 +1,426 @@
+use super::{
+    crl::RevocationReason,
+    der::{
+        certificate_extension, encode, encode_generalized_time, encode_sequence, sign_fields,
+        DerElement, OID_EXTENDED_KEY_USAGE, TAG_BIT_STRING, TAG_ENUMERATED, TAG_INTEGER,
+        TAG_OCTET_STRING, TAG_OID, TAG_SEQUENCE,
+    },
+    errors::{X509Error, X509Result},
+};
+use crate::utils::{generate_cert_key_pair, generate_certificate_serial_number};
+use openssl::{
+    asn1::{Asn1Integer, Asn1Object, Asn1OctetString, Asn1Time},
+    bn::BigNum,
+    error::ErrorStack,
+    hash::{hash, DigestBytes, MessageDigest},
+    nid::Nid,
+    pkey::{PKey, Private},
+    x509::{
+        extension::{BasicConstraints, ExtendedKeyUsage, KeyUsage},
+        X509Builder, X509Extension, X509Name, X509NameBuilder, X509,
+    },
+};
+
+// 1.3.6.1.5.5.7.48.1
+const OID_AD_OCSP: &[u8] = &[0x06, 0x08, 0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01];
+// 1.3.6.1.5.5.7.48.1.1
+const OID_OCSP_BASIC: &[u8] = &[
+    0x06, 0x09, 0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01,
+];
+// 1.3.6.1.5.5.7.48.1.2
+const OID_OCSP_NONCE: &[u8] = &[
+    0x06, 0x09, 0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x02,
+];
+// 1.3.6.1.5.5.7.3.9
+const OID_OCSP_SIGNING: &[u8] = &[0x06, 0x08, 0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x09];
+// 1.3.14.3.2.26
+const OID_SHA1: &[u8] = &[0x06, 0x05, 0x2b, 0x0e, 0x03, 0x02, 0x1a];
+// 2.16.840.1.101.3.4.2.{1,2,3}
+const OID_SHA256: &[u8] = &[
+    0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01,
+];
+const OID_SHA384: &[u8] = &[
+    0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02,
+];
+const OID_SHA512: &[u8] = &[
+    0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03,
+];
+
+// OCSPResponseStatus values for requests that get no signed answer
+pub const MALFORMED_REQUEST: u8 = 1;
+pub const INTERNAL_ERROR: u8 = 2;
+
+const DELEGATED_RESPONDER_DAYS: u32 = 30;
+
+// One certificate asked about, identified by hashes of its issuer
+pub struct CertId {
+    raw: Vec<u8>,
+    digest: Option<MessageDigest>,
+    issuer_name_hash: Vec<u8>,
+    issuer_key_hash: Vec<u8>,
+    pub serial: String,
+}
+
+impl CertId {
+    fn parse(cert_id: &DerElement) -> X509Result<Self> {
+        let fields: Vec<DerElement> = cert_id.expect(TAG_SEQUENCE)?.children()?;
+        let [hash_algorithm, issuer_name_hash, issuer_key_hash, serial] = fields.as_slice() else {
+            return Err(X509Error::DERParseError(
+                "CertID must have four fields".to_string(),
+            ));
+        };
+        let algorithm: Vec<DerElement> = hash_algorithm.expect(TAG_SEQUENCE)?.children()?;
+        let digest: Option<MessageDigest> = match algorithm.first().map(|oid: &DerElement| oid.raw)
+        {
+            Some(OID_SHA1) => Some(MessageDigest::sha1()),
+            Some(OID_SHA256) => Some(MessageDigest::sha256()),
+            Some(OID_SHA384) => Some(MessageDigest::sha384()),
+            Some(OID_SHA512) => Some(MessageDigest::sha512()),
+            _ => None,
+        };
+        let serial: String = BigNum::from_slice(serial.expect(TAG_INTEGER)?.contents)
+            .and_then(|serial: BigNum| serial.to_hex_str().map(|hex| hex.to_string()))
+            .map_err(|err: ErrorStack| X509Error::OCSPResponseError(err))?;
+        Ok(CertId {
+            raw: cert_id.raw.to_vec(),
+            digest,
+            issuer_name_hash: issuer_name_hash.expect(TAG_OCTET_STRING)?.contents.to_vec(),
+            issuer_key_hash: issuer_key_hash.expect(TAG_OCTET_STRING)?.contents.to_vec(),
+            serial,
+        })
+    }
+
+    // Whether the hashes name `ca_cert`; anything else is not ours to answer for
+    pub fn issued_by(&self, ca_cert: &X509) -> X509Result<bool> {
+        let Some(digest) = self.digest else {
+            return Ok(false);
+        };
+        let name: Vec<u8> = ca_cert
+            .subject_name()
+            .to_der()
+            .map_err(|err: ErrorStack| X509Error::OCSPResponseError(err))?;
+        let name_hash: DigestBytes =
+            hash(digest, &name).map_err(|err: ErrorStack| X509Error::OCSPResponseError(err))?;
+        let key_hash: DigestBytes = hash(digest, &public_key_bits(ca_cert)?)
+            .map_err(|err: ErrorStack| X509Error::OCSPResponseError(err))?;
+        Ok(*name_hash == *self.issuer_name_hash && *key_hash == *self.issuer_key_hash)
+    }
+}
+
+pub struct OcspRequest {
+    pub cert_ids: Vec<CertId>,
+    nonce: Option<Vec<u8>>,
+}
+
+impl OcspRequest {
+    // Signed requests are accepted, but the signature is ignored, as
+    // RFC 6960 allows for responders that don't require them
+    pub fn parse(der: &[u8]) -> X509Result<Self> {
+        let request: DerElement = DerElement::parse_exact(der)?.expect(TAG_SEQUENCE)?;
+        let tbs_request: DerElement = match request.children()?.first() {
+            Some(tbs_request) => tbs_request.expect(TAG_SEQUENCE)?,
+            None => return Err(X509Error::DERParseError("empty OCSPRequest".to_string())),
+        };
+        let mut cert_ids: Vec<CertId> = Vec::new();
+        let mut nonce: Option<Vec<u8>> = None;
+        for field in tbs_request.children()? {
+            match field.tag {
+                TAG_SEQUENCE => {
+                    for request in field.children()? {
+                        if let Some(cert_id) = request.expect(TAG_SEQUENCE)?.children()?.first() {
+                            cert_ids.push(CertId::parse(cert_id)?);
+                        }
+                    }
+                }
+                // [2] EXPLICIT requestExtensions
+                0xa2 => {
+                    if let Some(extensions) = field.children()?.first() {
+                        for extension in extensions.expect(TAG_SEQUENCE)?.children()? {
+                            let is_nonce: bool = extension
+                                .children()?
+                                .first()
+                                .is_some_and(|oid: &DerElement| oid.raw == OID_OCSP_NONCE);
+                            if is_nonce {
+                                nonce = Some(extension.raw.to_vec());
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if cert_ids.is_empty() {
+            return Err(X509Error::DERParseError(
+                "OCSPRequest asks about no certificates".to_string(),
+            ));
+        }
+        Ok(OcspRequest { cert_ids, nonce })
+    }
+}
+
+pub enum CertStatus {
+    Good,
+    Revoked {
+        revoked_at: i64,
+        reason: RevocationReason,
+    },
+    Unknown,
+}
+
+impl CertStatus {
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::Good => encode(0x80, &[]),
+            Self::Revoked { revoked_at, reason } => {
+                let mut revoked_info: Vec<u8> = encode_generalized_time(*revoked_at);
+                if *reason != RevocationReason::Unspecified {
+                    revoked_info.extend(encode(0xa0, &encode(TAG_ENUMERATED, &[reason.code()])));
+                }
+                encode(0xa1, &revoked_info)
+            }
+            Self::Unknown => encode(0x82, &[]),
+        }
+    }
+}
+
+// Signs responses either as the CA itself or as a certificate the CA
+// delegated OCSP signing to, which is then sent along with every response
+pub struct OcspResponder {
+    pub cert: X509,
+    key: PKey<Private>,
+    delegated: bool,
+}
+
+impl OcspResponder {
+    pub fn ca(ca_cert: X509, ca_key: PKey<Private>) -> Self {
+        OcspResponder {
+            cert: ca_cert,
+            key: ca_key,
+            delegated: false,
+        }
+    }
+
+    pub fn delegated(cert: X509, key: PKey<Private>, ca_cert: &X509) -> X509Result<Self> {
+        let issued_by_ca: bool = ca_cert
+            .public_key()
+            .and_then(|ca_key| cert.verify(&ca_key))
+            .map_err(|err: ErrorStack| X509Error::OCSPResponseError(err))?;
+        if !issued_by_ca {
+            return Err(X509Error::InvalidOCSPResponder(
+                "it was not issued by the CA".to_string(),
+            ));
+        }
+        let matches_key: bool = cert
+            .public_key()
+            .map(|public_key| public_key.public_eq(&key))
+            .map_err(|err: ErrorStack| X509Error::OCSPResponseError(err))?;
+        if !matches_key {
+            return Err(X509Error::InvalidOCSPResponder(
+                "the key does not belong to the certificate".to_string(),
+            ));
+        }
+        let mut ocsp_signing: bool = false;
+        if let Some((_critical, value)) = certificate_extension(&cert, OID_EXTENDED_KEY_USAGE)? {
+            for purpose in DerElement::parse_exact(&value)?
+                .expect(TAG_SEQUENCE)?
+                .children()?
+            {
+                ocsp_signing |= purpose.expect(TAG_OID)?.raw == OID_OCSP_SIGNING;
+            }
+        }
+        if !ocsp_signing {
+            return Err(X509Error::InvalidOCSPResponder(
+                "its Extended Key Usage does not include OCSPSigning".to_string(),
+            ));
+        }
+        Ok(OcspResponder {
+            cert,
+            key,
+            delegated: true,
+        })
+    }
+
+    // A short-lived responder certificate, so the CA key only has to be
+    // unlocked once at startup
+    pub fn generate_delegated(ca_cert: &X509, ca_key: &PKey<Private>) -> X509Result<Self> {
+        let (_rsa_priv, key) =
+            generate_cert_key_pair().map_err(X509Error::InitCARequestCertKeyPairError)?;
+        let serial_number: Asn1Integer = generate_certificate_serial_number()
+            .map_err(X509Error::InitSerialNumberGenerationError)?;
+        let not_before: Asn1Time = Asn1Time::days_from_now(0)
+            .map_err(|err: ErrorStack| X509Error::GenerateNotBeforeError(err))?;
+        let not_after: Asn1Time = Asn1Time::days_from_now(DELEGATED_RESPONDER_DAYS)
+            .map_err(|err: ErrorStack| X509Error::GenerateNotAfterError(err))?;
+
+        let mut name: X509NameBuilder = X509NameBuilder::new()
+            .map_err(|err: ErrorStack| X509Error::X509NameBuilderInitializeError(err))?;
+        name.append_entry_by_nid(Nid::COMMONNAME, "Vanish OCSP Responder")
+            .map_err(|err: ErrorStack| {
+                X509Error::X509NameBuilderEntryError(
+                    err,
+                    "CN".to_string(),
+                    "Vanish OCSP Responder".to_string(),
+                )
+            })?;
+        let name: X509Name = name.build();
+
+        let entry_error = |entry: &str| {
+            let entry: String = entry.to_string();
+            move |err: ErrorStack| X509Error::X509CertificateBuilerEntryError(err, entry)
+        };
+        let mut cert_builder: X509Builder = X509Builder::new()
+            .map_err(|err: ErrorStack| X509Error::X509CertificateBuilderInitializeError(err))?;
+        cert_builder
+            .set_version(2)
+            .map_err(entry_error("Version"))?;
+        cert_builder
+            .set_subject_name(&name)
+            .map_err(entry_error("Subject Name"))?;
+        cert_builder
+            .set_issuer_name(ca_cert.subject_name())
+            .map_err(entry_error("Issuer Name"))?;
+        cert_builder
+            .set_pubkey(&key)
+            .map_err(entry_error("Public Key"))?;
+        cert_builder
+            .set_not_before(&not_before)
+            .map_err(entry_error("Not Before"))?;
+        cert_builder
+            .set_not_after(&not_after)
+            .map_err(entry_error("Not After"))?;
+        cert_builder
+            .set_serial_number(&serial_number)
+            .map_err(entry_error("Serial Number"))?;
+        cert_builder
+            .append_extension(
+                BasicConstraints::new()
+                    .critical()
+                    .build()
+                    .map_err(|err: ErrorStack| X509Error::BasicConstraintsInitializeError(err))?,
+            )
+            .map_err(entry_error("BasicConstraints"))?;
+        cert_builder
+            .append_extension(
+                KeyUsage::new()
+                    .critical()
+                    .digital_signature()
+                    .build()
+                    .map_err(|err: ErrorStack| X509Error::KeyUsageBuildError(err))?,
+            )
+            .map_err(entry_error("KeyUsage"))?;
+        cert_builder
+            .append_extension(
+                ExtendedKeyUsage::new()
+                    .other("OCSPSigning")
+                    .build()
+                    .map_err(|err: ErrorStack| X509Error::ExtendedKeyUsageBuildError(err))?,
+            )
+            .map_err(entry_error("ExtendedKeyUsage"))?;
+        // id-pkix-ocsp-nocheck, so clients don't ask the responder about itself
+        let nocheck: X509Extension = Asn1Object::from_str("1.3.6.1.5.5.7.48.1.5")
+            .and_then(|oid: Asn1Object| {
+                let value: Asn1OctetString = Asn1OctetString::new_from_bytes(&[0x05, 0x00])?;
+                X509Extension::new_from_der(&oid, false, &value)
+            })
+            .map_err(entry_error("OCSP No Check"))?;
+        cert_builder
+            .append_extension(nocheck)
+            .map_err(entry_error("OCSP No Check"))?;
+        cert_builder
+            .sign(ca_key, MessageDigest::sha256())
+            .map_err(entry_error("Sign"))?;
+
+        Ok(OcspResponder {
+            cert: cert_builder.build(),
+            key,
+            delegated: true,
+        })
+    }
+
+    // A successful BasicOCSPResponse with one SingleResponse per CertID
+    pub fn respond(
+        &self,
+        request: &OcspRequest,
+        statuses: &[CertStatus],
+        this_update: i64,
+        next_update: i64,
+    ) -> X509Result<Vec<u8>> {
+        let key_hash: DigestBytes = hash(MessageDigest::sha1(), &public_key_bits(&self.cert)?)
+            .map_err(|err: ErrorStack| X509Error::OCSPResponseError(err))?;
+        let responses: Vec<Vec<u8>> = request
+            .cert_ids
+            .iter()
+            .zip(statuses)
+            .map(|(cert_id, status): (&CertId, &CertStatus)| {
+                encode_sequence(&[
+                    cert_id.raw.clone(),
+                    status.encode(),
+                    encode_generalized_time(this_update),
+                    encode(0xa0, &encode_generalized_time(next_update)),
+                ])
+            })
+            .collect();
+        let mut response_data: Vec<Vec<u8>> = vec![
+            // [2] EXPLICIT byKey
+            encode(0xa2, &encode(TAG_OCTET_STRING, &key_hash)),
+            encode_generalized_time(this_update),
+            encode_sequence(&responses),
+        ];
+        if let Some(nonce) = &request.nonce {
+            response_data.push(encode(0xa1, &encode(TAG_SEQUENCE, nonce)));
+        }
+
+        let mut basic_response: Vec<Vec<u8>> =
+            sign_fields(encode_sequence(&response_data), &self.key)?;
+        if self.delegated {
+            let cert: Vec<u8> = self
+                .cert
+                .to_der()
+                .map_err(|err: ErrorStack| X509Error::OCSPResponseError(err))?;
+            basic_response.push(encode(0xa0, &encode_sequence(&[cert])));
+        }
+
+        Ok(encode_sequence(&[
+            encode(TAG_ENUMERATED, &[0]),
+            encode(
+                0xa0,
+                &encode_sequence(&[
+                    OID_OCSP_BASIC.to_vec(),
+                    encode(TAG_OCTET_STRING, &encode_sequence(&basic_response)),
+                ]),
+            ),
+        ]))
+    }
+}
+
+// An OCSPResponse carrying only an error status
+pub fn error_response(status: u8) -> Vec<u8> {
+    encode_sequence(&[encode(TAG_ENUMERATED, &[status])])
+}
+
+// The value of an AuthorityInfoAccess extension naming a single OCSP responder
+pub fn authority_info_access(uri: &str) -> Vec<u8> {
+    encode_sequence(&[encode_sequence(&[
+        OID_AD_OCSP.to_vec(),
+        encode(0x86, uri.as_bytes()),
+    ])])
+}
+
+// The subjectPublicKey BIT STRING contents without the unused-bits octet,
+// which is what OCSP key hashes are taken over
+fn public_key_bits(cert: &X509) -> X509Result<Vec<u8>> {
+    let spki: Vec<u8> = cert
+        .public_key()
+        .and_then(|key| key.public_key_to_der())
+        .map_err(|err: ErrorStack| X509Error::OCSPResponseError(err))?;
+    let spki: DerElement = DerElement::parse_exact(&spki)?.expect(TAG_SEQUENCE)?;
+    match spki.children()?.get(1) {
+        Some(bits) => match bits.expect(TAG_BIT_STRING)?.contents.split_first() {
+            Some((_unused_bits, key)) => Ok(key.to_vec()),
+            None => Err(X509Error::DERParseError("empty public key".to_string())),
+        },
+        None => Err(X509Error::DERParseError(
+            "SubjectPublicKeyInfo without a key".to_string(),
+        )),
+    }
+}
@@ -0,0 +1,878 @@
+mod bundle {
+    use crate::x509::{
+        bundle::BundleLayout, ca_cert::CACert, distinguished_name::DistinguishedName,
+        leaf_cert::LeafCert, Certificate,
+    };
+    use openssl::{
+        pkey::{PKey, Private},
+        x509::X509,
+    };
+
+    fn distinguished_name() -> DistinguishedName {
+        DistinguishedName {
+            common_name: None,
+            organization: "Vanish".to_string(),
+            country: None,
+            state: None,
+        }
+    }
+
+    fn issue() -> (X509, PKey<Private>, X509) {
+        let (ca_cert, ca_key) = CACert::new(distinguished_name())
+            .unwrap()
+            .generate_certificate()
+            .unwrap();
+        let (leaf, key) = LeafCert::new(distinguished_name())
+            .unwrap()
+            .generate_certificate(&ca_cert, &ca_key, None)
+            .unwrap();
+        (leaf, key.unwrap(), ca_cert)
+    }
+
+    #[test]
+    fn fullchain_is_leaf_then_chain() {
+        let (leaf, _key, ca_cert) = issue();
+        let bundle: Vec<u8> = BundleLayout::Fullchain
+            .build(&leaf, None, std::slice::from_ref(&ca_cert), None)
+            .unwrap();
+        let certs: Vec<X509> = X509::stack_from_pem(&bundle).unwrap();
+        assert_eq!(certs.len(), 2, "Expected leaf and CA in the bundle");
+        assert_eq!(certs[0].to_der().unwrap(), leaf.to_der().unwrap());
+        assert_eq!(certs[1].to_der().unwrap(), ca_cert.to_der().unwrap());
+    }
+
+    #[test]
+    fn chain_excludes_leaf() {
+        let (leaf, _key, ca_cert) = issue();
+        let bundle: Vec<u8> = BundleLayout::Chain
+            .build(&leaf, None, std::slice::from_ref(&ca_cert), None)
+            .unwrap();
+        let certs: Vec<X509> = X509::stack_from_pem(&bundle).unwrap();
+        assert_eq!(certs.len(), 1, "Expected only the CA in the chain");
+        assert_eq!(certs[0].to_der().unwrap(), ca_cert.to_der().unwrap());
+    }
+
+    #[test]
+    fn combined_contains_key() {
+        let (leaf, key, ca_cert) = issue();
+        let bundle: Vec<u8> = BundleLayout::Combined
+            .build(&leaf, Some(&key), &[ca_cert], None)
+            .unwrap();
+        let parsed: PKey<Private> = PKey::private_key_from_pem(&bundle).unwrap();
+        assert!(
+            parsed.public_eq(&key),
+            "Expected the leaf key in the bundle"
+        );
+        assert!(BundleLayout::Combined
+            .build(&leaf, None, &[], None)
+            .is_err());
+    }
+}
+
+mod ca_req {
+    use crate::x509::{
+        ca_cert::CACert, ca_req::CAReq, distinguished_name::DistinguishedName,
+        encoding::pem_blocks, leaf_cert::LeafCert, Certificate,
+    };
+    use openssl::x509::X509Req;
+
+    fn distinguished_name() -> DistinguishedName {
+        DistinguishedName {
+            common_name: Some("api.test".to_string()),
+            organization: "Vanish".to_string(),
+            country: None,
+            state: None,
+        }
+    }
+
+    fn request() -> X509Req {
+        CAReq::new(distinguished_name())
+            .unwrap()
+            .generate_certificate()
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn splits_multiple_pem_requests() {
+        let mut data: Vec<u8> = request().to_pem().unwrap();
+        data.extend(b"some comment between blocks\n");
+        data.extend(request().to_pem().unwrap());
+        let blocks: Vec<&[u8]> = pem_blocks(&data, &["CERTIFICATE REQUEST"]);
+        assert_eq!(blocks.len(), 2, "Expected both requests to be found");
+        for block in blocks {
+            assert!(X509Req::from_pem(block).is_ok());
+        }
+    }
+
+    #[test]
+    fn parses_posted_pem_and_der_requests() {
+        let mut pem: Vec<u8> = request().to_pem().unwrap();
+        pem.extend(request().to_pem().unwrap());
+        assert_eq!(CAReq::parse_csrs(&pem).unwrap().len(), 2);
+        let der: Vec<u8> = request().to_der().unwrap();
+        assert_eq!(CAReq::parse_csrs(&der).unwrap().len(), 1);
+        assert!(CAReq::parse_csrs(b"junk").is_err());
+    }
+
+    #[test]
+    fn refuses_to_sign_requests_with_a_broken_signature() {
+        let (ca_cert, ca_key) = CACert::new(distinguished_name())
+            .unwrap()
+            .generate_certificate()
+            .unwrap();
+        let mut der: Vec<u8> = request().to_der().unwrap();
+        // The signature is the last field, so this only breaks the signature
+        let last: usize = der.len() - 1;
+        der[last] ^= 0xff;
+        let forged: X509Req = X509Req::from_der(&der).unwrap();
+        assert!(CAReq::check_signature(&forged).is_err());
+        assert!(LeafCert::new(distinguished_name())
+            .unwrap()
+            .generate_certificate(&ca_cert, &ca_key, Some(&forged))
+            .is_err());
+        assert!(LeafCert::new(distinguished_name())
+            .unwrap()
+            .generate_certificate(&ca_cert, &ca_key, Some(&request()))
+            .is_ok());
+    }
+
+    #[test]
+    fn finds_requested_subject_alt_name() {
+        let (critical, value) = CAReq::requested_subject_alt_name(&request())
+            .unwrap()
+            .expect("Expected a SAN in the request");
+        assert!(!critical);
+        // GeneralNames holding a single dNSName of "localhost"
+        assert_eq!(value, b"\x30\x0b\x82\x09localhost");
+    }
+}
+
+mod encoding {
+    use crate::{
+        utils::generate_cert_key_pair,
+        x509::{
+            ca_cert::CACert,
+            distinguished_name::DistinguishedName,
+            encoding::{
+                carries_private_key, parse_pkcs12, private_key_to_pem, read_certificate,
+                read_pkcs12, read_private_key, Encoding,
+            },
+            Certificate,
+        },
+    };
+    use openssl::{
+        pkcs12::Pkcs12,
+        pkey::{PKey, Private},
+        x509::X509,
+    };
+
+    fn ca() -> (X509, PKey<Private>) {
+        CACert::new(DistinguishedName {
+            common_name: Some("Vanish Test CA".to_string()),
+            organization: "Vanish".to_string(),
+            country: None,
+            state: None,
+        })
+        .unwrap()
+        .generate_certificate()
+        .unwrap()
+    }
+
+    fn pkcs12(cert: &X509, key: &PKey<Private>, password: &str) -> Vec<u8> {
+        Pkcs12::builder()
+            .name("Vanish Test CA")
+            .pkey(key)
+            .cert(cert)
+            .build2(password)
+            .unwrap()
+            .to_der()
+            .unwrap()
+    }
+
+    #[test]
+    fn reads_certificates_and_keys_from_pem_der_and_pkcs12() {
+        let (cert, key) = ca();
+        let cert_der: Vec<u8> = cert.to_der().unwrap();
+        let inputs: [(Vec<u8>, Vec<u8>, Encoding); 3] = [
+            (
+                cert.to_pem().unwrap(),
+                key.private_key_to_pem_pkcs8().unwrap(),
+                Encoding::Pem,
+            ),
+            (
+                cert_der.clone(),
+                key.private_key_to_der().unwrap(),
+                Encoding::Der,
+            ),
+            (
+                pkcs12(&cert, &key, ""),
+                pkcs12(&cert, &key, ""),
+                Encoding::Pkcs12,
+            ),
+        ];
+        for (cert_data, key_data, encoding) in inputs {
+            assert_eq!(Encoding::detect(&cert_data), encoding);
+            assert_eq!(Encoding::detect(&key_data), encoding);
+            let read_cert: X509 = read_certificate(&cert_data, "ca").unwrap();
+            assert_eq!(read_cert.to_der().unwrap(), cert_der);
+            let read_key: PKey<Private> = read_private_key(&key_data, "ca-key").unwrap();
+            assert!(read_key.public_eq(&key));
+        }
+        let parsed = read_pkcs12(&pkcs12(&cert, &key, ""), "ca.p12").unwrap();
+        assert_eq!(parsed.cert.unwrap().to_der().unwrap(), cert_der);
+        assert!(parsed.pkey.unwrap().public_eq(&key));
+    }
+
+    #[test]
+    fn refuses_pkcs12_with_the_wrong_password() {
+        let (cert, key) = ca();
+        let bundle: Vec<u8> = pkcs12(&cert, &key, "correct horse");
+        assert_eq!(Encoding::detect(&bundle), Encoding::Pkcs12);
+        assert!(parse_pkcs12(&bundle, "ca.p12", "wrong").is_err());
+        assert!(parse_pkcs12(&bundle, "ca.p12", "").is_err());
+        let parsed = parse_pkcs12(&bundle, "ca.p12", "correct horse").unwrap();
+        assert!(parsed.pkey.unwrap().public_eq(&key));
+    }
+
+    #[test]
+    fn only_bundles_and_pem_with_a_key_stand_in_for_a_key_file() {
+        let (cert, key) = ca();
+        let mut combined: Vec<u8> = cert.to_pem().unwrap();
+        combined.extend(key.private_key_to_pem_pkcs8().unwrap());
+        assert!(carries_private_key(&combined));
+        assert!(carries_private_key(&pkcs12(&cert, &key, "")));
+        assert!(!carries_private_key(&cert.to_pem().unwrap()));
+        assert!(!carries_private_key(&cert.to_der().unwrap()));
+    }
+
+    #[test]
+    fn encrypts_key_with_passphrase() {
+        let (_rsa, key) = generate_cert_key_pair().unwrap();
+        let pem: Vec<u8> = private_key_to_pem(&key, Some(b"correct horse")).unwrap();
+        assert!(
+            String::from_utf8_lossy(&pem).contains("BEGIN ENCRYPTED PRIVATE KEY"),
+            "Expected an encrypted PKCS#8 key"
+        );
+        assert!(PKey::private_key_from_pem_passphrase(&pem, b"wrong").is_err());
+        let decrypted: PKey<Private> =
+            PKey::private_key_from_pem_passphrase(&pem, b"correct horse").unwrap();
+        assert!(decrypted.public_eq(&key));
+    }
+}
+
+#[cfg(unix)]
+mod file {
+    use crate::x509::file::{write_file, Overwrite, CERT_FILE_MODE, KEY_FILE_MODE};
+    use std::{fs, os::unix::fs::PermissionsExt, path::PathBuf};
+
+    fn mode(path: &PathBuf) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn writes_keys_private_and_certs_readable() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o755)).unwrap();
+        let key: PathBuf = dir.path().join("key.pem");
+        let cert: PathBuf = dir.path().join("cert.pem");
+        write_file(
+            key.to_str().unwrap(),
+            b"key",
+            KEY_FILE_MODE,
+            Overwrite::Refuse,
+        )
+        .unwrap();
+        write_file(
+            cert.to_str().unwrap(),
+            b"cert",
+            CERT_FILE_MODE,
+            Overwrite::Refuse,
+        )
+        .unwrap();
+        assert_eq!(mode(&key), 0o600);
+        assert_eq!(mode(&cert), 0o644);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn refuses_group_or_world_writable_directory_without_sticky_bit() {
+        let dir = tempfile::tempdir().unwrap();
+        let key: PathBuf = dir.path().join("key.pem");
+        for refused in [0o777, 0o775, 0o757] {
+            fs::set_permissions(dir.path(), fs::Permissions::from_mode(refused)).unwrap();
+            assert!(write_file(
+                key.to_str().unwrap(),
+                b"key",
+                KEY_FILE_MODE,
+                Overwrite::Refuse
+            )
+            .is_err());
+            assert!(!key.exists());
+        }
+
+        // /tmp-like sticky directories keep other users away from our files
+        for allowed in [0o755, 0o1777] {
+            fs::set_permissions(dir.path(), fs::Permissions::from_mode(allowed)).unwrap();
+            write_file(
+                key.to_str().unwrap(),
+                b"key",
+                KEY_FILE_MODE,
+                Overwrite::Backup,
+            )
+            .unwrap();
+        }
+        assert_eq!(mode(&key), 0o600);
+    }
+
+    #[test]
+    fn refuses_to_overwrite_unless_backing_up() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o755)).unwrap();
+        let cert: PathBuf = dir.path().join("cert.pem");
+        let path: &str = cert.to_str().unwrap();
+        write_file(path, b"old", CERT_FILE_MODE, Overwrite::Refuse).unwrap();
+        assert!(write_file(path, b"new", CERT_FILE_MODE, Overwrite::Refuse).is_err());
+        assert_eq!(fs::read(&cert).unwrap(), b"old");
+
+        write_file(path, b"new", CERT_FILE_MODE, Overwrite::Backup).unwrap();
+        assert_eq!(fs::read(&cert).unwrap(), b"new");
+        let backups: Vec<PathBuf> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|entry: &PathBuf| entry.extension().is_some_and(|ext| ext == "bak"))
+            .collect();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read(&backups[0]).unwrap(), b"old");
+    }
+}
+
+mod leaf_cert {
+    use crate::{
+        utils::generate_cert_key_pair,
+        x509::{
+            ca_cert::CACert,
+            der::{certificate_extension, OID_SUBJECT_ALT_NAME},
+            distinguished_name::DistinguishedName,
+            leaf_cert::LeafCert,
+            Certificate,
+        },
+    };
+    use openssl::{
+        hash::MessageDigest,
+        nid::Nid,
+        stack::Stack,
+        x509::{
+            extension::SubjectAlternativeName, X509Extension, X509NameBuilder, X509Req,
+            X509ReqBuilder, X509,
+        },
+    };
+
+    fn request_with_san(san: &mut SubjectAlternativeName) -> X509Req {
+        let (_rsa, key) = generate_cert_key_pair().unwrap();
+        let mut name: X509NameBuilder = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, "api.test")
+            .unwrap();
+        let mut builder: X509ReqBuilder = X509ReqBuilder::new().unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.set_subject_name(&name.build()).unwrap();
+        let san: X509Extension = san.build(&builder.x509v3_context(None)).unwrap();
+        let mut extensions: Stack<X509Extension> = Stack::new().unwrap();
+        extensions.push(san).unwrap();
+        builder.add_extensions(&extensions).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        builder.build()
+    }
+
+    #[test]
+    fn signs_requested_names_into_a_rebuilt_non_critical_san() {
+        let distinguished_name: DistinguishedName = DistinguishedName {
+            common_name: None,
+            organization: "Vanish".to_string(),
+            country: None,
+            state: None,
+        };
+        let (ca_cert, ca_key) = CACert::new(distinguished_name.clone())
+            .unwrap()
+            .generate_certificate()
+            .unwrap();
+        let issue = |csr: &X509Req| {
+            LeafCert::new(distinguished_name.clone())
+                .unwrap()
+                .generate_certificate(&ca_cert, &ca_key, Some(csr))
+        };
+
+        let csr: X509Req = request_with_san(
+            SubjectAlternativeName::new()
+                .critical()
+                .dns("api.test")
+                .ip("127.0.0.1"),
+        );
+        let (certificate, _key) = issue(&csr).unwrap();
+        let (critical, _value) = certificate_extension(&certificate, OID_SUBJECT_ALT_NAME)
+            .unwrap()
+            .unwrap();
+        assert!(!critical);
+        let names: Vec<String> = certificate
+            .subject_alt_names()
+            .unwrap()
+            .iter()
+            .map(|name| match (name.dnsname(), name.ipaddress()) {
+                (Some(dns), _) => dns.to_string(),
+                (None, Some(ip)) => format!("{:?}", ip),
+                _ => "other".to_string(),
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec!["api.test".to_string(), "[127, 0, 0, 1]".to_string()]
+        );
+
+        for san in [
+            SubjectAlternativeName::new()
+                .dns("api.test")
+                .email("admin@api.test"),
+            SubjectAlternativeName::new()
+                .dns("api.test")
+                .uri("https://api.test/"),
+        ] {
+            assert!(issue(&request_with_san(san)).is_err());
+        }
+    }
+
+    #[test]
+    fn renewal_keeps_subject_names_and_key() {
+        let distinguished_name: DistinguishedName = DistinguishedName {
+            common_name: None,
+            organization: "Vanish".to_string(),
+            country: None,
+            state: None,
+        };
+        let (ca_cert, ca_key) = CACert::new(distinguished_name.clone())
+            .unwrap()
+            .generate_certificate()
+            .unwrap();
+        let (old, _key) = LeafCert::new(distinguished_name.clone())
+            .unwrap()
+            .with_domain("api.test")
+            .generate_certificate(&ca_cert, &ca_key, None)
+            .unwrap();
+        let renewed: X509 = LeafCert::new(distinguished_name)
+            .unwrap()
+            .renew_certificate(&old, old.public_key().unwrap().as_ref(), &ca_cert, &ca_key)
+            .unwrap();
+        assert_eq!(
+            renewed.subject_name().to_der().unwrap(),
+            old.subject_name().to_der().unwrap()
+        );
+        let names: Vec<String> = renewed
+            .subject_alt_names()
+            .unwrap()
+            .iter()
+            .filter_map(|name| name.dnsname().map(str::to_string))
+            .collect();
+        assert_eq!(names, vec!["api.test".to_string()]);
+        assert!(renewed
+            .public_key()
+            .unwrap()
+            .public_eq(&old.public_key().unwrap()));
+        assert_ne!(
+            renewed.serial_number().to_bn().unwrap(),
+            old.serial_number().to_bn().unwrap()
+        );
+        assert!(renewed.verify(&ca_key).unwrap());
+    }
+}
+
+mod crl {
+    use crate::x509::{
+        ca_cert::CACert,
+        crl::{build_crl, RevocationReason, RevokedCertificate},
+        distinguished_name::DistinguishedName,
+        Certificate,
+    };
+    use openssl::{asn1::Asn1Integer, bn::BigNum, x509::X509Crl};
+
+    #[test]
+    fn signed_crl_lists_revoked_serials() {
+        let (ca_cert, ca_key) = CACert::new(DistinguishedName {
+            common_name: None,
+            organization: "Vanish".to_string(),
+            country: None,
+            state: None,
+        })
+        .unwrap()
+        .generate_certificate()
+        .unwrap();
+        let revoked: Vec<RevokedCertificate> = vec![
+            RevokedCertificate {
+                serial: "8F00AA".to_string(),
+                revoked_at: 1_700_000_000,
+                reason: RevocationReason::KeyCompromise,
+            },
+            RevokedCertificate {
+                serial: "01".to_string(),
+                revoked_at: 1_700_000_000,
+                reason: RevocationReason::Unspecified,
+            },
+        ];
+        let crl: X509Crl =
+            build_crl(&ca_cert, &ca_key, &revoked, 1_700_000_000, 1_700_604_800, 3).unwrap();
+        assert!(crl.verify(&ca_key).unwrap());
+        assert_eq!(crl.get_revoked().unwrap().len(), 2);
+        let serial: Asn1Integer = BigNum::from_hex_str("8F00AA")
+            .unwrap()
+            .to_asn1_integer()
+            .unwrap();
+        assert!(!matches!(
+            crl.get_by_serial(&serial),
+            openssl::x509::CrlStatus::NotRevoked
+        ));
+        assert_eq!(
+            crl.next_update().unwrap().to_string(),
+            "Nov 21 22:13:20 2023 GMT"
+        );
+    }
+}
+
+mod ocsp {
+    use crate::x509::{
+        ca_cert::CACert,
+        crl::RevocationReason,
+        describe::serial_hex,
+        distinguished_name::DistinguishedName,
+        leaf_cert::LeafCert,
+        ocsp::{CertStatus, OcspRequest, OcspResponder},
+        Certificate,
+    };
+    use openssl::{
+        hash::MessageDigest,
+        ocsp::{
+            OcspBasicResponse, OcspCertId, OcspCertStatus, OcspFlag, OcspRequest as Request,
+            OcspResponse, OcspRevokedStatus,
+        },
+        stack::Stack,
+        x509::{store::X509StoreBuilder, X509},
+    };
+
+    #[test]
+    fn answers_with_signed_status_from_delegated_responder() {
+        let distinguished_name: DistinguishedName = DistinguishedName {
+            common_name: None,
+            organization: "Vanish".to_string(),
+            country: None,
+            state: None,
+        };
+        let (ca_cert, ca_key) = CACert::new(distinguished_name.clone())
+            .unwrap()
+            .generate_certificate()
+            .unwrap();
+        let (leaf, _key) = LeafCert::new(distinguished_name)
+            .unwrap()
+            .with_domain("revoked.test")
+            .generate_certificate(&ca_cert, &ca_key, None)
+            .unwrap();
+
+        let mut request: Request = Request::new().unwrap();
+        request
+            .add_id(OcspCertId::from_cert(MessageDigest::sha256(), &leaf, &ca_cert).unwrap())
+            .unwrap();
+        let request: OcspRequest = OcspRequest::parse(&request.to_der().unwrap()).unwrap();
+        assert_eq!(request.cert_ids.len(), 1);
+        assert!(request.cert_ids[0].issued_by(&ca_cert).unwrap());
+        assert_eq!(request.cert_ids[0].serial, serial_hex(&leaf).unwrap());
+
+        let responder: OcspResponder =
+            OcspResponder::generate_delegated(&ca_cert, &ca_key).unwrap();
+        let statuses: Vec<CertStatus> = vec![CertStatus::Revoked {
+            revoked_at: 1_700_000_000,
+            reason: RevocationReason::KeyCompromise,
+        }];
+        let response: Vec<u8> = responder
+            .respond(&request, &statuses, 1_700_000_000, 1_700_003_600)
+            .unwrap();
+
+        let basic: OcspBasicResponse = OcspResponse::from_der(&response).unwrap().basic().unwrap();
+        let mut store: X509StoreBuilder = X509StoreBuilder::new().unwrap();
+        store.add_cert(ca_cert.clone()).unwrap();
+        let certs: Stack<X509> = Stack::new().unwrap();
+        basic
+            .verify(&certs, &store.build(), OcspFlag::empty())
+            .unwrap();
+        let cert_id: OcspCertId =
+            OcspCertId::from_cert(MessageDigest::sha256(), &leaf, &ca_cert).unwrap();
+        let status = basic.find_status(&cert_id).unwrap();
+        assert_eq!(status.status, OcspCertStatus::REVOKED);
+        assert_eq!(status.reason, OcspRevokedStatus::KEY_COMPROMISE);
+    }
+}
+
+mod ca_cert {
+    use crate::utils::generate_cert_key_pair;
+    use crate::x509::{
+        ca_cert::{CACert, CASettings, KeyType},
+        crl::{build_crl, RevocationReason, RevokedCertificate},
+        der::is_ca_certificate,
+        describe::serial_hex,
+        distinguished_name::DistinguishedName,
+        leaf_cert::LeafCert,
+        Certificate,
+    };
+    use openssl::{
+        asn1::{Asn1Integer, Asn1Time},
+        bn::BigNum,
+        hash::MessageDigest,
+        nid::Nid,
+        pkey::{Id, PKey, Private},
+        ssl::SslFiletype,
+        stack::Stack,
+        x509::{
+            extension::BasicConstraints,
+            store::{X509Lookup, X509StoreBuilder},
+            verify::X509VerifyFlags,
+            X509Builder, X509Crl, X509Name, X509NameBuilder, X509StoreContext, X509VerifyResult,
+            X509,
+        },
+    };
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn distinguished_name(common_name: &str) -> DistinguishedName {
+        DistinguishedName {
+            common_name: Some(common_name.to_string()),
+            organization: "Vanish".to_string(),
+            country: None,
+            state: None,
+        }
+    }
+
+    #[test]
+    fn name_constraints_limit_what_the_ca_can_issue() {
+        let settings: CASettings = CASettings {
+            key_type: KeyType::EcdsaP256,
+            validity_days: 30,
+            name_constraints: vec!["test".to_string()],
+        };
+        let (ca_cert, ca_key) = CACert::with_settings(distinguished_name("Team CA"), &settings)
+            .unwrap()
+            .generate_certificate()
+            .unwrap();
+        assert_eq!(ca_cert.public_key().unwrap().id(), Id::EC);
+
+        let mut store: X509StoreBuilder = X509StoreBuilder::new().unwrap();
+        store.add_cert(ca_cert.clone()).unwrap();
+        let store = store.build();
+        let verifies = |domain: &str| -> bool {
+            let (leaf, _key) = LeafCert::new(distinguished_name(domain))
+                .unwrap()
+                .with_domain(domain)
+                .generate_certificate(&ca_cert, &ca_key, None)
+                .unwrap();
+            let chain: Stack<X509> = Stack::new().unwrap();
+            X509StoreContext::new()
+                .unwrap()
+                .init(&store, &leaf, &chain, |context| context.verify_cert())
+                .unwrap()
+        };
+        assert!(verifies("app.test"));
+        assert!(!verifies("app.example"));
+    }
+
+    #[test]
+    fn cross_signed_root_chains_up_to_the_previous_root() {
+        let (old_cert, old_key) = CACert::new(distinguished_name("Vanish CA"))
+            .unwrap()
+            .generate_certificate()
+            .unwrap();
+        let (new_cert, new_key) = CACert::new(distinguished_name("Vanish CA 2026-10-19"))
+            .unwrap()
+            .generate_certificate()
+            .unwrap();
+        let cross_signed: X509 = CACert::cross_sign(&new_cert, &old_cert, &old_key).unwrap();
+        assert!(cross_signed.not_after() <= old_cert.not_after());
+        let (leaf, _key) = LeafCert::new(distinguished_name("app.test"))
+            .unwrap()
+            .with_domain("app.test")
+            .generate_certificate(&new_cert, &new_key, None)
+            .unwrap();
+
+        let mut store: X509StoreBuilder = X509StoreBuilder::new().unwrap();
+        store.add_cert(old_cert).unwrap();
+        let store = store.build();
+        let mut chain: Stack<X509> = Stack::new().unwrap();
+        chain.push(cross_signed).unwrap();
+        let verified: bool = X509StoreContext::new()
+            .unwrap()
+            .init(&store, &leaf, &chain, |context| context.verify_cert())
+            .unwrap();
+        assert!(verified);
+    }
+
+    #[test]
+    fn revocations_during_a_rotation_are_signed_by_the_issuing_root() {
+        let (old_cert, old_key) = CACert::new(distinguished_name("Vanish CA"))
+            .unwrap()
+            .generate_certificate()
+            .unwrap();
+        let (old_leaf, _key) = LeafCert::new(distinguished_name("old.test"))
+            .unwrap()
+            .with_domain("old.test")
+            .generate_certificate(&old_cert, &old_key, None)
+            .unwrap();
+        let (new_cert, new_key) = CACert::new(distinguished_name("Vanish CA 2026-10-19"))
+            .unwrap()
+            .generate_certificate()
+            .unwrap();
+        let cross_signed: X509 = CACert::cross_sign(&new_cert, &old_cert, &old_key).unwrap();
+        let (new_leaf, _key) = LeafCert::new(distinguished_name("new.test"))
+            .unwrap()
+            .with_domain("new.test")
+            .generate_certificate(&new_cert, &new_key, None)
+            .unwrap();
+
+        // What `vanish crl` writes while the rotation is under way
+        let revoked: Vec<RevokedCertificate> = vec![RevokedCertificate {
+            serial: serial_hex(&old_leaf).unwrap(),
+            revoked_at: 1_700_000_000,
+            reason: RevocationReason::KeyCompromise,
+        }];
+        let previous_crl: X509Crl = build_crl(
+            &old_cert,
+            &old_key,
+            &revoked,
+            1_700_000_000,
+            4_102_444_799,
+            1,
+        )
+        .unwrap();
+        let crl: X509Crl =
+            build_crl(&new_cert, &new_key, &[], 1_700_000_000, 4_102_444_799, 1).unwrap();
+        let mut crls: NamedTempFile = NamedTempFile::new().unwrap();
+        crls.write_all(&previous_crl.to_pem().unwrap()).unwrap();
+        crls.write_all(&crl.to_pem().unwrap()).unwrap();
+
+        // A machine that only trusts the old root
+        let mut store: X509StoreBuilder = X509StoreBuilder::new().unwrap();
+        store.add_cert(old_cert).unwrap();
+        store
+            .add_lookup(X509Lookup::file())
+            .unwrap()
+            .load_crl_file(crls.path(), SslFiletype::PEM)
+            .unwrap();
+        store.set_flags(X509VerifyFlags::CRL_CHECK).unwrap();
+        let store = store.build();
+        let verify = |leaf: &X509| -> X509VerifyResult {
+            let mut chain: Stack<X509> = Stack::new().unwrap();
+            chain.push(cross_signed.clone()).unwrap();
+            X509StoreContext::new()
+                .unwrap()
+                .init(&store, leaf, &chain, |context| {
+                    context.verify_cert()?;
+                    Ok(context.error())
+                })
+                .unwrap()
+        };
+        assert_eq!(verify(&new_leaf), X509VerifyResult::OK);
+        assert_eq!(verify(&old_leaf).error_string(), "certificate revoked");
+    }
+
+    // A root as Vanish made them before they carried a key identifier
+    fn root_without_key_identifier(common_name: &str) -> (X509, PKey<Private>) {
+        let (_rsa, key) = generate_cert_key_pair().unwrap();
+        let mut name: X509NameBuilder = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, common_name)
+            .unwrap();
+        let name: X509Name = name.build();
+        let mut builder: X509Builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_serial_number(&Asn1Integer::from_bn(&BigNum::from_u32(1).unwrap()).unwrap())
+            .unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(365).unwrap())
+            .unwrap();
+        builder
+            .append_extension(BasicConstraints::new().ca().build().unwrap())
+            .unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        (builder.build(), key)
+    }
+
+    #[test]
+    fn cross_signed_root_names_both_key_identifiers() {
+        let (new_cert, _new_key) = CACert::new(distinguished_name("Vanish CA 2026-10-19"))
+            .unwrap()
+            .generate_certificate()
+            .unwrap();
+        let new_key_id: &[u8] = new_cert.subject_key_id().unwrap().as_slice();
+
+        let (old_cert, old_key) = CACert::new(distinguished_name("Vanish CA"))
+            .unwrap()
+            .generate_certificate()
+            .unwrap();
+        let cross_signed: X509 = CACert::cross_sign(&new_cert, &old_cert, &old_key).unwrap();
+        assert_eq!(
+            cross_signed.subject_key_id().unwrap().as_slice(),
+            new_key_id
+        );
+        assert_eq!(
+            cross_signed.authority_key_id().unwrap().as_slice(),
+            old_cert.subject_key_id().unwrap().as_slice()
+        );
+
+        // Roots from before key identifiers are named by issuer and serial
+        let (old_cert, old_key) = root_without_key_identifier("Vanish CA");
+        let cross_signed: X509 = CACert::cross_sign(&new_cert, &old_cert, &old_key).unwrap();
+        assert_eq!(
+            cross_signed.subject_key_id().unwrap().as_slice(),
+            new_key_id
+        );
+        assert!(cross_signed.authority_key_id().is_none());
+        assert_eq!(
+            cross_signed.authority_serial().unwrap().to_bn().unwrap(),
+            old_cert.serial_number().to_bn().unwrap()
+        );
+    }
+
+    #[test]
+    fn only_ca_certificates_are_recognised_as_cas() {
+        let (ca_cert, ca_key) = CACert::new(distinguished_name("Team CA"))
+            .unwrap()
+            .generate_certificate()
+            .unwrap();
+        let (leaf, _key) = LeafCert::new(distinguished_name("app.test"))
+            .unwrap()
+            .with_domain("app.test")
+            .generate_certificate(&ca_cert, &ca_key, None)
+            .unwrap();
+        assert!(is_ca_certificate(&ca_cert).unwrap());
+        assert!(!is_ca_certificate(&leaf).unwrap());
+    }
+}
+
+mod data_dir {
+    use crate::x509::resolve_data_dir;
+    use std::{env, ffi::OsString, path::PathBuf};
+
+    #[test]
+    fn caroot_overrides_the_platform_data_dir() {
+        assert_eq!(
+            resolve_data_dir(Some(OsString::from("/tmp/project-ca"))),
+            Some(PathBuf::from("/tmp/project-ca"))
+        );
+        assert_eq!(
+            resolve_data_dir(Some(OsString::from("ca"))),
+            Some(env::current_dir().unwrap().join("ca"))
+        );
+        assert_eq!(
+            resolve_data_dir(Some(OsString::new())),
+            resolve_data_dir(None)
+        );
+    }
+}